@@ -0,0 +1,666 @@
+use std::path::Path;
+
+use serde::{de::DeserializeOwned, Serialize};
+use specs::WorldExt;
+use winit::{
+    event::*,
+    event_loop::{ControlFlow, EventLoop},
+    window::WindowBuilder,
+};
+
+use crate::{
+    assets::AssetPaths,
+    components::{
+        background::Sky,
+        dynamic_mesh::DynamicMesh,
+        hierarchy::{GlobalTransform, Parent},
+        lighting::{Light, Lighting},
+        lod::Lod,
+        postprocessing::PostProcessing,
+        primitive::Primitive,
+        rendering::{Camera, Model, Renderer, Transform},
+        tonemapping::ToneMapping,
+    },
+    config::{EngineConfig, EngineError},
+    culling::CullingStats,
+    headless::OffscreenTarget,
+    import::ImportSettings,
+    material_manager::MaterialManager,
+    options::Options,
+    render_graph::{
+        nodes::{names, BloomNode, PostProcessingNode, SceneNode, ToneMappingNode},
+        RenderGraph, RenderNode,
+    },
+    render_target::{RenderTargets, HDR_FORMAT},
+    scene::{load_environment, Scene, SceneComponents},
+    systems::{
+        background::BackgroundSystem, camera::CameraSystem, dynamic_mesh::DynamicMeshSystem,
+        lighting::LightingSystem, lod::LodSystem, model_builder::ModelBuilderSystem,
+        postprocessing::PostProcessingSystem, rendering::RenderSystem, resizing::ResizingSystem,
+        transform::TransformSystem,
+    },
+};
+
+/// Names of the engine's systems, for use as dependencies of custom systems.
+pub mod stages {
+    /// Loads `Model` files into `Renderer`s.
+    pub const MODEL_BUILDER: &str = "model_builder";
+    /// Propagates `Transform`s into `GlobalTransform`s. Runs after every custom system.
+    pub const TRANSFORM: &str = "transform";
+    /// Uploads the changes to every `DynamicMesh` into its `Renderer`. Runs after every custom
+    /// system.
+    pub const DYNAMIC_MESH: &str = "dynamic_mesh";
+    /// Computes the view-projection of every `Renderer`. Runs after `TRANSFORM`.
+    pub const CAMERA: &str = "camera";
+    /// Gathers the `Light`s for the shaders. Runs after `TRANSFORM`.
+    pub const LIGHTING: &str = "lighting";
+    /// Prepares the first camera's `Background`. Runs after `TRANSFORM`.
+    pub const BACKGROUND: &str = "background";
+    /// Prepares the first camera's post-processing stack. Runs after `TRANSFORM`.
+    pub const POST_PROCESSING: &str = "post_processing";
+    /// Picks the level of detail of every `Renderer` with a `Lod`. Runs after `TRANSFORM` and
+    /// `MODEL_BUILDER`.
+    pub const LOD: &str = "lod";
+}
+
+type AddSystem = Box<dyn FnOnce(&mut specs::DispatcherBuilder<'static, 'static>)>;
+type WorldCallback = Box<dyn FnOnce(&mut specs::World)>;
+
+/// Entry point for applications built on grt.
+///
+/// ```no_run
+/// # async fn example() -> Result<(), grt::EngineError> {
+/// grt::App::builder()
+///     .with_scene("cube.ron")
+///     .with_startup(|world| {
+///         // Create entities, insert resources...
+///     })
+///     .run()
+///     .await
+/// # }
+/// ```
+pub struct App;
+
+impl App {
+    pub fn builder() -> AppBuilder {
+        AppBuilder::default()
+    }
+}
+
+#[derive(Default)]
+pub struct AppBuilder {
+    options: Options,
+    config: EngineConfig,
+    scene: Option<String>,
+    systems: Vec<(String, AddSystem)>,
+    thread_local_systems: Vec<AddSystem>,
+    setup: Vec<WorldCallback>,
+    startup: Vec<WorldCallback>,
+}
+
+impl AppBuilder {
+    /// Uses the window size, headless and save settings of the command-line options, and opens
+    /// the scene or model they name.
+    pub fn with_options(mut self, options: Options) -> Self {
+        self.scene = Some(options.path.clone());
+        self.options = options;
+        self
+    }
+
+    /// Adds a directory to search for models and textures.
+    pub fn with_asset_path(mut self, path: impl Into<String>) -> Self {
+        self.options.asset_paths.push(path.into());
+        self
+    }
+
+    /// Sets the samples per pixel of multisample anti-aliasing: 1, 2, 4 or 8.
+    pub fn with_sample_count(mut self, sample_count: u32) -> Self {
+        self.options.sample_count = sample_count;
+        self
+    }
+
+    /// Welds duplicate vertices and reorders the triangles of models as they're loaded.
+    pub fn with_mesh_optimization(mut self, enabled: bool) -> Self {
+        self.options.optimize_meshes = enabled;
+        self
+    }
+
+    /// Caches parsed OBJ files in `directory`, such as a directory under the platform's cache
+    /// directory. Without one, OBJ files are parsed every time they're loaded.
+    pub fn with_mesh_cache(mut self, directory: impl Into<String>) -> Self {
+        self.options.mesh_cache = Some(directory.into());
+        self
+    }
+
+    /// Parses OBJ files every time they're loaded, even if the options name a cache.
+    pub fn without_mesh_cache(mut self) -> Self {
+        self.options.mesh_cache = None;
+        self
+    }
+
+    /// Lights the scene with an equirectangular HDR image, replacing the scene's environment.
+    pub fn with_environment(mut self, path: impl Into<String>) -> Self {
+        self.options.environment = Some(path.into());
+        self
+    }
+
+    pub fn with_config(mut self, config: EngineConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Opens a scene (.ron) or model file at startup.
+    pub fn with_scene(mut self, path: impl Into<String>) -> Self {
+        self.scene = Some(path.into());
+        self
+    }
+
+    /// Adds a system that runs in parallel with the engine's systems, before transforms are
+    /// propagated. `dependencies` may name other custom systems or `stages::MODEL_BUILDER`.
+    pub fn with_system<S>(mut self, system: S, name: &str, dependencies: &[&str]) -> Self
+    where
+        S: for<'c> specs::System<'c> + Send + 'static,
+    {
+        let owned_name = name.to_string();
+        let dependencies = dependencies
+            .iter()
+            .map(|d| d.to_string())
+            .collect::<Vec<_>>();
+
+        self.systems.push((
+            name.to_string(),
+            Box::new(move |builder| {
+                let dependencies = dependencies.iter().map(String::as_str).collect::<Vec<_>>();
+                builder.add(system, &owned_name, &dependencies);
+            }),
+        ));
+        self
+    }
+
+    /// Adds a system that runs on the main thread after every parallel system, before rendering.
+    pub fn with_thread_local<S>(mut self, system: S) -> Self
+    where
+        S: for<'c> specs::RunNow<'c> + 'static,
+    {
+        self.thread_local_systems
+            .push(Box::new(move |builder| builder.add_thread_local(system)));
+        self
+    }
+
+    /// Adds a node to the render graph, after the engine's nodes. See `RenderGraph` for how
+    /// nodes are ordered.
+    pub fn with_render_node(mut self, name: impl Into<String>, node: impl RenderNode) -> Self {
+        let name = name.into();
+        self.setup.push(Box::new(move |world| {
+            world.write_resource::<RenderGraph>().add_node(name, node)
+        }));
+        self
+    }
+
+    /// Registers a component that no system uses, so that it can be inserted at startup.
+    ///
+    /// Scenes can't describe it; use `with_scene_component` for components they should.
+    pub fn with_component<C>(mut self) -> Self
+    where
+        C: specs::Component,
+        C::Storage: Default,
+    {
+        self.setup.push(Box::new(|world| world.register::<C>()));
+        self
+    }
+
+    /// Registers a component that scenes can describe as `Custom(name: <name>, value: ...)`, so
+    /// that it's loaded from scenes and saved into them. See `SceneComponents` for the components
+    /// that can be.
+    pub fn with_scene_component<C>(mut self, name: impl Into<String>) -> Self
+    where
+        C: specs::Component + Serialize + DeserializeOwned,
+        C::Storage: Default,
+    {
+        let name = name.into();
+        self.setup.push(Box::new(move |world| {
+            world.register::<C>();
+            world
+                .write_resource::<SceneComponents>()
+                .register::<C>(name);
+        }));
+        self
+    }
+
+    pub fn with_resource<R: specs::shred::Resource>(mut self, resource: R) -> Self {
+        self.setup
+            .push(Box::new(move |world| world.insert(resource)));
+        self
+    }
+
+    /// Runs once after the engine is set up and the scene is loaded, before the first frame.
+    pub fn with_startup(mut self, startup: impl FnOnce(&mut specs::World) + 'static) -> Self {
+        self.startup.push(Box::new(startup));
+        self
+    }
+
+    /// Opens the window, or renders the configured number of headless frames, and runs until
+    /// the window is closed.
+    // Input the application handles itself never reaches the window event match
+    #[allow(clippy::collapsible_match)]
+    pub async fn run(self) -> Result<(), EngineError> {
+        let AppBuilder {
+            options,
+            config,
+            scene,
+            systems,
+            thread_local_systems,
+            setup,
+            startup,
+        } = self;
+
+        let mut builder =
+            specs::DispatcherBuilder::new().with(ModelBuilderSystem, stages::MODEL_BUILDER, &[]);
+
+        let names = systems
+            .iter()
+            .map(|(name, _)| name.clone())
+            .collect::<Vec<_>>();
+        for (_, add) in systems {
+            add(&mut builder);
+        }
+
+        let names = names.iter().map(String::as_str).collect::<Vec<_>>();
+        builder.add(TransformSystem, stages::TRANSFORM, &names);
+        builder.add(DynamicMeshSystem, stages::DYNAMIC_MESH, &names);
+        builder.add(CameraSystem, stages::CAMERA, &[stages::TRANSFORM]);
+        builder.add(LightingSystem, stages::LIGHTING, &[stages::TRANSFORM]);
+        builder.add(BackgroundSystem, stages::BACKGROUND, &[stages::TRANSFORM]);
+        builder.add(
+            PostProcessingSystem,
+            stages::POST_PROCESSING,
+            &[stages::TRANSFORM],
+        );
+        builder.add(
+            LodSystem,
+            stages::LOD,
+            &[stages::TRANSFORM, stages::MODEL_BUILDER],
+        );
+
+        for add in thread_local_systems {
+            add(&mut builder);
+        }
+
+        builder.add_thread_local(ResizingSystem);
+        builder.add_thread_local(RenderSystem);
+
+        let dispatcher = builder.build();
+
+        let prepare = |app: &mut Application| {
+            for setup in setup {
+                setup(&mut app.world);
+            }
+
+            if let Some(scene) = &scene {
+                app.load(scene)?;
+            }
+
+            if let Some(environment) = &options.environment {
+                match load_environment(&app.world, environment) {
+                    Ok(environment) => app.world.insert(environment),
+                    Err(error) => {
+                        log::error!("Couldn't load environment {:?}: {}", environment, error)
+                    }
+                }
+            }
+
+            for startup in startup {
+                startup(&mut app.world);
+            }
+
+            Ok(())
+        };
+
+        if let Some(frames) = options.headless {
+            let mut app = Application::headless(dispatcher, &options, &config).await?;
+
+            prepare(&mut app)?;
+
+            for _ in 0..frames {
+                app.update();
+            }
+
+            app.save_frame(&options.output)
+                .map_err(|error| EngineError::SaveFrame {
+                    path: options.output.clone(),
+                    error,
+                })?;
+            log::info!("Saved frame {} to {:?}", frames, options.output);
+
+            return Ok(());
+        }
+
+        let event_loop = EventLoop::new();
+        let window = create_window(&event_loop, &options);
+        let mut app = Application::new(&window, dispatcher, &options, &config).await?;
+
+        prepare(&mut app)?;
+
+        event_loop.run(move |event, _, control_flow| match event {
+            Event::RedrawRequested(window_id) if window_id == window.id() => {
+                app.update();
+            }
+
+            Event::WindowEvent {
+                ref event,
+                window_id,
+            } if window_id == window.id() => {
+                if !app.input(event) {
+                    match event {
+                        WindowEvent::CloseRequested
+                        | WindowEvent::KeyboardInput {
+                            input:
+                                KeyboardInput {
+                                    state: ElementState::Pressed,
+                                    virtual_keycode: Some(VirtualKeyCode::Escape),
+                                    ..
+                                },
+                            ..
+                        } => *control_flow = ControlFlow::Exit,
+
+                        WindowEvent::Resized(physical_size) => {
+                            app.resize(*physical_size);
+                        }
+
+                        WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
+                            app.resize(**new_inner_size);
+                        }
+
+                        _ => {}
+                    }
+                }
+            }
+
+            Event::MainEventsCleared => {
+                window.request_redraw();
+            }
+
+            _ => {}
+        });
+    }
+}
+
+fn create_window(window_target: &EventLoop<()>, options: &Options) -> winit::window::Window {
+    let window = WindowBuilder::new()
+        .with_title("grt")
+        .with_inner_size(winit::dpi::PhysicalSize::new(options.width, options.height))
+        .build(window_target)
+        .unwrap();
+
+    #[cfg(target_arch = "wasm32")]
+    {
+        use winit::platform::web::WindowExtWebSys;
+        web_sys::window()
+            .and_then(|win| win.document())
+            .and_then(|doc| {
+                let dst = doc.get_element_by_id("wasm-example")?;
+                let canvas = web_sys::Element::from(window.canvas());
+                dst.append_child(&canvas).ok()?;
+                Some(())
+            })
+            .expect("Couldn't append canvas to document body.");
+    }
+
+    window
+}
+
+pub(crate) struct Application {
+    world: specs::World,
+    dispatcher: specs::Dispatcher<'static, 'static>,
+    save_path: String,
+}
+
+impl Application {
+    async fn new(
+        window: &winit::window::Window,
+        dispatcher: specs::Dispatcher<'static, 'static>,
+        options: &Options,
+        engine_config: &EngineConfig,
+    ) -> Result<Self, EngineError> {
+        let size = window.inner_size();
+
+        let instance = create_instance(engine_config);
+        let surface =
+            unsafe { instance.create_surface(window) }.map_err(EngineError::CreateSurface)?;
+        let (adapter, device, queue) =
+            request_device(&instance, Some(&surface), engine_config).await?;
+
+        let surface_caps = surface.get_capabilities(&adapter);
+        let surface_format = match surface_caps.formats.iter().find(|f| f.describe().srgb) {
+            Some(format) => *format,
+            None => {
+                log::warn!(
+                    "Surface has no sRGB format, colours will look washed out. Using {:?}",
+                    surface_caps.formats[0]
+                );
+                surface_caps.formats[0]
+            }
+        };
+
+        let config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format: surface_format,
+            width: 1,
+            height: 1,
+            present_mode: engine_config.select_present_mode(&surface_caps.present_modes),
+            alpha_mode: surface_caps.alpha_modes[0],
+            view_formats: vec![],
+        };
+
+        surface.configure(&device, &config);
+
+        let mut app = Self::with_device(&adapter, device, queue, config, size, dispatcher, options);
+        app.world.insert(surface);
+        app.dispatcher.setup(&mut app.world);
+        Ok(app)
+    }
+
+    /// Creates an application that renders into an `OffscreenTarget` instead of a window.
+    async fn headless(
+        dispatcher: specs::Dispatcher<'static, 'static>,
+        options: &Options,
+        engine_config: &EngineConfig,
+    ) -> Result<Self, EngineError> {
+        let size = winit::dpi::PhysicalSize::new(options.width, options.height);
+
+        let instance = create_instance(engine_config);
+        let (adapter, device, queue) = request_device(&instance, None, engine_config).await?;
+
+        let config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            width: 1,
+            height: 1,
+            present_mode: wgpu::PresentMode::Fifo,
+            alpha_mode: wgpu::CompositeAlphaMode::Opaque,
+            view_formats: vec![],
+        };
+
+        let target = OffscreenTarget::new(&device, config.format, size.width, size.height);
+
+        let mut app = Self::with_device(&adapter, device, queue, config, size, dispatcher, options);
+        app.world.insert(target);
+        app.dispatcher.setup(&mut app.world);
+        Ok(app)
+    }
+
+    fn with_device(
+        adapter: &wgpu::Adapter,
+        device: wgpu::Device,
+        queue: wgpu::Queue,
+        config: wgpu::SurfaceConfiguration,
+        size: winit::dpi::PhysicalSize<u32>,
+        dispatcher: specs::Dispatcher<'static, 'static>,
+        options: &Options,
+    ) -> Self {
+        let sample_count =
+            RenderTargets::supported_sample_count(options.sample_count, adapter, &device);
+        let targets = RenderTargets::new(&device, sample_count, config.width, config.height);
+        let tone_mapping = ToneMapping::new(&device, config.format, &targets);
+        let post_processing = PostProcessing::new(&device, config.format);
+
+        let mut render_graph = RenderGraph::new();
+        render_graph.add_node(names::SCENE, SceneNode);
+        render_graph.add_node(names::BLOOM, BloomNode);
+        render_graph.add_node(names::TONE_MAPPING, ToneMappingNode);
+        render_graph.add_node(names::POST_PROCESSING, PostProcessingNode);
+
+        let material_manager = MaterialManager::new(&device, &queue, adapter);
+
+        let lighting = Lighting::new(&device, material_manager.get_light_bind_group_layout());
+        let sky = Sky::new(&device, HDR_FORMAT, sample_count);
+
+        let mut world = specs::World::new();
+
+        // Resources
+        world.insert(size);
+        world.insert(config);
+        world.insert(device);
+        world.insert(queue);
+        world.insert(material_manager);
+        world.insert(lighting);
+        world.insert(sky);
+        world.insert(targets);
+        world.insert(tone_mapping);
+        world.insert(post_processing);
+        world.insert(render_graph);
+        world.insert(CullingStats::default());
+        world.insert(SceneComponents::default());
+        world.insert(AssetPaths::new(&options.asset_paths));
+        world.insert(ImportSettings {
+            optimize_meshes: options.optimize_meshes,
+            cache_directory: options.mesh_cache.as_ref().map(Into::into),
+        });
+
+        // Components
+        world.register::<Renderer>();
+        world.register::<Model>();
+        world.register::<Primitive>();
+        world.register::<DynamicMesh>();
+        world.register::<Transform>();
+        world.register::<Camera>();
+        world.register::<Light>();
+        world.register::<Lod>();
+        world.register::<Parent>();
+        world.register::<GlobalTransform>();
+
+        Self {
+            world,
+            dispatcher,
+            save_path: options.save_path.clone(),
+        }
+    }
+
+    /// Loads a scene file, or a model file into a default scene.
+    fn load(&mut self, path: &str) -> Result<(), EngineError> {
+        let scene = if path.ends_with(".ron") {
+            // Files named by the scene may be relative to it
+            if let Some(directory) = Path::new(path).parent() {
+                self.world.write_resource::<AssetPaths>().add(directory);
+            }
+            Scene::load(path)
+        } else {
+            Ok(Scene::from_model(path))
+        };
+
+        scene
+            .and_then(|s| s.instantiate(&mut self.world))
+            .map(drop)
+            .map_err(|error| EngineError::LoadScene {
+                path: path.to_string(),
+                error: Box::new(error),
+            })
+    }
+
+    fn save_frame(&self, path: &str) -> image::ImageResult<()> {
+        self.world.read_resource::<OffscreenTarget>().save_png(
+            path,
+            &self.world.read_resource::<wgpu::Device>(),
+            &self.world.read_resource::<wgpu::Queue>(),
+        )
+    }
+
+    fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
+        if new_size.width > 0 && new_size.height > 0 {
+            let size = self
+                .world
+                .get_mut::<winit::dpi::PhysicalSize<u32>>()
+                .unwrap();
+            *size = new_size;
+        }
+    }
+
+    fn input(&mut self, event: &WindowEvent) -> bool {
+        match event {
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::F5),
+                        ..
+                    },
+                ..
+            } => {
+                match Scene::from_world(&self.world).save(&self.save_path) {
+                    Ok(()) => log::info!("Saved scene to {:?}", self.save_path),
+                    Err(error) => log::error!("Couldn't save scene: {}", error),
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn update(&mut self) {
+        self.dispatcher.dispatch(&self.world);
+        self.world.maintain();
+    }
+}
+
+fn create_instance(engine_config: &EngineConfig) -> wgpu::Instance {
+    wgpu::Instance::new(wgpu::InstanceDescriptor {
+        backends: engine_config.backends,
+        dx12_shader_compiler: Default::default(),
+    })
+}
+
+async fn request_device(
+    instance: &wgpu::Instance,
+    surface: Option<&wgpu::Surface>,
+    engine_config: &EngineConfig,
+) -> Result<(wgpu::Adapter, wgpu::Device, wgpu::Queue), EngineError> {
+    let adapter = instance
+        .request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: engine_config.power_preference,
+            compatible_surface: surface,
+            force_fallback_adapter: engine_config.force_fallback_adapter,
+        })
+        .await
+        .ok_or(EngineError::NoAdapter {
+            backends: engine_config.backends,
+            force_fallback_adapter: engine_config.force_fallback_adapter,
+        })?;
+
+    log::info!("Using adapter {:?}", adapter.get_info());
+    engine_config.check_adapter(&adapter)?;
+
+    let (device, queue) = adapter
+        .request_device(
+            &wgpu::DeviceDescriptor {
+                features: engine_config.features
+                    | (engine_config.optional_features & adapter.features()),
+                limits: engine_config.limits.clone(),
+                label: None,
+            },
+            None, // Trace path
+        )
+        .await
+        .map_err(EngineError::RequestDevice)?;
+
+    Ok((adapter, device, queue))
+}
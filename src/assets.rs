@@ -0,0 +1,59 @@
+use std::path::{Path, PathBuf};
+
+/// Where models, materials and textures are looked up.
+///
+/// A relative path is tried against the directory of the file that references it, then against
+/// each search path in order, and finally against the working directory.
+#[derive(Debug, Clone, Default)]
+pub struct AssetPaths {
+    search_paths: Vec<PathBuf>,
+}
+
+impl AssetPaths {
+    pub fn new(search_paths: impl IntoIterator<Item = impl Into<PathBuf>>) -> Self {
+        Self {
+            search_paths: search_paths.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    /// Adds a directory to search after the existing ones.
+    pub fn add(&mut self, path: impl Into<PathBuf>) {
+        let path = path.into();
+        if !self.search_paths.contains(&path) {
+            self.search_paths.push(path);
+        }
+    }
+
+    pub fn search_paths(&self) -> &[PathBuf] {
+        &self.search_paths
+    }
+
+    /// Finds the file `path` refers to. `referrer` is the file it was named in, if any.
+    ///
+    /// If the file doesn't exist anywhere, the first candidate is returned so that the error
+    /// reported when opening it names a sensible location.
+    pub fn resolve(&self, path: &str, referrer: Option<&Path>) -> PathBuf {
+        let path = normalise(path);
+        if path.is_absolute() {
+            return path;
+        }
+
+        let base = referrer.and_then(Path::parent);
+        let candidates = base
+            .into_iter()
+            .chain(self.search_paths.iter().map(PathBuf::as_path))
+            .map(|directory| directory.join(&path))
+            .chain(std::iter::once(path.clone()))
+            .collect::<Vec<_>>();
+
+        match candidates.iter().find(|candidate| candidate.exists()) {
+            Some(found) => found.clone(),
+            None => candidates[0].clone(),
+        }
+    }
+}
+
+/// Turns the backslash separators written by Windows exporters into ones every platform accepts.
+pub fn normalise(path: &str) -> PathBuf {
+    PathBuf::from(path.replace('\\', "/"))
+}
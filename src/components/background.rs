@@ -0,0 +1,250 @@
+use serde::{Deserialize, Serialize};
+use wgpu::util::DeviceExt;
+
+use crate::{
+    environment::{Environment, Skybox},
+    material_manager::DEPTH_FORMAT,
+};
+
+/// What a `Camera` shows where no geometry covers the screen.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum Background {
+    /// A solid linear RGB colour.
+    Color([f32; 3]),
+    /// Blends between linear RGB colours, from `bottom` looking straight down to `top` looking
+    /// straight up.
+    Gradient { top: [f32; 3], bottom: [f32; 3] },
+    /// An equirectangular image, usually HDR.
+    Skybox(String),
+    /// One square image per cube face, in the order +X, -X, +Y, -Y, +Z, -Z.
+    Cubemap([String; 6]),
+    /// The cubemap of the world's `Environment`, or black if there is none.
+    Environment,
+}
+
+impl Default for Background {
+    fn default() -> Self {
+        Background::Color([0.1, 0.2, 0.3])
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct SkyUniform {
+    pub inverse_view_proj: [[f32; 4]; 4],
+    pub top: [f32; 4],
+    pub bottom: [f32; 4],
+    /// 0 for a gradient, 1 for a cubemap.
+    pub mode: u32,
+    pub _padding: [u32; 3],
+}
+
+/// Which cubemap the bind group samples.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BoundCubemap {
+    Fallback,
+    Skybox,
+    Environment(usize),
+}
+
+/// The background of the active camera as the shaders see it. Updated by the background system
+/// each frame, and drawn by the render system after opaque geometry.
+pub struct Sky {
+    pub buffer: wgpu::Buffer,
+    pub bind: wgpu::BindGroup,
+    pub pipeline: wgpu::RenderPipeline,
+    background: Background,
+    skybox: Option<Skybox>,
+    layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    /// Bound for backgrounds without a cubemap, or whose images couldn't be loaded.
+    fallback: Skybox,
+    /// What the bind group samples, or `None` if it has to be rebuilt.
+    bound: Option<BoundCubemap>,
+}
+
+impl Sky {
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat, sample_count: u32) -> Self {
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("sky_bind_group_layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::Cube,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Sky Buffer"),
+            contents: bytemuck::cast_slice(&[SkyUniform {
+                inverse_view_proj: cgmath::Matrix4::from_scale(1.0).into(),
+                top: [0.0; 4],
+                bottom: [0.0; 4],
+                mode: 0,
+                _padding: [0; 3],
+            }]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Sky Sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let fallback = Skybox::black(device);
+        let bind = create_bind_group(&buffer, &fallback.view, &sampler, device, &layout);
+        let pipeline = create_pipeline(&layout, format, sample_count, device);
+
+        Self {
+            buffer,
+            bind,
+            pipeline,
+            background: Background::default(),
+            skybox: None,
+            layout,
+            sampler,
+            fallback,
+            bound: Some(BoundCubemap::Fallback),
+        }
+    }
+
+    pub fn background(&self) -> &Background {
+        &self.background
+    }
+
+    /// Replaces the background, along with the cubemap loaded for it, if any.
+    pub fn set_background(&mut self, background: Background, skybox: Option<Skybox>) {
+        self.background = background;
+        self.skybox = skybox;
+        self.bound = None;
+    }
+
+    /// What the render pass clears to. Backgrounds other than solid colours are drawn over it.
+    pub fn clear_color(&self) -> wgpu::Color {
+        match self.background {
+            Background::Color([r, g, b]) => wgpu::Color {
+                r: r as f64,
+                g: g as f64,
+                b: b as f64,
+                a: 1.0,
+            },
+            _ => wgpu::Color::BLACK,
+        }
+    }
+
+    /// Whether the sky has to be drawn, rather than just cleared to.
+    pub fn is_drawn(&self) -> bool {
+        !matches!(self.background, Background::Color(_))
+    }
+
+    /// Rebuilds the bind group if the cubemap of the background isn't the one it was built with.
+    pub fn bind_cubemap(&mut self, environment: Option<&Environment>, device: &wgpu::Device) {
+        let (bound, view) = match (&self.background, &self.skybox, environment) {
+            (Background::Environment, _, Some(environment)) => (
+                BoundCubemap::Environment(environment.id),
+                &environment.cubemap_view,
+            ),
+            (_, Some(skybox), _) => (BoundCubemap::Skybox, &skybox.view),
+            _ => (BoundCubemap::Fallback, &self.fallback.view),
+        };
+
+        if Some(bound) != self.bound {
+            self.bind = create_bind_group(&self.buffer, view, &self.sampler, device, &self.layout);
+            self.bound = Some(bound);
+        }
+    }
+}
+
+fn create_bind_group(
+    buffer: &wgpu::Buffer,
+    view: &wgpu::TextureView,
+    sampler: &wgpu::Sampler,
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::TextureView(view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::Sampler(sampler),
+            },
+        ],
+        label: Some("sky_bind_group"),
+    })
+}
+
+fn create_pipeline(
+    layout: &wgpu::BindGroupLayout,
+    format: wgpu::TextureFormat,
+    sample_count: u32,
+    device: &wgpu::Device,
+) -> wgpu::RenderPipeline {
+    let shader = device.create_shader_module(wgpu::include_wgsl!("../shaders/sky.wgsl"));
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("Sky Layout"),
+        bind_group_layouts: &[layout],
+        push_constant_ranges: &[],
+    });
+
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Sky Pipeline"),
+        layout: Some(&pipeline_layout),
+        vertex: wgpu::VertexState {
+            module: &shader,
+            entry_point: "vs_main",
+            buffers: &[],
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: &shader,
+            entry_point: "fs_main",
+            targets: &[Some(format.into())],
+        }),
+        primitive: wgpu::PrimitiveState::default(),
+        // The sky lies on the far plane, which the depth buffer is cleared to
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: DEPTH_FORMAT,
+            depth_write_enabled: false,
+            depth_compare: wgpu::CompareFunction::LessEqual,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }),
+        multisample: wgpu::MultisampleState {
+            count: sample_count,
+            ..Default::default()
+        },
+        multiview: None,
+    })
+}
@@ -0,0 +1,156 @@
+use std::ops::Range;
+
+use specs::{Component, VecStorage};
+
+use crate::{components::rendering::Vertex, material::PbrMaterial};
+
+/// A mesh whose vertices and indices systems can change while it's drawn, for simulations and
+/// procedural geometry. It's drawn by the entity's `Renderer`, which it's uploaded into after the
+/// custom systems of each frame it changes in, and takes the place of a `Model` or `Primitive` on
+/// the same entity.
+///
+/// Only the ranges that were edited are uploaded again, so edits through `vertices_mut` and
+/// `indices_mut` should cover as little as they can. The buffers grow when the mesh outgrows
+/// them.
+#[derive(Component, Default)]
+#[storage(VecStorage)]
+pub struct DynamicMesh {
+    vertices: Vec<Vertex>,
+    indices: Vec<u32>,
+    material: PbrMaterial,
+    /// What changed since the last upload.
+    pub(crate) changes: Changes,
+}
+
+#[derive(Default)]
+pub(crate) struct Changes {
+    pub vertices: Option<Range<usize>>,
+    pub indices: Option<Range<usize>>,
+    pub material: bool,
+}
+
+impl DynamicMesh {
+    pub fn new(vertices: Vec<Vertex>, indices: Vec<u32>) -> Self {
+        Self {
+            changes: Changes {
+                vertices: Some(0..vertices.len()),
+                indices: Some(0..indices.len()),
+                material: true,
+            },
+            vertices,
+            indices,
+            material: PbrMaterial::default(),
+        }
+    }
+
+    pub fn with_material(mut self, material: PbrMaterial) -> Self {
+        self.set_material(material);
+        self
+    }
+
+    pub fn vertices(&self) -> &[Vertex] {
+        &self.vertices
+    }
+
+    pub fn indices(&self) -> &[u32] {
+        &self.indices
+    }
+
+    pub fn material(&self) -> &PbrMaterial {
+        &self.material
+    }
+
+    /// The vertices in `range`, to edit in place.
+    pub fn vertices_mut(&mut self, range: Range<usize>) -> &mut [Vertex] {
+        self.changes.vertices = Some(union(self.changes.vertices.take(), range.clone()));
+        &mut self.vertices[range]
+    }
+
+    /// The indices in `range`, to edit in place. They're checked against the vertices before
+    /// they're uploaded.
+    pub fn indices_mut(&mut self, range: Range<usize>) -> &mut [u32] {
+        self.changes.indices = Some(union(self.changes.indices.take(), range.clone()));
+        &mut self.indices[range]
+    }
+
+    /// Replaces every vertex, changing how many there are.
+    pub fn set_vertices(&mut self, vertices: Vec<Vertex>) {
+        self.changes.vertices = Some(0..vertices.len());
+        self.vertices = vertices;
+    }
+
+    /// Replaces every index, changing how many triangles are drawn.
+    pub fn set_indices(&mut self, indices: Vec<u32>) {
+        self.changes.indices = Some(0..indices.len());
+        self.indices = indices;
+    }
+
+    pub fn set_material(&mut self, material: PbrMaterial) {
+        self.material = material;
+        self.changes.material = true;
+    }
+}
+
+fn union(range: Option<Range<usize>>, other: Range<usize>) -> Range<usize> {
+    match range {
+        Some(range) => range.start.min(other.start)..range.end.max(other.end),
+        None => other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mesh() -> DynamicMesh {
+        let vertices = vec![
+            Vertex {
+                position: [0.0; 3],
+                tex_coords: [0.0; 2],
+                normal: [0.0, 0.0, 1.0],
+            };
+            10
+        ];
+        let mut mesh = DynamicMesh::new(vertices, (0..9).collect());
+        mesh.changes = Changes::default();
+        mesh
+    }
+
+    #[test]
+    fn new_meshes_upload_everything() {
+        let mesh = DynamicMesh::new(mesh().vertices, vec![0, 1, 2]);
+        assert_eq!(mesh.changes.vertices, Some(0..10));
+        assert_eq!(mesh.changes.indices, Some(0..3));
+        assert!(mesh.changes.material);
+    }
+
+    #[test]
+    fn overlapping_edits_merge() {
+        let mut mesh = mesh();
+        mesh.vertices_mut(2..5);
+        mesh.vertices_mut(4..7);
+        mesh.vertices_mut(3..4);
+        assert_eq!(mesh.changes.vertices, Some(2..7));
+        assert_eq!(mesh.changes.indices, None);
+    }
+
+    #[test]
+    fn disjoint_edits_cover_the_gap_between_them() {
+        let mut mesh = mesh();
+        mesh.indices_mut(6..9);
+        mesh.indices_mut(0..3);
+        assert_eq!(mesh.changes.indices, Some(0..9));
+        assert_eq!(mesh.changes.vertices, None);
+    }
+
+    #[test]
+    fn replacing_covers_every_value() {
+        let mut mesh = mesh();
+        mesh.vertices_mut(8..10);
+        mesh.set_vertices(mesh.vertices[..4].to_vec());
+        assert_eq!(mesh.changes.vertices, Some(0..4));
+        mesh.set_indices(vec![0, 1, 2, 2, 3, 0]);
+        assert_eq!(mesh.changes.indices, Some(0..6));
+        assert!(!mesh.changes.material);
+    }
+}
@@ -0,0 +1,34 @@
+use specs::{Component, VecStorage};
+
+/// Attaches an entity to another entity, making its `Transform` relative to the parent.
+///
+/// When the parent entity is deleted, its children are deleted along with it.
+#[derive(Component, Debug, Clone, Copy)]
+#[storage(VecStorage)]
+pub struct Parent {
+    pub entity: specs::Entity,
+}
+
+/// The world-space matrix of an entity, computed by the `TransformSystem` from its `Transform`
+/// and the transforms of its ancestors.
+#[derive(Component, Debug, Clone, Copy)]
+#[storage(VecStorage)]
+pub struct GlobalTransform {
+    pub matrix: cgmath::Matrix4<f32>,
+}
+
+impl GlobalTransform {
+    pub fn position(&self) -> cgmath::Point3<f32> {
+        use cgmath::EuclideanSpace;
+        cgmath::Point3::from_vec(self.matrix.w.truncate())
+    }
+}
+
+impl Default for GlobalTransform {
+    fn default() -> Self {
+        use cgmath::SquareMatrix;
+        Self {
+            matrix: cgmath::Matrix4::identity(),
+        }
+    }
+}
@@ -0,0 +1,150 @@
+use serde::{Deserialize, Serialize};
+use specs::{Component, VecStorage};
+use wgpu::util::DeviceExt;
+
+use crate::environment::Environment;
+
+/// The most lights that are shaded at once. Further lights are ignored.
+pub const MAX_LIGHTS: usize = 8;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LightKind {
+    /// Shines along the entity's -Z axis, like the sun.
+    #[default]
+    Directional,
+    /// Shines in every direction from the entity's position, falling off with distance.
+    Point,
+}
+
+/// A light source, placed by the entity's transform.
+#[derive(Component, Debug, Clone)]
+#[storage(VecStorage)]
+pub struct Light {
+    pub kind: LightKind,
+    /// Linear RGB colour.
+    pub color: [f32; 3],
+    /// Multiplies the colour. For point lights this is the radiance at a distance of one unit.
+    pub intensity: f32,
+}
+
+impl Default for Light {
+    fn default() -> Self {
+        Self {
+            kind: LightKind::Directional,
+            color: [1.0, 1.0, 1.0],
+            intensity: 3.0,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct LightUniform {
+    /// The position of a point light, with w = 1, or the direction towards a directional light,
+    /// with w = 0.
+    pub position: [f32; 4],
+    /// Colour multiplied by intensity.
+    pub color: [f32; 4],
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct LightsUniform {
+    pub ambient: [f32; 3],
+    pub count: u32,
+    pub environment_intensity: f32,
+    pub max_reflection_lod: f32,
+    pub _padding: [f32; 2],
+    pub lights: [LightUniform; MAX_LIGHTS],
+}
+
+/// The lights of the world as the shaders see them. Rewritten by the lighting system each frame.
+pub struct Lighting {
+    /// Light that reaches every surface from all directions, in linear RGB. Added to the light
+    /// of the `Environment`, if there is one.
+    pub ambient: [f32; 3],
+    pub buffer: wgpu::Buffer,
+    pub bind: wgpu::BindGroup,
+    /// Bound while the world has no `Environment` resource.
+    fallback: Environment,
+    bound_environment: usize,
+}
+
+impl Lighting {
+    pub fn new(device: &wgpu::Device, layout: &wgpu::BindGroupLayout) -> Self {
+        let ambient = [0.03, 0.03, 0.03];
+        let uniform = LightsUniform {
+            ambient,
+            count: 0,
+            environment_intensity: 0.0,
+            max_reflection_lod: 0.0,
+            _padding: [0.0; 2],
+            lights: [LightUniform::default(); MAX_LIGHTS],
+        };
+
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Light Buffer"),
+            contents: bytemuck::cast_slice(&[uniform]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let fallback = Environment::black(device);
+        let bind = create_bind_group(&buffer, &fallback, device, layout);
+
+        Self {
+            ambient,
+            bind,
+            bound_environment: fallback.id,
+            fallback,
+            buffer,
+        }
+    }
+
+    /// Rebuilds the bind group if the environment isn't the one it was built with.
+    pub fn bind_environment(
+        &mut self,
+        environment: Option<&Environment>,
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+    ) {
+        let environment = environment.unwrap_or(&self.fallback);
+        if environment.id != self.bound_environment {
+            self.bind = create_bind_group(&self.buffer, environment, device, layout);
+            self.bound_environment = environment.id;
+        }
+    }
+}
+
+fn create_bind_group(
+    buffer: &wgpu::Buffer,
+    environment: &Environment,
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::TextureView(&environment.irradiance_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::TextureView(&environment.prefiltered_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: wgpu::BindingResource::TextureView(&environment.brdf_lut_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 4,
+                resource: wgpu::BindingResource::Sampler(&environment.sampler),
+            },
+        ],
+        label: Some("light_bind_group"),
+    })
+}
@@ -0,0 +1,95 @@
+use serde::{Deserialize, Serialize};
+use specs::{Component, VecStorage};
+
+/// Where the meshes of a level of detail come from.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum LodModel {
+    /// A model file of its own, with its own materials.
+    File(String),
+    /// The entity's `Model` or `Primitive`, simplified to this fraction of its triangles when it's loaded.
+    Simplified(f32),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct LodLevel {
+    pub model: LodModel,
+    /// The level is drawn once the entity's bounds are smaller than this fraction of the
+    /// screen's height.
+    pub screen_size: f32,
+}
+
+/// Coarser versions of an entity's `Model` or `Primitive`, drawn instead of it as the entity gets smaller on
+/// the first camera's screen.
+#[derive(Component, Debug, Clone, Default)]
+#[storage(VecStorage)]
+pub struct Lod {
+    /// From the most detailed, with decreasing screen sizes.
+    pub levels: Vec<LodLevel>,
+    /// How far past a screen size, as a fraction of it, the entity has to get before the level
+    /// changes, so that an entity lingering around it doesn't flicker between levels.
+    pub hysteresis: f32,
+}
+
+impl Lod {
+    /// The level to draw at `screen_size`, where 0 is the `Model` and 1 onwards are `levels`,
+    /// when `current` was drawn last.
+    pub fn select(&self, screen_size: f32, current: usize) -> usize {
+        let mut level = current.min(self.levels.len());
+        while level < self.levels.len()
+            && screen_size < self.levels[level].screen_size * (1.0 - self.hysteresis)
+        {
+            level += 1;
+        }
+        while level > 0
+            && screen_size > self.levels[level - 1].screen_size * (1.0 + self.hysteresis)
+        {
+            level -= 1;
+        }
+        level
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lod() -> Lod {
+        let level = |screen_size| LodLevel {
+            model: LodModel::Simplified(0.5),
+            screen_size,
+        };
+        Lod {
+            levels: vec![level(0.5), level(0.25)],
+            hysteresis: 0.1,
+        }
+    }
+
+    #[test]
+    fn selects_the_level_of_the_screen_size() {
+        let lod = lod();
+        assert_eq!(lod.select(1.0, 0), 0);
+        assert_eq!(lod.select(0.4, 0), 1);
+        assert_eq!(lod.select(0.1, 0), 2);
+        assert_eq!(lod.select(0.1, 2), 2);
+        assert_eq!(lod.select(1.0, 2), 0);
+    }
+
+    #[test]
+    fn levels_change_only_past_the_hysteresis() {
+        let lod = lod();
+        // Within 10% of the 0.5 threshold, the current level stays
+        assert_eq!(lod.select(0.47, 0), 0);
+        assert_eq!(lod.select(0.53, 1), 1);
+        assert_eq!(lod.select(0.44, 0), 1);
+        assert_eq!(lod.select(0.56, 1), 0);
+        assert_eq!(lod.select(0.26, 2), 2);
+        assert_eq!(lod.select(0.3, 2), 1);
+    }
+
+    #[test]
+    fn levels_past_the_last_are_clamped() {
+        assert_eq!(lod().select(1.0, 10), 0);
+        assert_eq!(Lod::default().select(0.0, 3), 0);
+    }
+}
@@ -1 +1,9 @@
+pub mod background;
+pub mod dynamic_mesh;
+pub mod hierarchy;
+pub mod lighting;
+pub mod lod;
+pub mod postprocessing;
+pub mod primitive;
 pub mod rendering;
+pub mod tonemapping;
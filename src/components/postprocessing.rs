@@ -0,0 +1,508 @@
+use serde::{Deserialize, Serialize};
+use wgpu::util::DeviceExt;
+
+use crate::{material_manager::Texture, render_target::HDR_FORMAT};
+
+/// A full-screen effect applied to what a `Camera` sees.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum Effect {
+    /// Spreads the light brighter than `threshold` over its surroundings. Always applied before
+    /// tone mapping, wherever it is in the stack.
+    Bloom { threshold: f32, intensity: f32 },
+    /// Fast approximate anti-aliasing, which smooths the edges that MSAA misses, such as those
+    /// of cut out materials.
+    Fxaa,
+    /// Darkens the corners by up to `intensity`, over `smoothness` of the distance from them to
+    /// the center.
+    Vignette { intensity: f32, smoothness: f32 },
+    /// Remaps colours with a lookup table image: a strip of N slices of N by N pixels, for
+    /// instance 256 by 16.
+    ColorGrading { lut: String },
+    /// Adds noise that changes every frame.
+    FilmGrain { intensity: f32 },
+}
+
+/// An effect in a `Camera`'s post-processing stack, which is applied in order after tone
+/// mapping.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PostEffect {
+    pub effect: Effect,
+    #[serde(default = "enabled")]
+    pub enabled: bool,
+}
+
+fn enabled() -> bool {
+    true
+}
+
+impl From<Effect> for PostEffect {
+    fn from(effect: Effect) -> Self {
+        Self {
+            effect,
+            enabled: true,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct EffectUniform {
+    pub intensity: f32,
+    pub threshold: f32,
+    pub smoothness: f32,
+    pub seed: f32,
+}
+
+impl EffectUniform {
+    fn new(effect: &Effect, seed: f32) -> Self {
+        let uniform = Self {
+            seed,
+            ..Default::default()
+        };
+        match *effect {
+            Effect::Bloom {
+                threshold,
+                intensity,
+            } => Self {
+                threshold,
+                intensity,
+                ..uniform
+            },
+            Effect::Vignette {
+                intensity,
+                smoothness,
+            } => Self {
+                intensity,
+                smoothness,
+                ..uniform
+            },
+            Effect::FilmGrain { intensity } => Self {
+                intensity,
+                ..uniform
+            },
+            Effect::Fxaa | Effect::ColorGrading { .. } => uniform,
+        }
+    }
+}
+
+/// The most times bloom halves the image.
+const BLOOM_LEVELS: u32 = 6;
+
+struct EffectPass {
+    effect: Effect,
+    buffer: wgpu::Buffer,
+    bind: wgpu::BindGroup,
+}
+
+struct Pipelines {
+    bloom_prefilter: wgpu::RenderPipeline,
+    bloom_downsample: wgpu::RenderPipeline,
+    bloom_upsample: wgpu::RenderPipeline,
+    fxaa: wgpu::RenderPipeline,
+    vignette: wgpu::RenderPipeline,
+    color_grading: wgpu::RenderPipeline,
+    film_grain: wgpu::RenderPipeline,
+}
+
+/// The GPU side of the first camera's post-processing stack, kept in sync by the
+/// post-processing system and drawn by the bloom and post-processing nodes of the render graph.
+pub struct PostProcessing {
+    effects: Vec<PostEffect>,
+    passes: Vec<EffectPass>,
+    pipelines: Pipelines,
+    source_layout: wgpu::BindGroupLayout,
+    effect_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    /// Bound in place of a lookup table by the effects without one.
+    no_lut: wgpu::TextureView,
+    frame: u32,
+}
+
+impl PostProcessing {
+    /// Creates an empty stack whose last effect draws in `format`.
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            },
+            count: None,
+        };
+        let sampler_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+            count: None,
+        };
+
+        let source_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("post_source_bind_group_layout"),
+            entries: &[texture_entry(0), sampler_entry(1)],
+        });
+        let effect_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("post_effect_bind_group_layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                texture_entry(1),
+                sampler_entry(2),
+            ],
+        });
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Post Effect Sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let no_lut = create_image(device, "No LUT", wgpu::TextureFormat::Rgba8Unorm, 1, 1);
+        let pipelines = Pipelines::new(device, format, &source_layout, &effect_layout);
+
+        Self {
+            effects: Vec::new(),
+            passes: Vec::new(),
+            pipelines,
+            source_layout,
+            effect_layout,
+            sampler,
+            no_lut,
+            frame: 0,
+        }
+    }
+
+    /// The stack the passes were built from.
+    pub fn effects(&self) -> &[PostEffect] {
+        &self.effects
+    }
+
+    /// Rebuilds the passes for a new stack. `load_lut` loads the lookup tables of colour grading
+    /// effects; those whose table can't be loaded are left out.
+    pub fn set_effects(
+        &mut self,
+        effects: &[PostEffect],
+        mut load_lut: impl FnMut(&str) -> Option<std::sync::Arc<Texture>>,
+        device: &wgpu::Device,
+    ) {
+        self.effects = effects.to_vec();
+        self.passes.clear();
+
+        for effect in effects.iter().filter(|effect| effect.enabled) {
+            let lut = match &effect.effect {
+                Effect::ColorGrading { lut } => match load_lut(lut) {
+                    Some(lut) => Some(lut),
+                    None => continue,
+                },
+                _ => None,
+            };
+
+            let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Post Effect Buffer"),
+                contents: bytemuck::cast_slice(&[EffectUniform::new(&effect.effect, 0.0)]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            });
+            let bind = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                layout: &self.effect_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::TextureView(
+                            lut.as_ref().map_or(&self.no_lut, |lut| &lut.view),
+                        ),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: wgpu::BindingResource::Sampler(&self.sampler),
+                    },
+                ],
+                label: Some("post_effect_bind_group"),
+            });
+
+            self.passes.push(EffectPass {
+                effect: effect.effect.clone(),
+                buffer,
+                bind,
+            });
+        }
+    }
+
+    /// Writes the settings of every pass, and moves the grain on.
+    pub fn update(&mut self, queue: &wgpu::Queue) {
+        self.frame = self.frame.wrapping_add(1);
+        let seed = (self.frame % 1024) as f32;
+        for pass in &self.passes {
+            let uniform = EffectUniform::new(&pass.effect, seed);
+            queue.write_buffer(&pass.buffer, 0, bytemuck::cast_slice(&[uniform]));
+        }
+    }
+
+    pub fn has_bloom(&self) -> bool {
+        self.bloom_passes().next().is_some()
+    }
+
+    /// How many effects are applied after tone mapping.
+    pub fn screen_pass_count(&self) -> usize {
+        self.screen_passes().count()
+    }
+
+    /// How many times bloom halves an image of the given size.
+    pub fn bloom_levels(width: u32, height: u32) -> u32 {
+        width.min(height).max(1).ilog2().min(BLOOM_LEVELS)
+    }
+
+    /// Adds bloom onto the HDR target, blurring it through `levels`, which are each half the
+    /// size of the one before, starting at half the size of `hdr`.
+    pub fn draw_bloom(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        device: &wgpu::Device,
+        hdr: &wgpu::TextureView,
+        levels: &[&wgpu::TextureView],
+    ) {
+        let Some((&first, rest)) = levels.split_first() else {
+            return;
+        };
+        let sources = levels
+            .iter()
+            .map(|level| self.source_bind_group(level, device))
+            .collect::<Vec<_>>();
+        let hdr_source = self.source_bind_group(hdr, device);
+
+        for (pass, intensity) in self.bloom_passes() {
+            let pipelines = &self.pipelines;
+            let effect = &pass.bind;
+
+            let pipeline = &pipelines.bloom_prefilter;
+            draw(encoder, pipeline, &hdr_source, effect, first, None);
+            for (larger, smaller) in sources.iter().zip(rest) {
+                let pipeline = &pipelines.bloom_downsample;
+                draw(encoder, pipeline, larger, effect, smaller, None);
+            }
+
+            // Each level gathers the blurred light of all smaller ones on the way back up
+            for (larger, smaller) in levels.iter().zip(&sources[1..]).rev() {
+                let pipeline = &pipelines.bloom_upsample;
+                draw(encoder, pipeline, smaller, effect, larger, Some(1.0));
+            }
+            let pipeline = &pipelines.bloom_upsample;
+            draw(encoder, pipeline, &sources[0], effect, hdr, Some(intensity));
+        }
+    }
+
+    /// Applies the effects after tone mapping to `images[0]`, drawing the last of them into
+    /// `output`. With more than one effect, they ping-pong between `images[0]` and `images[1]`.
+    pub fn draw(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        device: &wgpu::Device,
+        images: &[&wgpu::TextureView],
+        output: &wgpu::TextureView,
+    ) {
+        let passes = self.screen_passes().collect::<Vec<_>>();
+        let sources = images
+            .iter()
+            .map(|image| self.source_bind_group(image, device))
+            .collect::<Vec<_>>();
+        for (index, (pipeline, pass)) in passes.iter().enumerate() {
+            let target = if index + 1 == passes.len() {
+                output
+            } else {
+                images[(index + 1) % 2]
+            };
+            draw(
+                encoder,
+                pipeline,
+                &sources[index % 2],
+                &pass.bind,
+                target,
+                None,
+            );
+        }
+    }
+
+    fn source_bind_group(
+        &self,
+        view: &wgpu::TextureView,
+        device: &wgpu::Device,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.source_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+            ],
+            label: Some("post_source_bind_group"),
+        })
+    }
+
+    fn bloom_passes(&self) -> impl Iterator<Item = (&EffectPass, f32)> {
+        self.passes.iter().filter_map(|pass| match pass.effect {
+            Effect::Bloom { intensity, .. } => Some((pass, intensity)),
+            _ => None,
+        })
+    }
+
+    fn screen_passes(&self) -> impl Iterator<Item = (&wgpu::RenderPipeline, &EffectPass)> {
+        self.passes.iter().filter_map(|pass| {
+            let pipeline = match pass.effect {
+                Effect::Bloom { .. } => return None,
+                Effect::Fxaa => &self.pipelines.fxaa,
+                Effect::Vignette { .. } => &self.pipelines.vignette,
+                Effect::ColorGrading { .. } => &self.pipelines.color_grading,
+                Effect::FilmGrain { .. } => &self.pipelines.film_grain,
+            };
+            Some((pipeline, pass))
+        })
+    }
+}
+
+/// Draws a full-screen triangle into `target`, clearing it unless `blend` gives the factor the
+/// result is added with.
+fn draw(
+    encoder: &mut wgpu::CommandEncoder,
+    pipeline: &wgpu::RenderPipeline,
+    source: &wgpu::BindGroup,
+    effect: &wgpu::BindGroup,
+    target: &wgpu::TextureView,
+    blend: Option<f32>,
+) {
+    let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some("Post Effect Pass"),
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+            view: target,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: match blend {
+                    Some(_) => wgpu::LoadOp::Load,
+                    None => wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                },
+                store: true,
+            },
+        })],
+        depth_stencil_attachment: None,
+    });
+    render_pass.set_pipeline(pipeline);
+    render_pass.set_bind_group(0, source, &[]);
+    render_pass.set_bind_group(1, effect, &[]);
+    if let Some(factor) = blend {
+        let factor = factor as f64;
+        render_pass.set_blend_constant(wgpu::Color {
+            r: factor,
+            g: factor,
+            b: factor,
+            a: factor,
+        });
+    }
+    render_pass.draw(0..3, 0..1);
+}
+
+impl Pipelines {
+    fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        source_layout: &wgpu::BindGroupLayout,
+        effect_layout: &wgpu::BindGroupLayout,
+    ) -> Self {
+        let shader = device.create_shader_module(wgpu::include_wgsl!("../shaders/post.wgsl"));
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Post Effect Layout"),
+            bind_group_layouts: &[source_layout, effect_layout],
+            push_constant_ranges: &[],
+        });
+
+        let create = |entry_point, format, blend| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(entry_point),
+                layout: Some(&layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_main",
+                    buffers: &[],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point,
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format,
+                        blend,
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+            })
+        };
+
+        // Adds the result scaled by the blend constant
+        let additive = wgpu::BlendComponent {
+            src_factor: wgpu::BlendFactor::Constant,
+            dst_factor: wgpu::BlendFactor::One,
+            operation: wgpu::BlendOperation::Add,
+        };
+
+        Self {
+            bloom_prefilter: create("fs_bloom_prefilter", HDR_FORMAT, None),
+            bloom_downsample: create("fs_bloom_downsample", HDR_FORMAT, None),
+            bloom_upsample: create(
+                "fs_bloom_upsample",
+                HDR_FORMAT,
+                Some(wgpu::BlendState {
+                    color: additive,
+                    alpha: wgpu::BlendComponent::OVER,
+                }),
+            ),
+            fxaa: create("fs_fxaa", format, None),
+            vignette: create("fs_vignette", format, None),
+            color_grading: create("fs_color_grading", format, None),
+            film_grain: create("fs_film_grain", format, None),
+        }
+    }
+}
+
+fn create_image(
+    device: &wgpu::Device,
+    label: &str,
+    format: wgpu::TextureFormat,
+    width: u32,
+    height: u32,
+) -> wgpu::TextureView {
+    device
+        .create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        })
+        .create_view(&wgpu::TextureViewDescriptor::default())
+}
@@ -0,0 +1,98 @@
+use serde::{Deserialize, Serialize};
+use specs::{Component, VecStorage};
+
+/// A generated shape drawn by the entity's `Renderer`, instead of a `Model` file. The shapes are
+/// generated by `crate::primitives`, and fit in a unit cube centred on the origin, except the
+/// capsule.
+///
+/// Counts too small for a shape are raised to the least it needs, and counts past
+/// `primitives::MAX_GRID_SUBDIVISIONS` for cubes and planes, `MAX_ICOSPHERE_SUBDIVISIONS` for
+/// icospheres, and `MAX_SEGMENTS` for segments, rings and sides are lowered to them with a
+/// warning.
+#[derive(Component, Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[storage(VecStorage)]
+pub enum Primitive {
+    /// Each face divided into `subdivisions` by `subdivisions` squares.
+    Cube {
+        #[serde(default = "one")]
+        subdivisions: u32,
+    },
+    UvSphere {
+        #[serde(default = "segments")]
+        segments: u32,
+        #[serde(default = "rings")]
+        rings: u32,
+    },
+    /// An icosahedron with each triangle split into four `subdivisions` times.
+    Icosphere {
+        #[serde(default = "icosphere_subdivisions")]
+        subdivisions: u32,
+    },
+    /// A square in the XZ plane facing up.
+    Plane {
+        #[serde(default = "one")]
+        subdivisions: u32,
+    },
+    Cylinder {
+        #[serde(default = "segments")]
+        segments: u32,
+    },
+    Cone {
+        #[serde(default = "segments")]
+        segments: u32,
+    },
+    /// A ring around Y, with `segments` around the ring and `sides` around its tube.
+    Torus {
+        #[serde(default = "segments")]
+        segments: u32,
+        #[serde(default = "rings")]
+        sides: u32,
+        /// From the centre to the middle of the tube.
+        #[serde(default = "torus_radius")]
+        radius: f32,
+        #[serde(default = "tube_radius")]
+        tube_radius: f32,
+    },
+    /// A cylinder with a hemisphere on each end, half a unit wide, with `rings` on each
+    /// hemisphere.
+    Capsule {
+        #[serde(default = "segments")]
+        segments: u32,
+        #[serde(default = "capsule_rings")]
+        rings: u32,
+        #[serde(default = "capsule_height")]
+        height: f32,
+    },
+}
+
+fn one() -> u32 {
+    1
+}
+
+fn segments() -> u32 {
+    32
+}
+
+fn rings() -> u32 {
+    16
+}
+
+fn icosphere_subdivisions() -> u32 {
+    3
+}
+
+fn torus_radius() -> f32 {
+    0.375
+}
+
+fn tube_radius() -> f32 {
+    0.125
+}
+
+fn capsule_rings() -> u32 {
+    8
+}
+
+fn capsule_height() -> f32 {
+    2.0
+}
@@ -1,6 +1,15 @@
 use cgmath::Rotation3;
 use specs::{Component, VecStorage};
 
+use crate::{
+    components::{
+        background::Background, hierarchy::GlobalTransform, lod::LodModel,
+        postprocessing::PostEffect, primitive::Primitive, tonemapping::ToneMapper,
+    },
+    culling::Aabb,
+    material::AlphaMode,
+};
+
 #[derive(Component, Default, Debug)]
 #[storage(VecStorage)]
 pub struct Model {
@@ -11,7 +20,32 @@ pub struct Model {
 #[storage(VecStorage)]
 pub struct Renderer {
     pub meshes: Vec<Mesh>,
+    /// The meshes of each of the entity's `Lod` levels, whose materials are in `materials` too.
+    pub lods: Vec<Vec<Mesh>>,
     pub materials: Vec<Material>,
+    /// The level of detail to draw: 0 for `meshes`, or 1 onwards for `lods`.
+    pub level: usize,
+    /// The model file the meshes were loaded from, so that they're only reloaded when it changes.
+    pub source: Option<String>,
+    /// The `Primitive` the meshes were generated from, likewise.
+    pub primitive: Option<Primitive>,
+    /// The `Lod` models the levels were loaded from.
+    pub lod_source: Vec<LodModel>,
+}
+
+impl Renderer {
+    /// The meshes of the current level of detail. Levels that failed to load fall back to
+    /// `meshes`.
+    pub fn drawn_meshes(&self) -> &[Mesh] {
+        match self
+            .level
+            .checked_sub(1)
+            .and_then(|level| self.lods.get(level))
+        {
+            Some(meshes) if !meshes.is_empty() => meshes,
+            _ => &self.meshes,
+        }
+    }
 }
 
 #[derive(Default, Debug)]
@@ -20,13 +54,27 @@ pub struct Mesh {
     pub vertex_buffer: Option<wgpu::Buffer>,
     pub index_buffer: Option<wgpu::Buffer>,
     pub num_elements: u32,
+    /// 16-bit when the mesh has few enough vertices, halving the size of its indices.
+    pub index_format: wgpu::IndexFormat,
+    /// Drawn as a point at each index instead of as triangles, for point clouds.
+    pub points: bool,
+    /// Per-vertex RGBA colours, for meshes whose file has them.
+    pub color_buffer: Option<wgpu::Buffer>,
+    /// A second set of texture coordinates, for meshes whose file has them.
+    pub tex_coords1_buffer: Option<wgpu::Buffer>,
+    /// Tangents for normal mapping, for meshes whose file or generator has them.
+    pub tangent_buffer: Option<wgpu::Buffer>,
     pub material: usize,
+    /// The mesh's bounds in model space, which it's culled by. Blended meshes are sorted by
+    /// their centre.
+    pub bounds: Aabb,
 }
 
 #[derive(Default, Debug)]
 pub struct Material {
     pub name: String,
-    pub diffuse_bind: Option<wgpu::BindGroup>,
+    pub bind: Option<wgpu::BindGroup>,
+    pub alpha_mode: AlphaMode,
 }
 
 #[repr(C)]
@@ -52,10 +100,116 @@ impl Vertex {
     }
 }
 
+impl Mesh {
+    pub fn layout(&self) -> VertexLayout {
+        VertexLayout {
+            colors: self.color_buffer.is_some(),
+            tex_coords1: self.tex_coords1_buffer.is_some(),
+            tangents: self.tangent_buffer.is_some(),
+            points: self.points,
+        }
+    }
+
+    /// The mesh's vertex buffers, in the order of its layout's `buffers`.
+    pub fn vertex_buffers(&self) -> impl Iterator<Item = &wgpu::Buffer> {
+        [
+            &self.vertex_buffer,
+            &self.color_buffer,
+            &self.tex_coords1_buffer,
+            &self.tangent_buffer,
+        ]
+        .into_iter()
+        .flatten()
+    }
+}
+
+/// The vertex buffers a mesh is drawn from: a buffer of `Vertex`, and a buffer for each optional
+/// attribute the mesh has. Each layout has its own pipelines, whose vertex shader reads those
+/// buffers.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct VertexLayout {
+    /// RGBA colours that multiply the material's base colour.
+    pub colors: bool,
+    /// A second set of texture coordinates, which materials can read their occlusion map with.
+    pub tex_coords1: bool,
+    /// Tangents with the sign of the bitangent in w, which normal maps are applied along instead
+    /// of a frame derived from the texture coordinates' screen-space derivatives.
+    pub tangents: bool,
+    /// Drawn as points instead of triangles, which isn't a buffer but needs pipelines of its own.
+    pub points: bool,
+}
+
+impl VertexLayout {
+    const COLOR_ATTRIBS: [wgpu::VertexAttribute; 1] = wgpu::vertex_attr_array![3 => Float32x4];
+    const TEX_COORDS1_ATTRIBS: [wgpu::VertexAttribute; 1] =
+        wgpu::vertex_attr_array![4 => Float32x2];
+    const TANGENT_ATTRIBS: [wgpu::VertexAttribute; 1] = wgpu::vertex_attr_array![5 => Float32x4];
+
+    pub fn buffers<'a>(self) -> Vec<wgpu::VertexBufferLayout<'a>> {
+        let attribute = |size: usize, attributes| wgpu::VertexBufferLayout {
+            array_stride: size as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes,
+        };
+
+        let mut buffers = vec![Vertex::descriptor()];
+        if self.colors {
+            buffers.push(attribute(
+                std::mem::size_of::<[f32; 4]>(),
+                &Self::COLOR_ATTRIBS,
+            ));
+        }
+        if self.tex_coords1 {
+            buffers.push(attribute(
+                std::mem::size_of::<[f32; 2]>(),
+                &Self::TEX_COORDS1_ATTRIBS,
+            ));
+        }
+        if self.tangents {
+            buffers.push(attribute(
+                std::mem::size_of::<[f32; 4]>(),
+                &Self::TANGENT_ATTRIBS,
+            ));
+        }
+        buffers
+    }
+
+    /// The vertex shader entry point that reads the layout's buffers.
+    pub fn entry_point(self) -> &'static str {
+        match (self.colors, self.tex_coords1, self.tangents) {
+            (false, false, false) => "vs_main",
+            (true, false, false) => "vs_colored",
+            (false, true, false) => "vs_tex_coords1",
+            (true, true, false) => "vs_colored_tex_coords1",
+            (false, false, true) => "vs_tangents",
+            (true, false, true) => "vs_colored_tangents",
+            (false, true, true) => "vs_tex_coords1_tangents",
+            (true, true, true) => "vs_colored_tex_coords1_tangents",
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct MaterialUniform {
+    pub base_color: [f32; 4],
+    pub emissive: [f32; 3],
+    pub normal_scale: f32,
+    pub metallic: f32,
+    pub roughness: f32,
+    pub occlusion_strength: f32,
+    pub alpha_cutoff: f32,
+    pub occlusion_tex_coord: u32,
+    pub _padding: [u32; 3],
+}
+
 #[repr(C)]
 #[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct CameraUniform {
     view_proj: [[f32; 4]; 4],
+    model: [[f32; 4]; 4],
+    normal: [[f32; 4]; 4],
+    view_position: [f32; 4],
 }
 
 impl CameraUniform {
@@ -63,12 +217,37 @@ impl CameraUniform {
         use cgmath::SquareMatrix;
         Self {
             view_proj: cgmath::Matrix4::identity().into(),
+            model: cgmath::Matrix4::identity().into(),
+            normal: cgmath::Matrix4::identity().into(),
+            view_position: [0.0, 0.0, 0.0, 1.0],
         }
     }
 
     pub fn set_projection(&mut self, projection: cgmath::Matrix4<f32>) {
         self.view_proj = projection.into();
     }
+
+    /// Sets the object's world matrix, and the matrix its normals are transformed with.
+    pub fn set_model(&mut self, model: cgmath::Matrix4<f32>) {
+        use cgmath::{Matrix, SquareMatrix};
+
+        self.model = model.into();
+        self.normal = model
+            .invert()
+            .unwrap_or_else(cgmath::Matrix4::identity)
+            .transpose()
+            .into();
+    }
+
+    pub fn set_view_position(&mut self, position: cgmath::Point3<f32>) {
+        self.view_position = position.to_homogeneous().into();
+    }
+}
+
+impl Default for CameraUniform {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 #[derive(Component, Debug)]
@@ -76,12 +255,56 @@ impl CameraUniform {
 pub struct Camera {
     pub target: cgmath::Point3<f32>,
     pub up: cgmath::Vector3<f32>,
+    /// Width over height, kept at the window's or output image's by the camera system.
     pub aspect: f32,
     pub fovy: f32,
     pub znear: f32,
     pub zfar: f32,
+    pub background: Background,
+    /// Brightens the scene by this many stops before it's tone mapped.
+    pub exposure: f32,
+    pub tone_mapper: ToneMapper,
+    pub post_effects: Vec<PostEffect>,
+}
+
+impl Camera {
+    /// The view matrix of the camera at `global`. The target and up vector are relative to the
+    /// camera's parent, which is found by undoing its own `transform`.
+    pub fn view_matrix(
+        &self,
+        transform: &Transform,
+        global: &GlobalTransform,
+    ) -> cgmath::Matrix4<f32> {
+        use cgmath::{SquareMatrix, Transform as _};
+
+        let parent_matrix = global.matrix
+            * transform
+                .local_matrix()
+                .invert()
+                .unwrap_or_else(cgmath::Matrix4::identity);
+
+        cgmath::Matrix4::look_at_rh(
+            global.position(),
+            parent_matrix.transform_point(self.target),
+            parent_matrix.transform_vector(self.up),
+        )
+    }
+
+    /// The perspective projection, with depth mapped to wgpu's range of 0 to 1.
+    pub fn projection_matrix(&self) -> cgmath::Matrix4<f32> {
+        OPENGL_TO_WGPU_MATRIX
+            * cgmath::perspective(cgmath::Deg(self.fovy), self.aspect, self.znear, self.zfar)
+    }
 }
 
+#[rustfmt::skip]
+const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
+    1.0, 0.0, 0.0, 0.0,
+    0.0, 1.0, 0.0, 0.0,
+    0.0, 0.0, 0.5, 0.0,
+    0.0, 0.0, 0.5, 1.0,
+);
+
 impl Default for Camera {
     fn default() -> Self {
         Self {
@@ -91,6 +314,10 @@ impl Default for Camera {
             fovy: 45.0,
             znear: 0.001,
             zfar: 1000.0,
+            background: Background::default(),
+            exposure: 0.0,
+            tone_mapper: ToneMapper::default(),
+            post_effects: Vec::new(),
         }
     }
 }
@@ -118,3 +345,15 @@ impl Default for Transform {
         }
     }
 }
+
+impl Transform {
+    /// The matrix of this transform relative to its parent, or to the world if it has none.
+    pub fn local_matrix(&self) -> cgmath::Matrix4<f32> {
+        cgmath::Matrix4::from_translation(cgmath::Vector3 {
+            x: self.position.x,
+            y: self.position.y,
+            z: self.position.z,
+        }) * cgmath::Matrix4::from(self.rotation)
+            * cgmath::Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, self.scale.z)
+    }
+}
@@ -0,0 +1,189 @@
+use serde::{Deserialize, Serialize};
+use wgpu::util::DeviceExt;
+
+use crate::render_target::RenderTargets;
+
+/// How a `Camera` maps the HDR scene to the colours the display can show.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ToneMapper {
+    /// Clips everything brighter than white.
+    Clamp,
+    Reinhard,
+    /// A fit of the ACES filmic curve, with strong contrast and saturated highlights.
+    #[default]
+    Aces,
+    /// Desaturates bright colours towards white, like film does.
+    AgX,
+}
+
+impl ToneMapper {
+    fn id(self) -> u32 {
+        match self {
+            ToneMapper::Clamp => 0,
+            ToneMapper::Reinhard => 1,
+            ToneMapper::Aces => 2,
+            ToneMapper::AgX => 3,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct ToneMappingUniform {
+    /// The linear factor the scene is multiplied with.
+    pub exposure: f32,
+    pub tone_mapper: u32,
+    pub _padding: [u32; 2],
+}
+
+impl ToneMappingUniform {
+    pub fn new(exposure: f32, tone_mapper: ToneMapper) -> Self {
+        Self {
+            exposure: exposure.exp2(),
+            tone_mapper: tone_mapper.id(),
+            _padding: [0; 2],
+        }
+    }
+}
+
+/// The pass that draws the HDR target of `RenderTargets` onto the output. Its bind group is
+/// rebuilt by the `ResizingSystem` whenever the targets are.
+pub struct ToneMapping {
+    pub buffer: wgpu::Buffer,
+    pub bind: wgpu::BindGroup,
+    pub pipeline: wgpu::RenderPipeline,
+    layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+}
+
+impl ToneMapping {
+    pub fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        targets: &RenderTargets,
+    ) -> Self {
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("tone_mapping_bind_group_layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Tone Mapping Buffer"),
+            contents: bytemuck::cast_slice(&[ToneMappingUniform::new(0.0, ToneMapper::default())]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        // The target is the size of the output, so every pixel is sampled at its center
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Tone Mapping Sampler"),
+            ..Default::default()
+        });
+        let bind = create_bind_group(&buffer, &targets.hdr, &sampler, device, &layout);
+        let pipeline = create_pipeline(&layout, format, device);
+
+        Self {
+            buffer,
+            bind,
+            pipeline,
+            layout,
+            sampler,
+        }
+    }
+
+    /// Rebinds the HDR target after the targets were recreated.
+    pub fn bind_targets(&mut self, targets: &RenderTargets, device: &wgpu::Device) {
+        self.bind = create_bind_group(
+            &self.buffer,
+            &targets.hdr,
+            &self.sampler,
+            device,
+            &self.layout,
+        );
+    }
+}
+
+fn create_bind_group(
+    buffer: &wgpu::Buffer,
+    view: &wgpu::TextureView,
+    sampler: &wgpu::Sampler,
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(sampler),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: buffer.as_entire_binding(),
+            },
+        ],
+        label: Some("tone_mapping_bind_group"),
+    })
+}
+
+fn create_pipeline(
+    layout: &wgpu::BindGroupLayout,
+    format: wgpu::TextureFormat,
+    device: &wgpu::Device,
+) -> wgpu::RenderPipeline {
+    let shader = device.create_shader_module(wgpu::include_wgsl!("../shaders/tonemapping.wgsl"));
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("Tone Mapping Layout"),
+        bind_group_layouts: &[layout],
+        push_constant_ranges: &[],
+    });
+
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Tone Mapping Pipeline"),
+        layout: Some(&pipeline_layout),
+        vertex: wgpu::VertexState {
+            module: &shader,
+            entry_point: "vs_main",
+            buffers: &[],
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: &shader,
+            entry_point: "fs_main",
+            targets: &[Some(format.into())],
+        }),
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+    })
+}
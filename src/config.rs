@@ -0,0 +1,152 @@
+use crate::scene::SceneError;
+
+/// How the engine picks its GPU, device and surface settings.
+#[derive(Debug, Clone)]
+pub struct EngineConfig {
+    /// Present modes in order of preference. The first one the surface supports is used, and
+    /// `Fifo` is used if none of them are.
+    pub present_modes: Vec<wgpu::PresentMode>,
+    pub backends: wgpu::Backends,
+    pub power_preference: wgpu::PowerPreference,
+    /// Features the device must support, or the engine fails to start.
+    pub features: wgpu::Features,
+    /// Features that are enabled when the adapter supports them, such as compressed textures.
+    pub optional_features: wgpu::Features,
+    /// Limits the device must support, or the engine fails to start.
+    pub limits: wgpu::Limits,
+    /// Use a software adapter instead of a GPU.
+    pub force_fallback_adapter: bool,
+}
+
+impl Default for EngineConfig {
+    fn default() -> Self {
+        Self {
+            present_modes: vec![wgpu::PresentMode::Fifo],
+            backends: wgpu::Backends::all(),
+            power_preference: wgpu::PowerPreference::default(),
+            features: wgpu::Features::empty(),
+            optional_features: wgpu::Features::TEXTURE_COMPRESSION_BC
+                | wgpu::Features::TEXTURE_COMPRESSION_ETC2
+                | wgpu::Features::TEXTURE_COMPRESSION_ASTC_LDR
+                // Allows MSAA sample counts other than 4
+                | wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES,
+            limits: if cfg!(target_arch = "wasm32") {
+                wgpu::Limits::downlevel_webgl2_defaults()
+            } else {
+                wgpu::Limits::default()
+            },
+            force_fallback_adapter: false,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum EngineError {
+    CreateSurface(wgpu::CreateSurfaceError),
+    NoAdapter {
+        backends: wgpu::Backends,
+        force_fallback_adapter: bool,
+    },
+    MissingFeatures {
+        adapter: String,
+        missing: wgpu::Features,
+    },
+    UnsupportedLimits {
+        adapter: String,
+        failures: Vec<String>,
+    },
+    RequestDevice(wgpu::RequestDeviceError),
+    LoadScene {
+        path: String,
+        error: Box<SceneError>,
+    },
+    SaveFrame {
+        path: String,
+        error: image::ImageError,
+    },
+}
+
+impl std::fmt::Display for EngineError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EngineError::CreateSurface(error) => write!(f, "Couldn't create surface: {error}"),
+            EngineError::NoAdapter {
+                backends,
+                force_fallback_adapter: true,
+            } => write!(f, "No fallback adapter found for backends {backends:?}"),
+            EngineError::NoAdapter { backends, .. } => {
+                write!(f, "No adapter found for backends {backends:?}")
+            }
+            EngineError::MissingFeatures { adapter, missing } => {
+                write!(
+                    f,
+                    "Adapter {adapter:?} is missing required features {missing:?}"
+                )
+            }
+            EngineError::UnsupportedLimits { adapter, failures } => write!(
+                f,
+                "Adapter {adapter:?} doesn't support the required limits: {}",
+                failures.join(", ")
+            ),
+            EngineError::RequestDevice(error) => write!(f, "Couldn't create device: {error}"),
+            EngineError::LoadScene { path, error } => {
+                write!(f, "Couldn't load scene {path:?}: {error}")
+            }
+            EngineError::SaveFrame { path, error } => {
+                write!(f, "Couldn't save frame to {path:?}: {error}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for EngineError {}
+
+impl EngineConfig {
+    /// Checks that an adapter supports the required features and limits.
+    pub fn check_adapter(&self, adapter: &wgpu::Adapter) -> Result<(), EngineError> {
+        let name = adapter.get_info().name;
+
+        let missing = self.features - adapter.features();
+        if !missing.is_empty() {
+            return Err(EngineError::MissingFeatures {
+                adapter: name,
+                missing,
+            });
+        }
+
+        let mut failures = Vec::new();
+        self.limits.check_limits_with_fail_fn(
+            &adapter.limits(),
+            false,
+            |limit, required, allowed| {
+                failures.push(format!("{limit} (required {required}, allowed {allowed})"))
+            },
+        );
+        if !failures.is_empty() {
+            return Err(EngineError::UnsupportedLimits {
+                adapter: name,
+                failures,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Picks the first preferred present mode the surface supports.
+    pub fn select_present_mode(&self, supported: &[wgpu::PresentMode]) -> wgpu::PresentMode {
+        match self
+            .present_modes
+            .iter()
+            .find(|mode| supported.contains(mode))
+        {
+            Some(mode) => *mode,
+            None => {
+                log::warn!(
+                    "None of the present modes {:?} are supported, falling back to Fifo",
+                    self.present_modes
+                );
+                wgpu::PresentMode::Fifo
+            }
+        }
+    }
+}
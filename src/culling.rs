@@ -0,0 +1,248 @@
+//! Skipping meshes that fall outside the camera's view.
+
+use cgmath::{InnerSpace, Vector3, Vector4};
+
+/// An axis-aligned bounding box.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Aabb {
+    pub min: [f32; 3],
+    pub max: [f32; 3],
+}
+
+impl Aabb {
+    /// The smallest box around `points`, or an empty box at the origin when there are none.
+    pub fn from_points(points: impl IntoIterator<Item = [f32; 3]>) -> Self {
+        points
+            .into_iter()
+            .fold(None, |bounds: Option<Self>, p| {
+                Some(match bounds {
+                    Some(Self { min, max }) => Self {
+                        min: [0, 1, 2].map(|axis| min[axis].min(p[axis])),
+                        max: [0, 1, 2].map(|axis| max[axis].max(p[axis])),
+                    },
+                    None => Self { min: p, max: p },
+                })
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn center(&self) -> [f32; 3] {
+        [0, 1, 2].map(|axis| (self.min[axis] + self.max[axis]) / 2.0)
+    }
+
+    /// Half the size of the box along each axis.
+    pub fn extents(&self) -> [f32; 3] {
+        [0, 1, 2].map(|axis| (self.max[axis] - self.min[axis]) / 2.0)
+    }
+
+    /// The smallest box around both this one and `other`.
+    pub fn union(&self, other: &Self) -> Self {
+        Self {
+            min: [0, 1, 2].map(|axis| self.min[axis].min(other.min[axis])),
+            max: [0, 1, 2].map(|axis| self.max[axis].max(other.max[axis])),
+        }
+    }
+
+    /// The box around this one once it's transformed by `matrix`.
+    pub fn transformed(&self, matrix: &cgmath::Matrix4<f32>) -> Self {
+        let center = matrix * Vector3::from(self.center()).extend(1.0);
+        let extents = Vector3::from(self.extents());
+        // Each axis of the new box spans the absolute projections of the old box's axes
+        let extents = [0, 1, 2].map(|row| {
+            extents.x * matrix.x[row].abs()
+                + extents.y * matrix.y[row].abs()
+                + extents.z * matrix.z[row].abs()
+        });
+
+        Self {
+            min: [0, 1, 2].map(|axis| center[axis] - extents[axis]),
+            max: [0, 1, 2].map(|axis| center[axis] + extents[axis]),
+        }
+    }
+}
+
+/// The six planes bounding what a camera sees, each pointing inwards.
+#[derive(Debug, Clone, Copy)]
+pub struct Frustum {
+    planes: [Vector4<f32>; 6],
+}
+
+impl Frustum {
+    /// The frustum of a view projection matrix with wgpu's depth range of 0 to 1.
+    pub fn from_view_projection(matrix: &cgmath::Matrix4<f32>) -> Self {
+        use cgmath::Matrix;
+
+        let row = |i| matrix.row(i);
+        Self {
+            planes: [
+                row(3) + row(0),
+                row(3) - row(0),
+                row(3) + row(1),
+                row(3) - row(1),
+                row(2),
+                row(3) - row(2),
+            ],
+        }
+    }
+
+    /// Whether any of the world-space box `bounds` might be visible. Boxes near the frustum's
+    /// corners can pass without being seen, which only costs drawing them.
+    pub fn intersects(&self, bounds: &Aabb) -> bool {
+        let center = Vector3::from(bounds.center());
+        let extents = Vector3::from(bounds.extents());
+        self.planes.iter().all(|plane| {
+            let normal = plane.truncate();
+            let radius = extents.dot(normal.map(f32::abs));
+            normal.dot(center) + plane.w >= -radius
+        })
+    }
+}
+
+/// How many `Renderer` entities the last frame drew, and how many it skipped because none of
+/// their meshes were in view.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CullingStats {
+    pub drawn: usize,
+    pub culled: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{Deg, Matrix4, Rotation3, SquareMatrix};
+
+    use super::*;
+    use crate::components::rendering::Camera;
+
+    /// A camera seeing from 1 to 10 units away, and as far to each side as it is away.
+    fn camera() -> Camera {
+        Camera {
+            fovy: 90.0,
+            aspect: 1.0,
+            znear: 1.0,
+            zfar: 10.0,
+            ..Default::default()
+        }
+    }
+
+    /// The frustum of `camera()` at the origin looking down -Z.
+    fn frustum() -> Frustum {
+        Frustum::from_view_projection(&camera().projection_matrix())
+    }
+
+    fn cube(center: [f32; 3], half_size: f32) -> Aabb {
+        Aabb {
+            min: center.map(|c| c - half_size),
+            max: center.map(|c| c + half_size),
+        }
+    }
+
+    fn assert_close(a: &Aabb, b: &Aabb) {
+        for (a, b) in a.min.iter().chain(&a.max).zip(b.min.iter().chain(&b.max)) {
+            assert!((a - b).abs() < 1e-5, "{a:?} != {b:?}");
+        }
+    }
+
+    #[test]
+    fn boxes_inside_the_frustum_intersect_it() {
+        let frustum = frustum();
+        assert!(frustum.intersects(&cube([0.0, 0.0, -5.0], 0.5)));
+        assert!(frustum.intersects(&cube([3.0, -3.0, -5.0], 0.5)));
+        // Larger than the frustum on every side
+        assert!(frustum.intersects(&cube([0.0, 0.0, -5.0], 100.0)));
+    }
+
+    #[test]
+    fn boxes_outside_any_plane_are_culled() {
+        let frustum = frustum();
+        for center in [
+            [-10.0, 0.0, -5.0],
+            [10.0, 0.0, -5.0],
+            [0.0, -10.0, -5.0],
+            [0.0, 10.0, -5.0],
+            // In front of the near plane, behind the camera, and past the far plane
+            [0.0, 0.0, -0.25],
+            [0.0, 0.0, 5.0],
+            [0.0, 0.0, -12.0],
+        ] {
+            assert!(!frustum.intersects(&cube(center, 0.5)), "{center:?}");
+        }
+    }
+
+    #[test]
+    fn boxes_straddling_any_plane_intersect_it() {
+        let frustum = frustum();
+        for center in [
+            [-5.0, 0.0, -5.0],
+            [5.0, 0.0, -5.0],
+            [0.0, -5.0, -5.0],
+            [0.0, 5.0, -5.0],
+            [0.0, 0.0, -1.0],
+            [0.0, 0.0, -10.0],
+        ] {
+            assert!(frustum.intersects(&cube(center, 0.5)), "{center:?}");
+        }
+    }
+
+    #[test]
+    fn the_view_moves_the_frustum() {
+        // Looking down +X from 20 units along it
+        let view = Matrix4::look_to_rh(
+            cgmath::Point3::new(20.0, 0.0, 0.0),
+            Vector3::unit_x(),
+            Vector3::unit_y(),
+        );
+        let frustum = Frustum::from_view_projection(&(camera().projection_matrix() * view));
+        assert!(frustum.intersects(&cube([25.0, 0.0, 0.0], 0.5)));
+        assert!(!frustum.intersects(&cube([15.0, 0.0, 0.0], 0.5)));
+        assert!(!frustum.intersects(&cube([0.0, 0.0, -5.0], 0.5)));
+    }
+
+    #[test]
+    fn transformed_boxes_are_scaled_and_moved() {
+        let matrix = Matrix4::from_translation(Vector3::new(1.0, 2.0, 3.0))
+            * Matrix4::from_nonuniform_scale(2.0, 3.0, -1.0);
+        let expected = Aabb {
+            min: [-1.0, -1.0, 2.0],
+            max: [3.0, 5.0, 4.0],
+        };
+        assert_close(&cube([0.0, 0.0, 0.0], 1.0).transformed(&matrix), &expected);
+        assert_eq!(
+            cube([0.0, 0.0, 0.0], 1.0).transformed(&Matrix4::identity()),
+            cube([0.0, 0.0, 0.0], 1.0)
+        );
+    }
+
+    #[test]
+    fn transformed_boxes_cover_their_rotated_corners() {
+        let bounds = Aabb {
+            min: [0.0, 0.0, 0.0],
+            max: [2.0, 1.0, 1.0],
+        };
+        let quarter_turn = Matrix4::from(cgmath::Quaternion::from_angle_z(Deg(90.0)));
+        let expected = Aabb {
+            min: [-1.0, 0.0, 0.0],
+            max: [0.0, 2.0, 1.0],
+        };
+        assert_close(&bounds.transformed(&quarter_turn), &expected);
+
+        // The box around the transformed corners, for a turn that isn't a multiple of 90 degrees
+        let matrix = Matrix4::from_translation(Vector3::new(-4.0, 1.0, 0.5))
+            * Matrix4::from(cgmath::Quaternion::from_axis_angle(
+                Vector3::new(1.0, 1.0, 0.0).normalize(),
+                Deg(30.0),
+            ))
+            * Matrix4::from_nonuniform_scale(1.0, 0.5, 3.0);
+        let corners = (0..8).map(|corner| {
+            let point = [0, 1, 2].map(|axis| {
+                if corner & (1 << axis) == 0 {
+                    bounds.min[axis]
+                } else {
+                    bounds.max[axis]
+                }
+            });
+            let point = matrix * Vector3::from(point).extend(1.0);
+            [point.x, point.y, point.z]
+        });
+        assert_close(&bounds.transformed(&matrix), &Aabb::from_points(corners));
+    }
+}
@@ -0,0 +1,604 @@
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use wgpu::util::DeviceExt;
+
+use crate::{
+    material_manager::{MaterialManager, SamplerOptions, Texture},
+    texture_data::{ColorSpace, TextureError},
+};
+
+const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+const BRDF_LUT_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rg16Float;
+const MAX_CUBEMAP_SIZE: u32 = 512;
+const MAX_SKYBOX_SIZE: u32 = 2048;
+const IRRADIANCE_SIZE: u32 = 32;
+const PREFILTERED_SIZE: u32 = 128;
+const PREFILTERED_MIP_COUNT: u32 = 5;
+const BRDF_LUT_SIZE: u32 = 256;
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// Image-based lighting from the surroundings of a scene, as a resource.
+///
+/// The environment is converted to a cubemap once, and the maps the lit shader needs are
+/// precomputed from it on the GPU: diffuse irradiance, specular radiance prefiltered for
+/// increasing roughness along its mip levels, and the split-sum BRDF lookup table.
+pub struct Environment {
+    /// Multiplies the light the environment casts.
+    pub intensity: f32,
+    /// The file the environment was loaded from, if any.
+    pub source: Option<String>,
+
+    pub cubemap: wgpu::Texture,
+    /// A cube view of every mip level of `cubemap`.
+    pub cubemap_view: wgpu::TextureView,
+    pub irradiance: wgpu::Texture,
+    pub irradiance_view: wgpu::TextureView,
+    pub prefiltered: wgpu::Texture,
+    pub prefiltered_view: wgpu::TextureView,
+    pub brdf_lut: wgpu::Texture,
+    pub brdf_lut_view: wgpu::TextureView,
+    pub sampler: wgpu::Sampler,
+    /// Identifies the environment, so that bind groups using it can tell when it's replaced.
+    pub(crate) id: usize,
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct Parameters {
+    face: u32,
+    roughness: f32,
+    lod: f32,
+    source_size: f32,
+}
+
+impl Environment {
+    /// Loads an equirectangular (latitude-longitude) image, usually an HDR or EXR file.
+    pub fn load(
+        path: &Path,
+        material_manager: &MaterialManager,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Result<Self, TextureError> {
+        let options = SamplerOptions {
+            anisotropy: 1,
+            ..Default::default()
+        };
+        let equirectangular =
+            material_manager.load_texture(path, &options, ColorSpace::Srgb, device, queue)?;
+
+        let mut environment = Self::from_equirectangular(&equirectangular, device, queue);
+        environment.source = Some(path.to_string_lossy().into_owned());
+        Ok(environment)
+    }
+
+    /// Builds an environment from an equirectangular texture.
+    pub fn from_equirectangular(
+        equirectangular: &Texture,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Self {
+        let source_size = equirectangular.texture.size();
+        // Four faces span the width of the image
+        let cubemap_size = (source_size.width / 4)
+            .next_power_of_two()
+            .clamp(IRRADIANCE_SIZE, MAX_CUBEMAP_SIZE);
+        let cubemap_mips = cubemap_size.ilog2() + 1;
+        let source_lod = (source_size.width as f32 / 4.0 / cubemap_size as f32)
+            .log2()
+            .max(0.0);
+
+        let mut environment = Self::create(
+            cubemap_size,
+            cubemap_mips,
+            IRRADIANCE_SIZE,
+            PREFILTERED_SIZE,
+            PREFILTERED_MIP_COUNT,
+            BRDF_LUT_SIZE,
+            device,
+        );
+        environment.intensity = 1.0;
+
+        let precompute = Precompute::new(device);
+        let irradiance_pipeline = precompute.pipeline("fs_irradiance", FORMAT, device);
+        let prefilter_pipeline = precompute.pipeline("fs_prefilter", FORMAT, device);
+        let brdf_pipeline =
+            create_pipeline(&precompute.shader, None, "fs_brdf", BRDF_LUT_FORMAT, device);
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Environment Encoder"),
+        });
+
+        // Every mip of the cubemap is drawn from the source, so the cubemap never has to be
+        // sampled while it's being rendered to
+        precompute.draw_faces(
+            "fs_equirectangular",
+            [&equirectangular.view; 6],
+            source_lod,
+            &environment.cubemap,
+            &environment.sampler,
+            &mut encoder,
+            device,
+        );
+
+        for face in 0..6 {
+            let parameters = Parameters {
+                face,
+                roughness: 0.0,
+                lod: (cubemap_size as f32 / IRRADIANCE_SIZE as f32).log2(),
+                source_size: cubemap_size as f32,
+            };
+            let bind = precompute.bind_group(
+                &environment.cubemap_view,
+                true,
+                &environment.sampler,
+                parameters,
+                device,
+            );
+            draw(
+                &mut encoder,
+                &irradiance_pipeline,
+                Some(&bind),
+                &face_view(&environment.irradiance, face, 0),
+            );
+        }
+
+        for mip in 0..PREFILTERED_MIP_COUNT {
+            let level_size = (PREFILTERED_SIZE >> mip).max(1);
+            for face in 0..6 {
+                let parameters = Parameters {
+                    face,
+                    roughness: mip as f32 / (PREFILTERED_MIP_COUNT - 1) as f32,
+                    lod: (cubemap_size as f32 / level_size as f32).log2().max(0.0),
+                    source_size: cubemap_size as f32,
+                };
+                let bind = precompute.bind_group(
+                    &environment.cubemap_view,
+                    true,
+                    &environment.sampler,
+                    parameters,
+                    device,
+                );
+                draw(
+                    &mut encoder,
+                    &prefilter_pipeline,
+                    Some(&bind),
+                    &face_view(&environment.prefiltered, face, mip),
+                );
+            }
+        }
+
+        draw(
+            &mut encoder,
+            &brdf_pipeline,
+            None,
+            &environment
+                .brdf_lut
+                .create_view(&wgpu::TextureViewDescriptor::default()),
+        );
+
+        queue.submit(std::iter::once(encoder.finish()));
+
+        environment
+    }
+
+    /// An environment that casts no light, for scenes without one.
+    pub fn black(device: &wgpu::Device) -> Self {
+        // Textures start out zeroed
+        Self::create(1, 1, 1, 1, 1, 1, device)
+    }
+
+    /// The highest mip level of `prefiltered`, which holds fully rough reflections.
+    pub fn max_reflection_lod(&self) -> f32 {
+        (self.prefiltered.mip_level_count() - 1) as f32
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn create(
+        cubemap_size: u32,
+        cubemap_mips: u32,
+        irradiance_size: u32,
+        prefiltered_size: u32,
+        prefiltered_mips: u32,
+        brdf_lut_size: u32,
+        device: &wgpu::Device,
+    ) -> Self {
+        let cubemap = create_cube("Environment Cubemap", cubemap_size, cubemap_mips, device);
+        let irradiance = create_cube("Irradiance Cubemap", irradiance_size, 1, device);
+        let prefiltered = create_cube(
+            "Prefiltered Cubemap",
+            prefiltered_size,
+            prefiltered_mips,
+            device,
+        );
+        let brdf_lut = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("BRDF Lookup Table"),
+            size: wgpu::Extent3d {
+                width: brdf_lut_size,
+                height: brdf_lut_size,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: BRDF_LUT_FORMAT,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        });
+
+        Self {
+            intensity: 0.0,
+            source: None,
+            cubemap_view: cube_view(&cubemap),
+            irradiance_view: cube_view(&irradiance),
+            prefiltered_view: cube_view(&prefiltered),
+            brdf_lut_view: brdf_lut.create_view(&wgpu::TextureViewDescriptor::default()),
+            cubemap,
+            irradiance,
+            prefiltered,
+            brdf_lut,
+            sampler: create_sampler(device),
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+        }
+    }
+}
+
+/// A cubemap drawn behind the scene by the `Sky`.
+pub struct Skybox {
+    pub texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+}
+
+impl Skybox {
+    /// Projects an equirectangular texture onto a cubemap, at a resolution that keeps its detail.
+    pub fn from_equirectangular(
+        equirectangular: &Texture,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Self {
+        let width = equirectangular.texture.width();
+        let size = (width / 4).next_power_of_two().min(MAX_SKYBOX_SIZE);
+        let source_lod = (width as f32 / 4.0 / size as f32).log2().max(0.0);
+
+        Self::draw(
+            "fs_equirectangular",
+            [&equirectangular.view; 6],
+            size,
+            source_lod,
+            device,
+            queue,
+        )
+    }
+
+    /// Assembles a cubemap from one square image per face, in the order +X, -X, +Y, -Y, +Z, -Z.
+    pub fn from_faces(faces: [&Texture; 6], device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        let width = faces[0].texture.width();
+        let size = width.min(MAX_SKYBOX_SIZE);
+        let source_lod = (width as f32 / size as f32).log2();
+
+        Self::draw(
+            "fs_face",
+            faces.map(|face| &face.view),
+            size,
+            source_lod,
+            device,
+            queue,
+        )
+    }
+
+    /// A 1x1 black cubemap.
+    pub fn black(device: &wgpu::Device) -> Self {
+        // Textures start out zeroed
+        let texture = create_cube("Skybox", 1, 1, device);
+        Self {
+            view: cube_view(&texture),
+            texture,
+        }
+    }
+
+    fn draw(
+        entry_point: &str,
+        sources: [&wgpu::TextureView; 6],
+        size: u32,
+        source_lod: f32,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Self {
+        let texture = create_cube("Skybox", size, 1, device);
+        let sampler = create_sampler(device);
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Skybox Encoder"),
+        });
+        Precompute::new(device).draw_faces(
+            entry_point,
+            sources,
+            source_lod,
+            &texture,
+            &sampler,
+            &mut encoder,
+            device,
+        );
+        queue.submit(std::iter::once(encoder.finish()));
+
+        Self {
+            view: cube_view(&texture),
+            texture,
+        }
+    }
+}
+
+/// The shader of the precomputation passes, and the layouts of their 2D and cube sources.
+struct Precompute {
+    shader: wgpu::ShaderModule,
+    equirectangular_layout: wgpu::BindGroupLayout,
+    cube_layout: wgpu::BindGroupLayout,
+}
+
+impl Precompute {
+    fn new(device: &wgpu::Device) -> Self {
+        let shader = device.create_shader_module(wgpu::include_wgsl!("shaders/environment.wgsl"));
+        let entry = |binding, ty| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty,
+            count: None,
+        };
+        let sampler_entry = entry(
+            1,
+            wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+        );
+        let parameters_entry = entry(
+            2,
+            wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+        );
+        let texture_entry = |binding, view_dimension| {
+            entry(
+                binding,
+                wgpu::BindingType::Texture {
+                    multisampled: false,
+                    view_dimension,
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                },
+            )
+        };
+
+        let equirectangular_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("equirectangular_bind_group_layout"),
+                entries: &[
+                    texture_entry(0, wgpu::TextureViewDimension::D2),
+                    sampler_entry,
+                    parameters_entry,
+                ],
+            });
+        let cube_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("environment_cube_bind_group_layout"),
+            entries: &[
+                texture_entry(3, wgpu::TextureViewDimension::Cube),
+                sampler_entry,
+                parameters_entry,
+            ],
+        });
+
+        Self {
+            shader,
+            equirectangular_layout,
+            cube_layout,
+        }
+    }
+
+    /// A pipeline that samples the cubemap.
+    fn pipeline(
+        &self,
+        entry_point: &str,
+        format: wgpu::TextureFormat,
+        device: &wgpu::Device,
+    ) -> wgpu::RenderPipeline {
+        create_pipeline(
+            &self.shader,
+            Some(&self.cube_layout),
+            entry_point,
+            format,
+            device,
+        )
+    }
+
+    fn bind_group(
+        &self,
+        source: &wgpu::TextureView,
+        cube: bool,
+        sampler: &wgpu::Sampler,
+        parameters: Parameters,
+        device: &wgpu::Device,
+    ) -> wgpu::BindGroup {
+        let (layout, binding) = if cube {
+            (&self.cube_layout, 3)
+        } else {
+            (&self.equirectangular_layout, 0)
+        };
+
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Environment Parameters"),
+            contents: bytemuck::cast_slice(&[parameters]),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding,
+                    resource: wgpu::BindingResource::TextureView(source),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: buffer.as_entire_binding(),
+                },
+            ],
+        })
+    }
+
+    /// Draws every face and mip level of `cubemap` from 2D sources, one per face. Each mip level
+    /// samples the sources `source_lod` levels further down.
+    #[allow(clippy::too_many_arguments)]
+    fn draw_faces(
+        &self,
+        entry_point: &str,
+        sources: [&wgpu::TextureView; 6],
+        source_lod: f32,
+        cubemap: &wgpu::Texture,
+        sampler: &wgpu::Sampler,
+        encoder: &mut wgpu::CommandEncoder,
+        device: &wgpu::Device,
+    ) {
+        let pipeline = create_pipeline(
+            &self.shader,
+            Some(&self.equirectangular_layout),
+            entry_point,
+            FORMAT,
+            device,
+        );
+
+        for mip in 0..cubemap.mip_level_count() {
+            for (face, source) in (0..6).zip(sources) {
+                let parameters = Parameters {
+                    face,
+                    roughness: 0.0,
+                    lod: source_lod + mip as f32,
+                    source_size: 0.0,
+                };
+                let bind = self.bind_group(source, false, sampler, parameters, device);
+                draw(
+                    encoder,
+                    &pipeline,
+                    Some(&bind),
+                    &face_view(cubemap, face, mip),
+                );
+            }
+        }
+    }
+}
+
+fn create_sampler(device: &wgpu::Device) -> wgpu::Sampler {
+    device.create_sampler(&wgpu::SamplerDescriptor {
+        label: Some("Environment Sampler"),
+        address_mode_u: wgpu::AddressMode::ClampToEdge,
+        address_mode_v: wgpu::AddressMode::ClampToEdge,
+        address_mode_w: wgpu::AddressMode::ClampToEdge,
+        mag_filter: wgpu::FilterMode::Linear,
+        min_filter: wgpu::FilterMode::Linear,
+        mipmap_filter: wgpu::FilterMode::Linear,
+        ..Default::default()
+    })
+}
+
+fn create_cube(
+    label: &str,
+    size: u32,
+    mip_level_count: u32,
+    device: &wgpu::Device,
+) -> wgpu::Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+        label: Some(label),
+        size: wgpu::Extent3d {
+            width: size,
+            height: size,
+            depth_or_array_layers: 6,
+        },
+        mip_level_count,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: FORMAT,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::RENDER_ATTACHMENT,
+        view_formats: &[],
+    })
+}
+
+fn cube_view(texture: &wgpu::Texture) -> wgpu::TextureView {
+    texture.create_view(&wgpu::TextureViewDescriptor {
+        dimension: Some(wgpu::TextureViewDimension::Cube),
+        ..Default::default()
+    })
+}
+
+fn face_view(texture: &wgpu::Texture, face: u32, mip: u32) -> wgpu::TextureView {
+    texture.create_view(&wgpu::TextureViewDescriptor {
+        label: Some("Cubemap Face"),
+        dimension: Some(wgpu::TextureViewDimension::D2),
+        base_mip_level: mip,
+        mip_level_count: std::num::NonZeroU32::new(1),
+        base_array_layer: face,
+        array_layer_count: std::num::NonZeroU32::new(1),
+        ..Default::default()
+    })
+}
+
+fn create_pipeline(
+    shader: &wgpu::ShaderModule,
+    layout: Option<&wgpu::BindGroupLayout>,
+    entry_point: &str,
+    format: wgpu::TextureFormat,
+    device: &wgpu::Device,
+) -> wgpu::RenderPipeline {
+    let layouts = layout.into_iter().collect::<Vec<_>>();
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some(entry_point),
+        bind_group_layouts: &layouts,
+        push_constant_ranges: &[],
+    });
+
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(entry_point),
+        layout: Some(&pipeline_layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: "vs_main",
+            buffers: &[],
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point,
+            targets: &[Some(format.into())],
+        }),
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+    })
+}
+
+fn draw(
+    encoder: &mut wgpu::CommandEncoder,
+    pipeline: &wgpu::RenderPipeline,
+    bind_group: Option<&wgpu::BindGroup>,
+    target: &wgpu::TextureView,
+) {
+    let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some("Environment Pass"),
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+            view: target,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                store: true,
+            },
+        })],
+        depth_stencil_attachment: None,
+    });
+
+    render_pass.set_pipeline(pipeline);
+    if let Some(bind_group) = bind_group {
+        render_pass.set_bind_group(0, bind_group, &[]);
+    }
+    render_pass.draw(0..3, 0..1);
+}
@@ -0,0 +1,93 @@
+/// A texture that the `RenderSystem` draws into when there is no window to present to.
+pub struct OffscreenTarget {
+    pub texture: wgpu::Texture,
+}
+
+impl OffscreenTarget {
+    pub fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        width: u32,
+        height: u32,
+    ) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Offscreen Target"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+
+        Self { texture }
+    }
+
+    /// Reads the target back from the GPU and writes it to a PNG file.
+    pub fn save_png(
+        &self,
+        path: &str,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> image::ImageResult<()> {
+        let size = self.texture.size();
+
+        // Rows in a texture-to-buffer copy have to be padded to a fixed alignment
+        let unpadded_bytes_per_row = 4 * size.width;
+        let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let padded_bytes_per_row = unpadded_bytes_per_row.div_ceil(align) * align;
+
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Offscreen Readback Buffer"),
+            size: (padded_bytes_per_row * size.height) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Offscreen Readback Encoder"),
+        });
+
+        encoder.copy_texture_to_buffer(
+            self.texture.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: std::num::NonZeroU32::new(padded_bytes_per_row),
+                    rows_per_image: std::num::NonZeroU32::new(size.height),
+                },
+            },
+            size,
+        );
+
+        queue.submit(std::iter::once(encoder.finish()));
+
+        let slice = buffer.slice(..);
+        slice.map_async(wgpu::MapMode::Read, |result| {
+            result.expect("Couldn't map readback buffer");
+        });
+        device.poll(wgpu::Maintain::Wait);
+
+        let pixels = slice
+            .get_mapped_range()
+            .chunks(padded_bytes_per_row as usize)
+            .flat_map(|row| row[..unpadded_bytes_per_row as usize].to_vec())
+            .collect::<Vec<_>>();
+
+        buffer.unmap();
+
+        image::save_buffer(
+            path,
+            &pixels,
+            size.width,
+            size.height,
+            image::ColorType::Rgba8,
+        )
+    }
+}
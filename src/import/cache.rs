@@ -0,0 +1,497 @@
+//! A binary copy of every parsed OBJ file, so that it's only parsed again when it changes.
+//!
+//! Each file is stored in the cache directory under a hash of its path, and its entry holds a hash
+//! of its path and contents, so that a changed file replaces its entry instead of leaving it
+//! behind. Besides the meshes, an entry holds the MTL materials and the hashes of the libraries
+//! they came from, and it's ignored when a library has changed, or when it was written by a
+//! different importer.
+
+use std::path::{Path, PathBuf};
+
+use super::{obj::ParsedObj, ImportError, MeshData};
+use crate::{components::rendering::Vertex, culling::Aabb};
+
+const MAGIC: &[u8; 8] = b"GRTMESH\0";
+
+/// Bump whenever `obj::parse` returns something different for the same file, so that entries
+/// written before are parsed again.
+const VERSION: u32 = 4;
+
+/// Loads the parse of the OBJ file at `path`, whose contents are `source`, from the cache in
+/// `directory`, or calls `parse` and stores what it returns. Caches that can't be read or
+/// written are only logged.
+pub fn load_or_parse(
+    directory: &Path,
+    path: &Path,
+    source: &[u8],
+    parse: impl FnOnce() -> Result<ParsedObj, ImportError>,
+) -> Result<ParsedObj, ImportError> {
+    let name = Hasher::new().write(path.to_string_lossy().as_bytes());
+    let entry = directory.join(format!("{:016x}.mesh", name.0));
+    let key = name.write(source).finish();
+
+    match std::fs::read(&entry) {
+        Ok(bytes) => match read(&bytes, key) {
+            Some(parsed) => {
+                log::debug!("Loaded {:?} from the mesh cache", path);
+                return Ok(parsed);
+            }
+            None => {
+                log::debug!("Mesh cache of {:?} is stale", path);
+                // Removed now rather than replaced, in case the file no longer parses
+                if let Err(error) = std::fs::remove_file(&entry) {
+                    log::warn!("Couldn't remove mesh cache {:?}: {}", entry, error);
+                }
+            }
+        },
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => {}
+        Err(error) => log::warn!("Couldn't read mesh cache {:?}: {}", entry, error),
+    }
+
+    let parsed = parse()?;
+    if let Err(error) = store(directory, &entry, &write(&parsed, key)) {
+        log::warn!("Couldn't write mesh cache {:?}: {}", entry, error);
+    }
+    Ok(parsed)
+}
+
+fn store(directory: &Path, entry: &Path, bytes: &[u8]) -> std::io::Result<()> {
+    std::fs::create_dir_all(directory)?;
+    // Renamed into place, so that another instance never reads half an entry
+    let partial = entry.with_extension(format!("{}.partial", std::process::id()));
+    std::fs::write(&partial, bytes)?;
+    std::fs::rename(&partial, entry)
+}
+
+/// The hash of a file's contents, or `None` if it can't be read.
+fn hash_file(path: &Path) -> Option<u64> {
+    let bytes = std::fs::read(path).ok()?;
+    Some(Hasher::new().write(&bytes).finish())
+}
+
+/// 64-bit FNV-1a, which unlike the standard library's hasher is the same in every build.
+struct Hasher(u64);
+
+impl Hasher {
+    fn new() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+
+    fn write(mut self, bytes: &[u8]) -> Self {
+        for &byte in bytes {
+            self.0 = (self.0 ^ u64::from(byte)).wrapping_mul(0x0000_0100_0000_01b3);
+        }
+        self
+    }
+
+    fn finish(self) -> u64 {
+        self.0
+    }
+}
+
+fn write(parsed: &ParsedObj, key: u64) -> Vec<u8> {
+    let mut writer = Writer::default();
+    writer.bytes(MAGIC);
+    writer.u32(VERSION);
+    writer.str(env!("CARGO_PKG_VERSION"));
+    writer.u32(std::mem::size_of::<Vertex>() as u32);
+    writer.u64(key);
+
+    writer.u32(parsed.libraries.len() as u32);
+    for library in &parsed.libraries {
+        writer.str(&library.to_string_lossy());
+        writer.option(hash_file(library), Writer::u64);
+    }
+
+    writer.u32(parsed.materials.len() as u32);
+    for material in &parsed.materials {
+        write_material(&mut writer, material);
+    }
+
+    writer.u32(parsed.meshes.len() as u32);
+    for mesh in &parsed.meshes {
+        writer.str(&mesh.name);
+        writer.u64(mesh.material as u64);
+        for value in mesh.bounds.min.iter().chain(&mesh.bounds.max) {
+            writer.f32(*value);
+        }
+        writer.slice(&mesh.vertices);
+        writer.slice(&mesh.indices);
+        writer.option(mesh.colors.as_deref(), Writer::slice);
+        writer.option(mesh.tex_coords1.as_deref(), Writer::slice);
+        writer.option(mesh.tangents.as_deref(), Writer::slice);
+    }
+
+    writer.0
+}
+
+/// Reads an entry, or returns `None` if it's damaged or out of date.
+fn read(bytes: &[u8], key: u64) -> Option<ParsedObj> {
+    let mut reader = Reader(bytes);
+    let current = reader.bytes(MAGIC.len())? == MAGIC
+        && reader.u32()? == VERSION
+        && reader.str()? == env!("CARGO_PKG_VERSION")
+        && reader.u32()? == std::mem::size_of::<Vertex>() as u32
+        && reader.u64()? == key;
+    if !current {
+        return None;
+    }
+
+    let libraries = (0..reader.u32()?)
+        .map(|_| {
+            let library = PathBuf::from(reader.str()?);
+            let hash = reader.option(Reader::u64)?;
+            (hash == hash_file(&library)).then_some(library)
+        })
+        .collect::<Option<Vec<_>>>()?;
+
+    let materials = (0..reader.u32()?)
+        .map(|_| read_material(&mut reader))
+        .collect::<Option<Vec<_>>>()?;
+
+    let meshes = (0..reader.u32()?)
+        .map(|_| {
+            let name = reader.str()?;
+            let material = reader.u64()? as usize;
+            let bounds = Aabb {
+                min: read_vector(&mut reader)?,
+                max: read_vector(&mut reader)?,
+            };
+
+            let vertices = reader.slice::<Vertex>()?;
+            let indices = reader.slice::<u32>()?;
+            let colors = reader.option(Reader::slice::<[f32; 4]>)?;
+            let tex_coords1 = reader.option(Reader::slice::<[f32; 2]>)?;
+            let tangents = reader.option(Reader::slice::<[f32; 4]>)?;
+
+            Some(MeshData {
+                name,
+                vertices,
+                indices,
+                // OBJ files only have faces
+                points: false,
+                colors,
+                tex_coords1,
+                tangents,
+                material,
+                bounds,
+            })
+        })
+        .collect::<Option<Vec<_>>>()?;
+
+    reader.0.is_empty().then_some(ParsedObj {
+        meshes,
+        materials,
+        libraries,
+    })
+}
+
+fn write_material(writer: &mut Writer, material: &tobj::Material) {
+    writer.str(&material.name);
+    for color in [material.ambient, material.diffuse, material.specular] {
+        for value in color {
+            writer.f32(value);
+        }
+    }
+    writer.f32(material.shininess);
+    writer.f32(material.dissolve);
+    writer.f32(material.optical_density);
+    for texture in [
+        &material.ambient_texture,
+        &material.diffuse_texture,
+        &material.specular_texture,
+        &material.normal_texture,
+        &material.shininess_texture,
+        &material.dissolve_texture,
+    ] {
+        writer.str(texture);
+    }
+    writer.option(material.illumination_model, |writer, model| {
+        writer.u32(model.into())
+    });
+
+    // Sorted, so that the same file always gives the same bytes
+    let mut parameters = material.unknown_param.iter().collect::<Vec<_>>();
+    parameters.sort();
+    writer.u32(parameters.len() as u32);
+    for (name, value) in parameters {
+        writer.str(name);
+        writer.str(value);
+    }
+}
+
+fn read_vector(reader: &mut Reader) -> Option<[f32; 3]> {
+    Some([reader.f32()?, reader.f32()?, reader.f32()?])
+}
+
+// The fields are read in the order `write_material` wrote them
+fn read_material(reader: &mut Reader) -> Option<tobj::Material> {
+    Some(tobj::Material {
+        name: reader.str()?,
+        ambient: read_vector(reader)?,
+        diffuse: read_vector(reader)?,
+        specular: read_vector(reader)?,
+        shininess: reader.f32()?,
+        dissolve: reader.f32()?,
+        optical_density: reader.f32()?,
+        ambient_texture: reader.str()?,
+        diffuse_texture: reader.str()?,
+        specular_texture: reader.str()?,
+        normal_texture: reader.str()?,
+        shininess_texture: reader.str()?,
+        dissolve_texture: reader.str()?,
+        illumination_model: reader.option(|reader| u8::try_from(reader.u32()?).ok())?,
+        unknown_param: (0..reader.u32()?)
+            .map(|_| Some((reader.str()?, reader.str()?)))
+            .collect::<Option<_>>()?,
+    })
+}
+
+/// Appends little-endian values.
+#[derive(Default)]
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.0.extend_from_slice(bytes);
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    fn f32(&mut self, value: f32) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    fn str(&mut self, value: &str) {
+        self.u32(value.len() as u32);
+        self.bytes(value.as_bytes());
+    }
+
+    /// Writes the length, then the values as they are in memory.
+    fn slice<T: bytemuck::Pod>(&mut self, values: &[T]) {
+        self.u32(values.len() as u32);
+        self.bytes(bytemuck::cast_slice(values));
+    }
+
+    fn option<T>(&mut self, value: Option<T>, write: impl FnOnce(&mut Self, T)) {
+        match value {
+            Some(value) => {
+                self.bytes(&[1]);
+                write(self, value);
+            }
+            None => self.bytes(&[0]),
+        }
+    }
+}
+
+/// Reads what a `Writer` wrote, returning `None` at the end of the bytes.
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, count: usize) -> Option<&'a [u8]> {
+        if count > self.0.len() {
+            return None;
+        }
+        let (bytes, rest) = self.0.split_at(count);
+        self.0 = rest;
+        Some(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> Option<[u8; N]> {
+        self.bytes(N)?.try_into().ok()
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        self.array().map(u32::from_le_bytes)
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        self.array().map(u64::from_le_bytes)
+    }
+
+    fn f32(&mut self) -> Option<f32> {
+        self.array().map(f32::from_le_bytes)
+    }
+
+    fn str(&mut self) -> Option<String> {
+        let length = self.u32()? as usize;
+        String::from_utf8(self.bytes(length)?.to_vec()).ok()
+    }
+
+    fn slice<T: bytemuck::Pod>(&mut self) -> Option<Vec<T>> {
+        let length = self.u32()? as usize;
+        // Checked before allocating, so that a damaged length can't ask for too much
+        let bytes = self.bytes(length.checked_mul(std::mem::size_of::<T>())?)?;
+        let mut values = vec![T::zeroed(); length];
+        bytemuck::cast_slice_mut(&mut values).copy_from_slice(bytes);
+        Some(values)
+    }
+
+    /// Reads an optional value, which is `Some(None)` when it was written as `None`.
+    fn option<T>(&mut self, read: impl FnOnce(&mut Self) -> Option<T>) -> Option<Option<T>> {
+        match self.bytes(1)? {
+            [0] => Some(None),
+            [1] => read(self).map(Some),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: u64 = 0x0123_4567_89ab_cdef;
+
+    /// A directory of its own for each test, removed when it's dropped.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!(
+                "grt-cache-test-{}-{}",
+                std::process::id(),
+                name
+            ));
+            std::fs::create_dir_all(&path).unwrap();
+            Self(path)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn parsed(libraries: Vec<PathBuf>) -> ParsedObj {
+        let vertex = |x: f32| Vertex {
+            position: [x, 1.0, 2.0],
+            tex_coords: [0.5, x],
+            normal: [0.0, 0.0, 1.0],
+        };
+        let mut mesh = MeshData::new(
+            "mesh".into(),
+            vec![vertex(0.0), vertex(1.0), vertex(2.0)],
+            vec![0, 1, 2],
+            0,
+        )
+        .with_colors(vec![[1.0, 0.5, 0.25, 1.0]; 3]);
+        mesh.tangents = Some(vec![[1.0, 0.0, 0.0, -1.0]; 3]);
+
+        let mut material = tobj::Material {
+            name: "material".into(),
+            diffuse: [0.8, 0.1, 0.1],
+            diffuse_texture: "diffuse.png".into(),
+            illumination_model: Some(2),
+            ..Default::default()
+        };
+        material.unknown_param.insert("Pr".into(), "0.5".into());
+
+        ParsedObj {
+            meshes: vec![mesh],
+            materials: vec![material],
+            libraries,
+        }
+    }
+
+    // Neither type compares, but their debug output has every field
+    fn assert_same(a: &ParsedObj, b: &ParsedObj) {
+        assert_eq!(format!("{a:?}"), format!("{b:?}"));
+    }
+
+    #[test]
+    fn reads_what_it_wrote() {
+        let original = parsed(Vec::new());
+        let read = read(&write(&original, KEY), KEY).unwrap();
+        assert_same(&read, &original);
+    }
+
+    #[test]
+    fn rejects_other_keys_and_versions() {
+        let bytes = write(&parsed(Vec::new()), KEY);
+        assert!(read(&bytes, KEY + 1).is_none());
+
+        let mut other_version = bytes.clone();
+        let offset = MAGIC.len();
+        other_version[offset..offset + 4].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        assert!(read(&other_version, KEY).is_none());
+
+        let mut other_magic = bytes;
+        other_magic[0] ^= 1;
+        assert!(read(&other_magic, KEY).is_none());
+    }
+
+    #[test]
+    fn rejects_truncated_entries_and_trailing_bytes() {
+        let mut bytes = write(&parsed(Vec::new()), KEY);
+        for length in 0..bytes.len() {
+            assert!(read(&bytes[..length], KEY).is_none(), "{length} bytes");
+        }
+        bytes.push(0);
+        assert!(read(&bytes, KEY).is_none());
+    }
+
+    #[test]
+    fn rejects_changed_libraries() {
+        let directory = TempDir::new("libraries");
+        let library = directory.0.join("materials.mtl");
+        std::fs::write(&library, "newmtl material\nKd 0.8 0.1 0.1\n").unwrap();
+
+        let bytes = write(&parsed(vec![library.clone()]), KEY);
+        assert!(read(&bytes, KEY).is_some());
+
+        std::fs::write(&library, "newmtl material\nKd 0.1 0.8 0.1\n").unwrap();
+        assert!(read(&bytes, KEY).is_none());
+
+        std::fs::remove_file(&library).unwrap();
+        assert!(read(&bytes, KEY).is_none());
+    }
+
+    #[test]
+    fn parses_only_when_the_entry_is_missing() {
+        let directory = TempDir::new("load");
+        let path = Path::new("model.obj");
+        let original = parsed(Vec::new());
+
+        let first = load_or_parse(&directory.0, path, b"v 0 0 0", || Ok(original.clone()));
+        assert_same(&first.unwrap(), &original);
+
+        let cached = load_or_parse(&directory.0, path, b"v 0 0 0", || {
+            panic!("parsed a file that was cached")
+        });
+        assert_same(&cached.unwrap(), &original);
+
+        // A change to the file replaces its entry
+        let mut parsed_again = false;
+        let changed = load_or_parse(&directory.0, path, b"v 1 0 0", || {
+            parsed_again = true;
+            Ok(original.clone())
+        });
+        assert!(changed.is_ok() && parsed_again);
+        assert_eq!(std::fs::read_dir(&directory.0).unwrap().count(), 1);
+        let cached = load_or_parse(&directory.0, path, b"v 1 0 0", || {
+            panic!("parsed a file that was cached")
+        });
+        assert!(cached.is_ok());
+    }
+
+    #[test]
+    fn removes_stale_entries_that_no_longer_parse() {
+        let directory = TempDir::new("stale");
+        let path = Path::new("model.obj");
+        load_or_parse(&directory.0, path, b"v 0 0 0", || Ok(parsed(Vec::new()))).unwrap();
+
+        let changed = load_or_parse(&directory.0, path, b"v 1 0", || {
+            Err(ImportError::Io(std::io::ErrorKind::InvalidData.into()))
+        });
+        assert!(changed.is_err());
+        assert_eq!(std::fs::read_dir(&directory.0).unwrap().count(), 0);
+    }
+}
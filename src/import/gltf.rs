@@ -0,0 +1,414 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+
+use super::{ImportError, ImportedModel, MeshData};
+use crate::{
+    components::rendering::Vertex,
+    material::{AlphaMode, PbrMaterial},
+    material_manager::{MaterialManager, SamplerOptions, Texture},
+    texture_data::ColorSpace,
+};
+
+/// Loads a glTF 2.0 file (.gltf or .glb) with its buffers and images.
+///
+/// The node hierarchy of the default scene is flattened: each mesh's vertices are moved by the
+/// transform of the node that uses it. Only triangle lists, the first two texture coordinate
+/// sets and the first colour set are supported.
+pub fn load(
+    path: &Path,
+    material_manager: &MaterialManager,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+) -> Result<ImportedModel, ImportError> {
+    let (document, buffers, images) = gltf::import(path)?;
+
+    let mut textures = TextureCache {
+        images: &images,
+        textures: HashMap::new(),
+        material_manager,
+        device,
+        queue,
+    };
+    let materials = document
+        .materials()
+        .map(|material| convert_material(&material, &mut textures))
+        .collect::<Vec<_>>();
+
+    let mut meshes = Vec::new();
+    let nodes = match document
+        .default_scene()
+        .or_else(|| document.scenes().next())
+    {
+        Some(scene) => scene.nodes().collect::<Vec<_>>(),
+        None => document.nodes().collect(),
+    };
+    let identity = cgmath::Matrix4::from_scale(1.0);
+    for node in nodes {
+        add_node(&node, identity, &buffers, materials.len(), &mut meshes)?;
+    }
+
+    Ok(ImportedModel { meshes, materials })
+}
+
+fn add_node(
+    node: &gltf::Node,
+    parent: cgmath::Matrix4<f32>,
+    buffers: &[gltf::buffer::Data],
+    material_count: usize,
+    meshes: &mut Vec<MeshData>,
+) -> Result<(), ImportError> {
+    use cgmath::{InnerSpace, Matrix, SquareMatrix, Transform};
+
+    let matrix = parent * cgmath::Matrix4::from(node.transform().matrix());
+    let normal_matrix = matrix
+        .invert()
+        .unwrap_or_else(cgmath::Matrix4::identity)
+        .transpose();
+    // A mirroring transform turns the triangles inside out unless their winding is reversed too
+    let is_mirrored = matrix.determinant() < 0.0;
+    let handedness = if is_mirrored { -1.0 } else { 1.0 };
+
+    if let Some(mesh) = node.mesh() {
+        for (index, primitive) in mesh.primitives().enumerate() {
+            if primitive.mode() != gltf::mesh::Mode::Triangles {
+                log::warn!(
+                    "Skipping {:?} primitive of mesh {:?}",
+                    primitive.mode(),
+                    mesh.name()
+                );
+                continue;
+            }
+
+            let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+            let Some(positions) = reader.read_positions() else {
+                continue;
+            };
+
+            let mut vertices = positions
+                .map(|position| Vertex {
+                    position: matrix
+                        .transform_point(cgmath::Point3::from(position))
+                        .into(),
+                    tex_coords: [0.0, 0.0],
+                    normal: [0.0, 0.0, 0.0],
+                })
+                .collect::<Vec<_>>();
+
+            if let Some(tex_coords) = reader.read_tex_coords(0) {
+                for (vertex, tex_coords) in vertices.iter_mut().zip(tex_coords.into_f32()) {
+                    vertex.tex_coords = tex_coords;
+                }
+            }
+            let tex_coords1 = reader
+                .read_tex_coords(1)
+                .map(|tex_coords| tex_coords.into_f32().collect::<Vec<_>>());
+            let colors = reader
+                .read_colors(0)
+                .map(|colors| colors.into_rgba_f32().collect::<Vec<_>>());
+            let tangents = reader.read_tangents().map(|tangents| {
+                tangents
+                    .map(|[x, y, z, w]| {
+                        let tangent = matrix.transform_vector(cgmath::Vector3::new(x, y, z));
+                        [tangent.x, tangent.y, tangent.z, w * handedness]
+                    })
+                    .collect::<Vec<_>>()
+            });
+
+            let name = format!("{}/{}", mesh.name().unwrap_or("mesh"), index);
+            let mut indices = match reader.read_indices() {
+                Some(indices) => indices.into_u32().collect(),
+                None => (0..vertices.len() as u32).collect::<Vec<_>>(),
+            };
+            if let Some(&index) = indices
+                .iter()
+                .find(|&&index| index as usize >= vertices.len())
+            {
+                return Err(ImportError::InvalidIndex {
+                    mesh: name,
+                    index,
+                    vertices: vertices.len(),
+                });
+            }
+            if is_mirrored {
+                for triangle in indices.chunks_exact_mut(3) {
+                    triangle.swap(1, 2);
+                }
+            }
+
+            match reader.read_normals() {
+                Some(normals) => {
+                    for (vertex, normal) in vertices.iter_mut().zip(normals) {
+                        vertex.normal = normal_matrix
+                            .transform_vector(cgmath::Vector3::from(normal))
+                            .normalize()
+                            .into();
+                    }
+                }
+                None => super::compute_normals(&mut vertices, &indices),
+            }
+
+            let mut data = MeshData::new(
+                name,
+                vertices,
+                indices,
+                primitive.material().index().unwrap_or(material_count),
+            );
+            data.colors = colors;
+            data.tex_coords1 = tex_coords1;
+            data.tangents = tangents;
+            meshes.push(data);
+        }
+    }
+
+    for child in node.children() {
+        add_node(&child, matrix, buffers, material_count, meshes)?;
+    }
+
+    Ok(())
+}
+
+fn convert_material(material: &gltf::Material, textures: &mut TextureCache) -> PbrMaterial {
+    let pbr = material.pbr_metallic_roughness();
+    let metallic_roughness = pbr
+        .metallic_roughness_texture()
+        .and_then(|info| textures.get(&info.texture(), ColorSpace::Linear));
+
+    PbrMaterial {
+        name: material.name().unwrap_or("material").to_string(),
+        base_color: pbr.base_color_factor(),
+        metallic: pbr.metallic_factor(),
+        roughness: pbr.roughness_factor(),
+        emissive: material.emissive_factor(),
+        normal_scale: material.normal_texture().map_or(1.0, |n| n.scale()),
+        occlusion_strength: material.occlusion_texture().map_or(1.0, |o| o.strength()),
+        occlusion_tex_coord: material.occlusion_texture().map_or(0, |o| o.tex_coord()),
+        alpha_mode: match material.alpha_mode() {
+            gltf::material::AlphaMode::Opaque => AlphaMode::Opaque,
+            gltf::material::AlphaMode::Mask => AlphaMode::Cutout {
+                cutoff: material.alpha_cutoff().unwrap_or(0.5),
+            },
+            gltf::material::AlphaMode::Blend => AlphaMode::Blend,
+        },
+        base_color_texture: pbr
+            .base_color_texture()
+            .and_then(|info| textures.get(&info.texture(), ColorSpace::Srgb)),
+        metallic_texture: metallic_roughness.clone(),
+        roughness_texture: metallic_roughness,
+        normal_texture: material
+            .normal_texture()
+            .and_then(|normal| textures.get(&normal.texture(), ColorSpace::Linear)),
+        occlusion_texture: material
+            .occlusion_texture()
+            .and_then(|occlusion| textures.get(&occlusion.texture(), ColorSpace::Linear)),
+        emissive_texture: material
+            .emissive_texture()
+            .and_then(|info| textures.get(&info.texture(), ColorSpace::Srgb)),
+    }
+}
+
+/// Uploads each glTF texture once per colour space it's used in.
+struct TextureCache<'a> {
+    images: &'a [gltf::image::Data],
+    textures: HashMap<(usize, ColorSpace), Arc<Texture>>,
+    material_manager: &'a MaterialManager,
+    device: &'a wgpu::Device,
+    queue: &'a wgpu::Queue,
+}
+
+impl TextureCache<'_> {
+    fn get(&mut self, texture: &gltf::Texture, color_space: ColorSpace) -> Option<Arc<Texture>> {
+        let key = (texture.index(), color_space);
+        if let Some(texture) = self.textures.get(&key) {
+            return Some(texture.clone());
+        }
+
+        let source = texture.source();
+        let uploaded = match to_image(&self.images[source.index()]) {
+            Some(image) => Arc::new(self.material_manager.add_texture(
+                &image,
+                &source.name().unwrap_or("glTF texture").to_string(),
+                &sampler_options(&texture.sampler()),
+                color_space,
+                self.device,
+                self.queue,
+            )),
+            None => {
+                log::error!("Couldn't convert glTF image {}", source.index());
+                self.material_manager.missing_texture()
+            }
+        };
+
+        self.textures.insert(key, uploaded.clone());
+        Some(uploaded)
+    }
+}
+
+fn to_image(data: &gltf::image::Data) -> Option<image::DynamicImage> {
+    use gltf::image::Format;
+    use image::{DynamicImage, ImageBuffer};
+
+    let (width, height) = (data.width, data.height);
+    let pixels = data.pixels.clone();
+    let wide = || bytemuck::pod_collect_to_vec::<u8, u16>(&data.pixels);
+    let float = || bytemuck::pod_collect_to_vec::<u8, f32>(&data.pixels);
+
+    Some(match data.format {
+        Format::R8 => DynamicImage::ImageLuma8(ImageBuffer::from_raw(width, height, pixels)?),
+        Format::R8G8 => DynamicImage::ImageLumaA8(ImageBuffer::from_raw(width, height, pixels)?),
+        Format::R8G8B8 => DynamicImage::ImageRgb8(ImageBuffer::from_raw(width, height, pixels)?),
+        Format::R8G8B8A8 => DynamicImage::ImageRgba8(ImageBuffer::from_raw(width, height, pixels)?),
+        Format::R16 => DynamicImage::ImageLuma16(ImageBuffer::from_raw(width, height, wide())?),
+        Format::R16G16 => DynamicImage::ImageLumaA16(ImageBuffer::from_raw(width, height, wide())?),
+        Format::R16G16B16 => {
+            DynamicImage::ImageRgb16(ImageBuffer::from_raw(width, height, wide())?)
+        }
+        Format::R16G16B16A16 => {
+            DynamicImage::ImageRgba16(ImageBuffer::from_raw(width, height, wide())?)
+        }
+        Format::R32G32B32FLOAT => {
+            DynamicImage::ImageRgb32F(ImageBuffer::from_raw(width, height, float())?)
+        }
+        Format::R32G32B32A32FLOAT => {
+            DynamicImage::ImageRgba32F(ImageBuffer::from_raw(width, height, float())?)
+        }
+    })
+}
+
+fn sampler_options(sampler: &gltf::texture::Sampler) -> SamplerOptions {
+    use gltf::texture::{MagFilter, MinFilter, WrappingMode};
+
+    let filter = |linear| {
+        if linear {
+            wgpu::FilterMode::Linear
+        } else {
+            wgpu::FilterMode::Nearest
+        }
+    };
+    let (min_linear, mipmap_linear) = match sampler.min_filter() {
+        Some(MinFilter::Nearest) => (false, false),
+        Some(MinFilter::NearestMipmapNearest) => (false, false),
+        Some(MinFilter::NearestMipmapLinear) => (false, true),
+        Some(MinFilter::LinearMipmapNearest) => (true, false),
+        Some(MinFilter::Linear) | Some(MinFilter::LinearMipmapLinear) | None => (true, true),
+    };
+
+    SamplerOptions {
+        address_mode: match sampler.wrap_s() {
+            WrappingMode::ClampToEdge => wgpu::AddressMode::ClampToEdge,
+            WrappingMode::MirroredRepeat => wgpu::AddressMode::MirrorRepeat,
+            WrappingMode::Repeat => wgpu::AddressMode::Repeat,
+        },
+        mag_filter: filter(sampler.mag_filter() != Some(MagFilter::Nearest)),
+        min_filter: filter(min_linear),
+        mipmap_filter: filter(mipmap_linear),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{InnerSpace, Vector3};
+
+    use super::*;
+
+    /// A GLB file with one node, scaled by `scale`, using a mesh of one primitive without normals.
+    fn glb(positions: &[[f32; 3]], indices: &[u16], scale: [f32; 3]) -> Vec<u8> {
+        let mut buffer = bytemuck::cast_slice::<_, u8>(positions).to_vec();
+        buffer.extend_from_slice(bytemuck::cast_slice(indices));
+        buffer.resize(buffer.len().next_multiple_of(4), 0);
+
+        let min = [0, 1, 2].map(|i| positions.iter().map(|p| p[i]).fold(f32::MAX, f32::min));
+        let max = [0, 1, 2].map(|i| positions.iter().map(|p| p[i]).fold(f32::MIN, f32::max));
+        let json = format!(
+            r#"{{
+                "asset": {{"version": "2.0"}},
+                "scene": 0,
+                "scenes": [{{"nodes": [0]}}],
+                "nodes": [{{"mesh": 0, "scale": {scale:?}}}],
+                "meshes": [{{"primitives": [{{"attributes": {{"POSITION": 0}}, "indices": 1}}]}}],
+                "buffers": [{{"byteLength": {}}}],
+                "bufferViews": [
+                    {{"buffer": 0, "byteLength": {}}},
+                    {{"buffer": 0, "byteOffset": {}, "byteLength": {}}}
+                ],
+                "accessors": [
+                    {{"bufferView": 0, "componentType": 5126, "count": {}, "type": "VEC3",
+                      "min": {min:?}, "max": {max:?}}},
+                    {{"bufferView": 1, "componentType": 5123, "count": {}, "type": "SCALAR"}}
+                ]
+            }}"#,
+            buffer.len(),
+            positions.len() * 12,
+            positions.len() * 12,
+            indices.len() * 2,
+            positions.len(),
+            indices.len(),
+        );
+        let mut json = json.into_bytes();
+        json.resize(json.len().next_multiple_of(4), b' ');
+
+        let mut glb = Vec::new();
+        glb.extend_from_slice(b"glTF");
+        glb.extend_from_slice(&2u32.to_le_bytes());
+        glb.extend_from_slice(&((12 + 8 + json.len() + 8 + buffer.len()) as u32).to_le_bytes());
+        for (chunk, kind) in [(&json, b"JSON"), (&buffer, b"BIN\0")] {
+            glb.extend_from_slice(&(chunk.len() as u32).to_le_bytes());
+            glb.extend_from_slice(kind);
+            glb.extend_from_slice(chunk);
+        }
+        glb
+    }
+
+    fn import(glb: &[u8]) -> Result<Vec<MeshData>, ImportError> {
+        let (document, buffers, _) = gltf::import_slice(glb).unwrap();
+        let mut meshes = Vec::new();
+        for node in document.default_scene().unwrap().nodes() {
+            add_node(
+                &node,
+                cgmath::Matrix4::from_scale(1.0),
+                &buffers,
+                0,
+                &mut meshes,
+            )?;
+        }
+        Ok(meshes)
+    }
+
+    /// The normal of the first triangle, from its winding.
+    fn face_normal(mesh: &MeshData) -> Vector3<f32> {
+        let [a, b, c] =
+            [0, 1, 2].map(|i| Vector3::from(mesh.vertices[mesh.indices[i] as usize].position));
+        (b - a).cross(c - a).normalize()
+    }
+
+    const TRIANGLE: [[f32; 3]; 3] = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]];
+
+    #[test]
+    fn out_of_range_indices_are_rejected() {
+        let result = import(&glb(&TRIANGLE, &[0, 1, 3], [1.0, 1.0, 1.0]));
+        assert!(matches!(
+            result,
+            Err(ImportError::InvalidIndex {
+                index: 3,
+                vertices: 3,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn mirrored_nodes_keep_their_triangles_facing_out() {
+        for scale in [[1.0, 1.0, 1.0], [-1.0, 1.0, 1.0], [-1.0, -1.0, 1.0]] {
+            let meshes = import(&glb(&TRIANGLE, &[0, 1, 2], scale)).unwrap();
+            let mesh = &meshes[0];
+            assert!(
+                (face_normal(mesh) - Vector3::unit_z()).magnitude() < 1e-5,
+                "{scale:?}"
+            );
+            for vertex in &mesh.vertices {
+                assert_eq!(vertex.normal, [0.0, 0.0, 1.0], "{scale:?}");
+            }
+        }
+    }
+}
@@ -0,0 +1,291 @@
+//! Loading models from files into meshes and materials, ready to be uploaded.
+
+pub mod cache;
+pub mod gltf;
+pub mod obj;
+pub mod optimize;
+pub mod ply;
+pub mod simplify;
+pub mod stl;
+
+use std::path::{Path, PathBuf};
+
+use crate::{
+    assets::AssetPaths, components::rendering::Vertex, culling::Aabb, material::PbrMaterial,
+    material_manager::MaterialManager,
+};
+
+/// A mesh read from a model file, before it's uploaded.
+#[derive(Debug, Clone, Default)]
+pub struct MeshData {
+    pub name: String,
+    pub vertices: Vec<Vertex>,
+    /// Triangle list indices into `vertices`, or the vertices to draw when `points` is set.
+    pub indices: Vec<u32>,
+    /// Drawn as a point at each index instead of as triangles, for point clouds.
+    pub points: bool,
+    /// An RGBA colour for each vertex, multiplying the material's base colour.
+    pub colors: Option<Vec<[f32; 4]>>,
+    /// A second set of texture coordinates for each vertex.
+    pub tex_coords1: Option<Vec<[f32; 2]>>,
+    /// A tangent for each vertex, with the sign of its bitangent in w.
+    pub tangents: Option<Vec<[f32; 4]>>,
+    /// Index into the model's materials.
+    pub material: usize,
+    pub bounds: Aabb,
+}
+
+impl MeshData {
+    pub fn new(name: String, vertices: Vec<Vertex>, indices: Vec<u32>, material: usize) -> Self {
+        let bounds = Aabb::from_points(vertices.iter().map(|vertex| vertex.position));
+        Self {
+            name,
+            vertices,
+            indices,
+            points: false,
+            colors: None,
+            tex_coords1: None,
+            tangents: None,
+            material,
+            bounds,
+        }
+    }
+
+    pub fn with_colors(mut self, colors: Vec<[f32; 4]>) -> Self {
+        self.colors = Some(colors);
+        self
+    }
+
+    /// Replaces the vertices with the ones at `kept`, in that order, keeping their optional
+    /// attributes. The indices are left for the caller to renumber.
+    pub fn select_vertices(&mut self, kept: &[u32]) {
+        fn select<T: Copy>(values: &mut Vec<T>, kept: &[u32]) {
+            *values = kept.iter().map(|&i| values[i as usize]).collect();
+        }
+
+        select(&mut self.vertices, kept);
+        if let Some(colors) = &mut self.colors {
+            select(colors, kept);
+        }
+        if let Some(tex_coords1) = &mut self.tex_coords1 {
+            select(tex_coords1, kept);
+        }
+        if let Some(tangents) = &mut self.tangents {
+            select(tangents, kept);
+        }
+    }
+}
+
+/// How models are read and prepared, kept as a resource for the `ModelBuilderSystem`.
+#[derive(Debug, Clone, Default)]
+pub struct ImportSettings {
+    /// Runs `optimize::optimize` on every mesh.
+    pub optimize_meshes: bool,
+    /// Where parsed OBJ files are cached, or `None` to parse them every time.
+    pub cache_directory: Option<PathBuf>,
+}
+
+/// The meshes and materials of a model file. Every mesh's material index is valid.
+#[derive(Default)]
+pub struct ImportedModel {
+    pub meshes: Vec<MeshData>,
+    pub materials: Vec<PbrMaterial>,
+}
+
+#[derive(Debug)]
+pub enum ImportError {
+    Io(std::io::Error),
+    Obj(tobj::LoadError),
+    Gltf(::gltf::Error),
+    Ply(String),
+    Stl(String),
+    /// A mesh refers to a vertex it doesn't have.
+    InvalidIndex {
+        mesh: String,
+        index: u32,
+        vertices: usize,
+    },
+    UnsupportedFormat(String),
+}
+
+impl std::fmt::Display for ImportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ImportError::Io(error) => write!(f, "Couldn't read model file: {error}"),
+            ImportError::Obj(error) => write!(f, "Couldn't parse OBJ file: {error}"),
+            ImportError::Gltf(error) => write!(f, "Couldn't load glTF file: {error}"),
+            ImportError::Ply(error) => write!(f, "Couldn't parse PLY file: {error}"),
+            ImportError::Stl(error) => write!(f, "Couldn't parse STL file: {error}"),
+            ImportError::InvalidIndex {
+                mesh,
+                index,
+                vertices,
+            } => write!(
+                f,
+                "Mesh {mesh:?} has vertex index {index}, but only {vertices} vertices"
+            ),
+            ImportError::UnsupportedFormat(extension) => {
+                write!(f, "Unsupported model format {extension:?}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ImportError {}
+
+impl From<std::io::Error> for ImportError {
+    fn from(error: std::io::Error) -> Self {
+        ImportError::Io(error)
+    }
+}
+
+impl From<tobj::LoadError> for ImportError {
+    fn from(error: tobj::LoadError) -> Self {
+        ImportError::Obj(error)
+    }
+}
+
+impl From<::gltf::Error> for ImportError {
+    fn from(error: ::gltf::Error) -> Self {
+        ImportError::Gltf(error)
+    }
+}
+
+/// Loads a model file, choosing the importer by its extension. Textures are uploaded as the
+/// materials are read.
+pub fn load(
+    path: &Path,
+    asset_paths: &AssetPaths,
+    settings: &ImportSettings,
+    material_manager: &MaterialManager,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+) -> Result<ImportedModel, ImportError> {
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(str::to_ascii_lowercase)
+        .unwrap_or_default();
+
+    let mut model = match extension.as_str() {
+        "obj" => obj::load(
+            path,
+            asset_paths,
+            settings.cache_directory.as_deref(),
+            material_manager,
+            device,
+            queue,
+        )?,
+        "gltf" | "glb" => gltf::load(path, material_manager, device, queue)?,
+        "ply" => ply::load(path)?,
+        "stl" => stl::load(path)?,
+        _ => return Err(ImportError::UnsupportedFormat(extension)),
+    };
+
+    // Meshes without a usable material share a default one
+    let material_count = model.materials.len();
+    if model
+        .meshes
+        .iter()
+        .any(|mesh| mesh.material >= material_count)
+    {
+        model.materials.push(PbrMaterial::default());
+        for mesh in model.meshes.iter_mut() {
+            if mesh.material >= material_count {
+                mesh.material = material_count;
+            }
+        }
+    }
+
+    if settings.optimize_meshes {
+        model
+            .meshes
+            .iter_mut()
+            .filter(|mesh| !mesh.points)
+            .for_each(optimize::optimize);
+    }
+
+    Ok(model)
+}
+
+/// Gives every vertex the average normal of the triangles around it, for files without normals.
+pub fn compute_normals(vertices: &mut [Vertex], indices: &[u32]) {
+    use cgmath::InnerSpace;
+
+    let mut normals = vec![cgmath::Vector3::new(0.0, 0.0, 0.0); vertices.len()];
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] =
+            [0, 1, 2].map(|i| cgmath::Vector3::from(vertices[triangle[i] as usize].position));
+        // Not normalised, so that larger triangles weigh more
+        let normal = (b - a).cross(c - a);
+        for &index in triangle {
+            normals[index as usize] += normal;
+        }
+    }
+
+    for (vertex, normal) in vertices.iter_mut().zip(normals) {
+        vertex.normal = if normal.magnitude2() > 0.0 {
+            normal.normalize().into()
+        } else {
+            [0.0, 0.0, 1.0]
+        };
+    }
+}
+
+/// Gives every vertex a tangent along which its first texture coordinate increases, with the
+/// sign of the bitangent, along which the second increases, in w. After Lengyel's method, with
+/// each vertex averaging the triangles around it.
+pub fn compute_tangents(vertices: &[Vertex], indices: &[u32]) -> Vec<[f32; 4]> {
+    use cgmath::{InnerSpace, Vector3};
+
+    let zero = Vector3::new(0.0, 0.0, 0.0);
+    let mut tangents = vec![zero; vertices.len()];
+    let mut bitangents = vec![zero; vertices.len()];
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [0, 1, 2].map(|i| &vertices[triangle[i] as usize]);
+        let edges = [b, c].map(|v| Vector3::from(v.position) - Vector3::from(a.position));
+        let [(du1, dv1), (du2, dv2)] = [b, c].map(|v| {
+            (
+                v.tex_coords[0] - a.tex_coords[0],
+                v.tex_coords[1] - a.tex_coords[1],
+            )
+        });
+
+        let determinant = du1 * dv2 - du2 * dv1;
+        if determinant.abs() < f32::EPSILON {
+            continue;
+        }
+        let tangent = (edges[0] * dv2 - edges[1] * dv1) / determinant;
+        let bitangent = (edges[1] * du1 - edges[0] * du2) / determinant;
+        for &index in triangle {
+            tangents[index as usize] += tangent;
+            bitangents[index as usize] += bitangent;
+        }
+    }
+
+    vertices
+        .iter()
+        .zip(tangents.into_iter().zip(bitangents))
+        .map(|(vertex, (tangent, bitangent))| {
+            let normal = Vector3::from(vertex.normal);
+            // Made perpendicular to the normal, or any perpendicular where the texture
+            // coordinates don't say
+            let mut tangent = tangent - normal * normal.dot(tangent);
+            if tangent.magnitude2() < 1e-12 {
+                let axis = if normal.x.abs() < 0.9 {
+                    Vector3::unit_x()
+                } else {
+                    Vector3::unit_y()
+                };
+                tangent = axis - normal * normal.dot(axis);
+            }
+            let tangent = tangent.normalize();
+            let sign = if normal.cross(tangent).dot(bitangent) < 0.0 {
+                -1.0
+            } else {
+                1.0
+            };
+            [tangent.x, tangent.y, tangent.z, sign]
+        })
+        .collect()
+}
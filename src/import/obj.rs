@@ -0,0 +1,146 @@
+use std::{
+    cell::RefCell,
+    path::{Path, PathBuf},
+};
+
+use super::{cache, ImportError, ImportedModel, MeshData};
+use crate::{
+    assets::AssetPaths, components::rendering::Vertex, material::PbrMaterial,
+    material_manager::MaterialManager,
+};
+
+/// An OBJ file as it's parsed, before the textures of its materials are loaded. This is what the
+/// mesh cache stores.
+#[derive(Debug, Clone, Default)]
+pub struct ParsedObj {
+    pub meshes: Vec<MeshData>,
+    pub materials: Vec<tobj::Material>,
+    /// The MTL libraries the file named, so that the cache notices when they change.
+    pub libraries: Vec<PathBuf>,
+}
+
+/// Loads a Wavefront OBJ file and the MTL libraries it names, relative to the OBJ file. With a
+/// `cache_directory`, the parsed file is read from there when neither it nor its libraries have
+/// changed, and written there otherwise.
+pub fn load(
+    path: &Path,
+    asset_paths: &AssetPaths,
+    cache_directory: Option<&Path>,
+    material_manager: &MaterialManager,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+) -> Result<ImportedModel, ImportError> {
+    let source = std::fs::read(path)?;
+    let parsed = match cache_directory {
+        Some(directory) => cache::load_or_parse(directory, path, &source, || {
+            parse(&source, path, asset_paths)
+        })?,
+        None => parse(&source, path, asset_paths)?,
+    };
+
+    let materials = parsed
+        .materials
+        .iter()
+        .map(|material| {
+            PbrMaterial::from_mtl(material, path, asset_paths, material_manager, device, queue)
+        })
+        .collect();
+
+    Ok(ImportedModel {
+        meshes: parsed.meshes,
+        materials,
+    })
+}
+
+/// Parses the OBJ file at `path`, whose contents are `source`, and the MTL libraries it names.
+pub fn parse(
+    source: &[u8],
+    path: &Path,
+    asset_paths: &AssetPaths,
+) -> Result<ParsedObj, ImportError> {
+    let mut object_reader = std::io::BufReader::new(source);
+    let libraries = RefCell::new(Vec::new());
+
+    let (imported_meshes, imported_materials) = tobj::load_obj_buf(
+        &mut object_reader,
+        &tobj::LoadOptions {
+            triangulate: true,
+            single_index: true,
+            ..Default::default()
+        },
+        |p| {
+            let material_path = asset_paths.resolve(&p.to_string_lossy(), Some(path));
+            libraries.borrow_mut().push(material_path.clone());
+            let material_text = std::fs::read_to_string(&material_path).map_err(|error| {
+                log::error!("{:?}: {}", material_path, error);
+                tobj::LoadError::OpenFileFailed
+            })?;
+            let material_cursor = std::io::Cursor::new(material_text);
+            let mut material_reader = std::io::BufReader::new(material_cursor);
+
+            tobj::load_mtl_buf(&mut material_reader)
+        },
+    )?;
+
+    let materials = match imported_materials {
+        Ok(materials) => materials,
+        Err(error) => {
+            log::warn!("Couldn't load the materials of {:?}: {}", path, error);
+            Vec::new()
+        }
+    };
+
+    let meshes = imported_meshes
+        .into_iter()
+        .map(|m| {
+            let mesh = m.mesh;
+            let mut vertices = (0..mesh.positions.len() / 3)
+                .map(|i| Vertex {
+                    position: [
+                        mesh.positions[i * 3],
+                        mesh.positions[i * 3 + 1],
+                        mesh.positions[i * 3 + 2],
+                    ],
+                    tex_coords: if mesh.texcoords.is_empty() {
+                        [0.0, 0.0]
+                    } else {
+                        [mesh.texcoords[i * 2], mesh.texcoords[i * 2 + 1]]
+                    },
+                    normal: if mesh.normals.is_empty() {
+                        [0.0, 0.0, 0.0]
+                    } else {
+                        [
+                            mesh.normals[i * 3],
+                            mesh.normals[i * 3 + 1],
+                            mesh.normals[i * 3 + 2],
+                        ]
+                    },
+                })
+                .collect::<Vec<_>>();
+
+            if mesh.normals.is_empty() {
+                super::compute_normals(&mut vertices, &mesh.indices);
+            }
+
+            // Colours are written after a vertex's position, as `v x y z r g b`
+            let colors = (!mesh.vertex_color.is_empty()).then(|| {
+                mesh.vertex_color
+                    .chunks_exact(3)
+                    .map(|color| [color[0], color[1], color[2], 1.0])
+                    .collect()
+            });
+
+            // Meshes without a material get the default one
+            let material = mesh.material_id.unwrap_or(usize::MAX);
+            let mut data = MeshData::new(m.name, vertices, mesh.indices, material);
+            data.colors = colors;
+            data
+        })
+        .collect();
+
+    Ok(ParsedObj {
+        meshes,
+        materials,
+        libraries: libraries.into_inner(),
+    })
+}
@@ -0,0 +1,413 @@
+//! Stanford PLY files, as written by 3D scanners.
+
+use std::path::Path;
+
+use super::{ImportError, ImportedModel, MeshData};
+use crate::components::rendering::Vertex;
+
+/// Loads an ASCII or binary PLY file's vertices, with their normals, texture coordinates and
+/// colours where the file has them, and its faces, which are split into triangles. Files
+/// without faces are point clouds, which are drawn as a point at each vertex.
+pub fn load(path: &Path) -> Result<ImportedModel, ImportError> {
+    let source = std::fs::read(path)?;
+    let name = path.file_stem().map_or_else(
+        || "ply".to_string(),
+        |stem| stem.to_string_lossy().into_owned(),
+    );
+    let mesh = parse(&source, name)?;
+
+    Ok(ImportedModel {
+        meshes: vec![mesh],
+        materials: Vec::new(),
+    })
+}
+
+fn error(message: impl Into<String>) -> ImportError {
+    ImportError::Ply(message.into())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Encoding {
+    Ascii,
+    LittleEndian,
+    BigEndian,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Scalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl Scalar {
+    fn parse(name: &str) -> Result<Self, ImportError> {
+        Ok(match name {
+            "char" | "int8" => Self::I8,
+            "uchar" | "uint8" => Self::U8,
+            "short" | "int16" => Self::I16,
+            "ushort" | "uint16" => Self::U16,
+            "int" | "int32" => Self::I32,
+            "uint" | "uint32" => Self::U32,
+            "float" | "float32" => Self::F32,
+            "double" | "float64" => Self::F64,
+            _ => return Err(error(format!("unknown property type {name:?}"))),
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Self::I8 | Self::U8 => 1,
+            Self::I16 | Self::U16 => 2,
+            Self::I32 | Self::U32 | Self::F32 => 4,
+            Self::F64 => 8,
+        }
+    }
+
+    /// The value that integer colours are divided by to bring them into 0 to 1.
+    fn color_scale(self) -> f64 {
+        match self {
+            Self::U8 => u8::MAX.into(),
+            Self::U16 => u16::MAX.into(),
+            _ => 1.0,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Property {
+    name: String,
+    value: Scalar,
+    /// The type of the length that precedes list properties' values.
+    list: Option<Scalar>,
+}
+
+#[derive(Debug)]
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+impl Element {
+    fn property(&self, names: &[&str]) -> Option<usize> {
+        self.properties
+            .iter()
+            .position(|property| names.contains(&property.name.as_str()))
+    }
+}
+
+/// Reads the header's lines up to `end_header`, returning the encoding, the elements, and the
+/// bytes after the header.
+fn parse_header(source: &[u8]) -> Result<(Encoding, Vec<Element>, &[u8]), ImportError> {
+    let mut rest = source;
+    let mut next_line = || -> Result<String, ImportError> {
+        let end = rest
+            .iter()
+            .position(|&byte| byte == b'\n')
+            .ok_or_else(|| error("the header has no end_header"))?;
+        let line = String::from_utf8_lossy(&rest[..end]).trim().to_string();
+        rest = &rest[end + 1..];
+        Ok(line)
+    };
+
+    if next_line()? != "ply" {
+        return Err(error("not a PLY file"));
+    }
+
+    let mut encoding = None;
+    let mut elements = Vec::<Element>::new();
+    loop {
+        let line = next_line()?;
+        let words = line.split_whitespace().collect::<Vec<_>>();
+        match words.as_slice() {
+            ["end_header"] => break,
+            ["comment" | "obj_info", ..] | [] => {}
+            ["format", format, _version] => {
+                encoding = Some(match *format {
+                    "ascii" => Encoding::Ascii,
+                    "binary_little_endian" => Encoding::LittleEndian,
+                    "binary_big_endian" => Encoding::BigEndian,
+                    _ => return Err(error(format!("unknown format {format:?}"))),
+                });
+            }
+            ["element", name, count] => elements.push(Element {
+                name: name.to_string(),
+                count: count
+                    .parse()
+                    .map_err(|_| error(format!("bad count of element {name:?}")))?,
+                properties: Vec::new(),
+            }),
+            ["property", "list", length, value, name] => elements
+                .last_mut()
+                .ok_or_else(|| error("property before any element"))?
+                .properties
+                .push(Property {
+                    name: name.to_string(),
+                    value: Scalar::parse(value)?,
+                    list: Some(Scalar::parse(length)?),
+                }),
+            ["property", value, name] => elements
+                .last_mut()
+                .ok_or_else(|| error("property before any element"))?
+                .properties
+                .push(Property {
+                    name: name.to_string(),
+                    value: Scalar::parse(value)?,
+                    list: None,
+                }),
+            _ => return Err(error(format!("unknown header line {line:?}"))),
+        }
+    }
+
+    let encoding = encoding.ok_or_else(|| error("the header has no format"))?;
+    Ok((encoding, elements, rest))
+}
+
+/// Reads the values of the elements after the header, one at a time.
+enum Values<'a> {
+    Ascii(std::str::SplitAsciiWhitespace<'a>),
+    Binary { bytes: &'a [u8], big_endian: bool },
+}
+
+impl Values<'_> {
+    fn read(&mut self, scalar: Scalar) -> Result<f64, ImportError> {
+        match self {
+            Values::Ascii(words) => words
+                .next()
+                .ok_or_else(|| error("the file ends early"))?
+                .parse()
+                .map_err(|_| error("bad number")),
+            Values::Binary { bytes, big_endian } => {
+                if bytes.len() < scalar.size() {
+                    return Err(error("the file ends early"));
+                }
+                let (value, rest) = std::mem::take(bytes).split_at(scalar.size());
+                *bytes = rest;
+
+                macro_rules! decode {
+                    ($type:ty) => {{
+                        let value = value.try_into().unwrap();
+                        if *big_endian {
+                            <$type>::from_be_bytes(value) as f64
+                        } else {
+                            <$type>::from_le_bytes(value) as f64
+                        }
+                    }};
+                }
+                Ok(match scalar {
+                    Scalar::I8 => decode!(i8),
+                    Scalar::U8 => decode!(u8),
+                    Scalar::I16 => decode!(i16),
+                    Scalar::U16 => decode!(u16),
+                    Scalar::I32 => decode!(i32),
+                    Scalar::U32 => decode!(u32),
+                    Scalar::F32 => decode!(f32),
+                    Scalar::F64 => decode!(f64),
+                })
+            }
+        }
+    }
+
+    /// Reads an element's properties into `values`, with the values of list properties after
+    /// each other, and where each property's values start into `starts`.
+    fn read_element(
+        &mut self,
+        element: &Element,
+        values: &mut Vec<f64>,
+        starts: &mut Vec<usize>,
+    ) -> Result<(), ImportError> {
+        values.clear();
+        starts.clear();
+        for property in &element.properties {
+            starts.push(values.len());
+            let count = match property.list {
+                Some(length) => self.read(length)? as usize,
+                None => 1,
+            };
+            for _ in 0..count {
+                values.push(self.read(property.value)?);
+            }
+        }
+        starts.push(values.len());
+        Ok(())
+    }
+}
+
+fn parse(source: &[u8], name: String) -> Result<MeshData, ImportError> {
+    let (encoding, elements, body) = parse_header(source)?;
+    let mut values = match encoding {
+        Encoding::Ascii => Values::Ascii(
+            std::str::from_utf8(body)
+                .map_err(|_| error("an ASCII file has binary data"))?
+                .split_ascii_whitespace(),
+        ),
+        _ => Values::Binary {
+            bytes: body,
+            big_endian: encoding == Encoding::BigEndian,
+        },
+    };
+
+    let mut vertices = Vec::new();
+    let mut colors = Vec::new();
+    let mut indices = Vec::new();
+    let mut has_normals = false;
+    let (mut element_values, mut starts) = (Vec::new(), Vec::new());
+
+    for element in &elements {
+        match element.name.as_str() {
+            "vertex" => {
+                let find = |names: &[&str]| element.property(names);
+                let position = [find(&["x"]), find(&["y"]), find(&["z"])];
+                let normal = [find(&["nx"]), find(&["ny"]), find(&["nz"])];
+                let tex_coords = [
+                    find(&["s", "u", "texture_s", "texture_u"]),
+                    find(&["t", "v", "texture_t", "texture_v"]),
+                ];
+                let color = [
+                    find(&["red", "diffuse_red", "r"]),
+                    find(&["green", "diffuse_green", "g"]),
+                    find(&["blue", "diffuse_blue", "b"]),
+                    find(&["alpha", "a"]),
+                ];
+                if position.contains(&None) {
+                    return Err(error("vertices have no position"));
+                }
+                has_normals = !normal.contains(&None);
+                let has_colors = color[..3].iter().all(Option::is_some);
+
+                // Not reserved from the count, which a damaged header can make huge
+                for _ in 0..element.count {
+                    values.read_element(element, &mut element_values, &mut starts)?;
+                    let value = |property: Option<usize>, default: f64| {
+                        property.map_or(default, |property| element_values[starts[property]])
+                    };
+
+                    vertices.push(Vertex {
+                        position: position.map(|p| value(p, 0.0) as f32),
+                        tex_coords: tex_coords.map(|t| value(t, 0.0) as f32),
+                        normal: normal.map(|n| value(n, 0.0) as f32),
+                    });
+                    if has_colors {
+                        colors.push(color.map(|c| {
+                            let scale =
+                                c.map_or(1.0, |c| element.properties[c].value.color_scale());
+                            (value(c, scale) / scale) as f32
+                        }));
+                    }
+                }
+            }
+            "face" => {
+                let corners = element
+                    .property(&["vertex_indices", "vertex_index"])
+                    .ok_or_else(|| error("faces have no vertex indices"))?;
+
+                for _ in 0..element.count {
+                    values.read_element(element, &mut element_values, &mut starts)?;
+                    let face = element_values[starts[corners]..starts[corners + 1]]
+                        .iter()
+                        .map(|&index| {
+                            let valid = index >= 0.0
+                                && index.fract() == 0.0
+                                && index <= f64::from(u32::MAX);
+                            valid
+                                .then_some(index as u32)
+                                .ok_or_else(|| error(format!("bad vertex index {index}")))
+                        })
+                        .collect::<Result<Vec<_>, _>>()?;
+                    // Polygons are split into a fan of triangles around their first corner
+                    for i in 1..face.len().saturating_sub(1) {
+                        indices.extend([face[0], face[i], face[i + 1]]);
+                    }
+                }
+            }
+            // Edges, materials and the like are read past
+            _ => {
+                for _ in 0..element.count {
+                    values.read_element(element, &mut element_values, &mut starts)?;
+                }
+            }
+        }
+    }
+
+    if indices
+        .iter()
+        .any(|&index| index as usize >= vertices.len())
+    {
+        return Err(error("a face uses a vertex that doesn't exist"));
+    }
+    if !has_normals {
+        super::compute_normals(&mut vertices, &indices);
+    }
+
+    let points = indices.is_empty();
+    if points {
+        indices = (0..vertices.len() as u32).collect();
+    }
+
+    let mut mesh = MeshData::new(name, vertices, indices, usize::MAX);
+    mesh.points = points;
+    Ok(if colors.is_empty() {
+        mesh
+    } else {
+        mesh.with_colors(colors)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn triangle(face: &str) -> String {
+        format!(
+            "ply\nformat ascii 1.0\nelement vertex 3\nproperty float x\nproperty float y\n\
+             property float z\nelement face 1\nproperty list uchar int vertex_indices\n\
+             end_header\n0 0 0\n1 0 0\n0 1 0\n{face}\n"
+        )
+    }
+
+    #[test]
+    fn reads_faces() {
+        let mesh = parse(triangle("3 0 1 2").as_bytes(), "ply".into()).unwrap();
+        assert_eq!(mesh.vertices.len(), 3);
+        assert_eq!(mesh.indices, [0, 1, 2]);
+    }
+
+    #[test]
+    fn point_clouds_are_drawn_as_points() {
+        let source = "ply\nformat ascii 1.0\nelement vertex 3\nproperty float x\n\
+                      property float y\nproperty float z\nend_header\n0 0 0\n1 0 0\n0 1 0\n";
+        let mesh = parse(source.as_bytes(), "ply".into()).unwrap();
+        assert!(mesh.points);
+        assert_eq!(mesh.indices, [0, 1, 2]);
+        assert!(
+            !parse(triangle("3 0 1 2").as_bytes(), "ply".into())
+                .unwrap()
+                .points
+        );
+    }
+
+    #[test]
+    fn rejects_bad_vertex_indices() {
+        for face in ["3 0 1 -1", "3 0 1 1.5", "3 0 1 3"] {
+            assert!(
+                parse(triangle(face).as_bytes(), "ply".into()).is_err(),
+                "{face}"
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_counts_past_the_end_of_the_file() {
+        let source = "ply\nformat binary_little_endian 1.0\nelement vertex 4000000000\n\
+                      property float x\nproperty float y\nproperty float z\nend_header\n";
+        let mut source = source.as_bytes().to_vec();
+        source.extend_from_slice(bytemuck::cast_slice(&[0.0f32; 3]));
+        assert!(parse(&source, "ply".into()).is_err());
+    }
+}
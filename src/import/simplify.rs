@@ -0,0 +1,466 @@
+//! Reducing meshes to fewer triangles by quadric error edge collapses, after Garland and
+//! Heckbert.
+
+use std::collections::{BTreeMap, BinaryHeap};
+
+use cgmath::{InnerSpace, Vector3};
+
+use super::MeshData;
+use crate::components::rendering::Vertex;
+
+/// How much more moving a vertex off an open edge costs than moving it off a face, so that holes,
+/// outlines and texture seams keep their shape.
+const BOUNDARY_WEIGHT: f64 = 100.0;
+
+/// The sum of squared distances to a set of planes, as a symmetric 4x4 matrix.
+#[derive(Debug, Clone, Copy, Default)]
+struct Quadric([f64; 10]);
+
+impl Quadric {
+    fn plane(normal: Vector3<f64>, point: Vector3<f64>, weight: f64) -> Self {
+        let [a, b, c] = [normal.x, normal.y, normal.z];
+        let d = -normal.dot(point);
+        Self([
+            a * a,
+            a * b,
+            a * c,
+            a * d,
+            b * b,
+            b * c,
+            b * d,
+            c * c,
+            c * d,
+            d * d,
+        ])
+        .scaled(weight)
+    }
+
+    fn scaled(self, weight: f64) -> Self {
+        Self(self.0.map(|value| value * weight))
+    }
+
+    fn error(&self, p: Vector3<f64>) -> f64 {
+        let [a2, ab, ac, ad, b2, bc, bd, c2, cd, d2] = self.0;
+        a2 * p.x * p.x
+            + b2 * p.y * p.y
+            + c2 * p.z * p.z
+            + 2.0 * (ab * p.x * p.y + ac * p.x * p.z + bc * p.y * p.z)
+            + 2.0 * (ad * p.x + bd * p.y + cd * p.z)
+            + d2
+    }
+}
+
+impl std::ops::Add for Quadric {
+    type Output = Self;
+
+    fn add(mut self, other: Self) -> Self {
+        for (value, other) in self.0.iter_mut().zip(other.0) {
+            *value += other;
+        }
+        self
+    }
+}
+
+/// Merging `from` into `into`, valid while neither vertex has changed since it was planned.
+#[derive(Debug)]
+struct Collapse {
+    cost: f64,
+    into: usize,
+    from: usize,
+    versions: (u32, u32),
+}
+
+impl PartialEq for Collapse {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+
+impl Eq for Collapse {}
+
+impl PartialOrd for Collapse {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Collapse {
+    // Reversed, so that the heap yields the cheapest collapse first
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        other.cost.total_cmp(&self.cost)
+    }
+}
+
+/// Where a merged vertex is, and so where its attributes are taken from.
+#[derive(Debug, Clone, Copy)]
+enum Merge {
+    First,
+    Second,
+    Middle,
+}
+
+impl Merge {
+    const ALL: [Self; 3] = [Self::First, Self::Second, Self::Middle];
+
+    fn apply<const N: usize>(self, first: [f32; N], second: [f32; N]) -> [f32; N] {
+        match self {
+            Merge::First => first,
+            Merge::Second => second,
+            Merge::Middle => std::array::from_fn(|i| (first[i] + second[i]) / 2.0),
+        }
+    }
+}
+
+struct Simplifier {
+    vertices: Vec<Vertex>,
+    colors: Option<Vec<[f32; 4]>>,
+    tex_coords1: Option<Vec<[f32; 2]>>,
+    tangents: Option<Vec<[f32; 4]>>,
+    quadrics: Vec<Quadric>,
+    versions: Vec<u32>,
+    removed: Vec<bool>,
+    triangles: Vec<[usize; 3]>,
+    live: Vec<bool>,
+    /// The triangles around each vertex, including removed ones except around the vertices that
+    /// were last merged into.
+    adjacency: Vec<Vec<usize>>,
+    heap: BinaryHeap<Collapse>,
+}
+
+/// Collapses edges of `mesh` until only `ratio` of its triangles are left, or no edge can be
+/// collapsed without folding the surface over.
+pub fn simplify(mesh: &MeshData, ratio: f32) -> MeshData {
+    let triangles = mesh
+        .indices
+        .chunks_exact(3)
+        .map(|triangle| [0, 1, 2].map(|i| triangle[i] as usize))
+        .collect::<Vec<_>>();
+    let target = (triangles.len() as f32 * ratio.clamp(0.0, 1.0)) as usize;
+
+    let mut simplifier = Simplifier::new(mesh.vertices.clone(), triangles);
+    simplifier.colors = mesh.colors.clone();
+    simplifier.tex_coords1 = mesh.tex_coords1.clone();
+    simplifier.tangents = mesh.tangents.clone();
+    let mut remaining = simplifier.triangles.len();
+    while remaining > target {
+        let Some(collapse) = simplifier.heap.pop() else {
+            break;
+        };
+        remaining -= simplifier.collapse(collapse);
+    }
+
+    let (kept, indices) = simplifier.finish();
+    let vertices = kept.iter().map(|&i| simplifier.vertices[i]).collect();
+    let mut simplified = MeshData::new(mesh.name.clone(), vertices, indices, mesh.material);
+    simplified.colors = simplifier
+        .colors
+        .map(|colors| kept.iter().map(|&i| colors[i]).collect());
+    simplified.tex_coords1 = simplifier
+        .tex_coords1
+        .map(|tex_coords| kept.iter().map(|&i| tex_coords[i]).collect());
+    simplified.tangents = simplifier
+        .tangents
+        .map(|tangents| kept.iter().map(|&i| tangents[i]).collect());
+    simplified
+}
+
+fn position(vertex: &Vertex) -> Vector3<f64> {
+    Vector3::from(vertex.position.map(f64::from))
+}
+
+impl Simplifier {
+    fn new(vertices: Vec<Vertex>, triangles: Vec<[usize; 3]>) -> Self {
+        let mut quadrics = vec![Quadric::default(); vertices.len()];
+        let mut adjacency = vec![Vec::new(); vertices.len()];
+        // Ordered, so that the same mesh is always simplified the same way
+        let mut edges = BTreeMap::new();
+
+        for (index, triangle) in triangles.iter().enumerate() {
+            let [a, b, c] = triangle.map(|i| position(&vertices[i]));
+            // Weighed by area, so that small triangles are the first to go
+            let normal = (b - a).cross(c - a);
+            let area = normal.magnitude();
+            if area > 0.0 {
+                let quadric = Quadric::plane(normal / area, a, area);
+                for &vertex in triangle {
+                    quadrics[vertex] = quadrics[vertex] + quadric;
+                }
+            }
+
+            for (i, &vertex) in triangle.iter().enumerate() {
+                adjacency[vertex].push(index);
+                let next = triangle[(i + 1) % 3];
+                *edges
+                    .entry((vertex.min(next), vertex.max(next)))
+                    .or_insert(0) += 1;
+            }
+        }
+
+        // Edges with a single triangle are held in place by a plane through them, perpendicular
+        // to the triangle
+        for triangle in &triangles {
+            let [a, b, c] = triangle.map(|i| position(&vertices[i]));
+            let normal = (b - a).cross(c - a);
+            if normal.magnitude2() == 0.0 {
+                continue;
+            }
+
+            for i in 0..3 {
+                let (start, end) = (triangle[i], triangle[(i + 1) % 3]);
+                if edges[&(start.min(end), start.max(end))] != 1 {
+                    continue;
+                }
+
+                let edge = position(&vertices[end]) - position(&vertices[start]);
+                let plane = edge.cross(normal);
+                if plane.magnitude2() > 0.0 {
+                    let quadric = Quadric::plane(
+                        plane.normalize(),
+                        position(&vertices[start]),
+                        BOUNDARY_WEIGHT * edge.magnitude2(),
+                    );
+                    quadrics[start] = quadrics[start] + quadric;
+                    quadrics[end] = quadrics[end] + quadric;
+                }
+            }
+        }
+
+        let mut simplifier = Self {
+            versions: vec![0; vertices.len()],
+            removed: vec![false; vertices.len()],
+            live: vec![true; triangles.len()],
+            vertices,
+            colors: None,
+            tex_coords1: None,
+            tangents: None,
+            quadrics,
+            triangles,
+            adjacency,
+            heap: BinaryHeap::new(),
+        };
+        for (a, b) in edges.into_keys() {
+            simplifier.plan(a, b);
+        }
+        simplifier
+    }
+
+    /// The cheapest vertex that `a` and `b` can be merged into, where it is, and its error. The
+    /// merged vertex is one of the two, or halfway between them.
+    fn merged(&self, a: usize, b: usize) -> (f64, Vertex, Merge) {
+        let (first, second) = (self.vertices[a], self.vertices[b]);
+        let quadric = self.quadrics[a] + self.quadrics[b];
+        Merge::ALL
+            .into_iter()
+            .map(|merge| {
+                let normal = Vector3::from(merge.apply(first.normal, second.normal));
+                let vertex = Vertex {
+                    position: merge.apply(first.position, second.position),
+                    tex_coords: merge.apply(first.tex_coords, second.tex_coords),
+                    normal: if normal.magnitude2() > 0.0 {
+                        normal.normalize().into()
+                    } else {
+                        first.normal
+                    },
+                };
+                (quadric.error(position(&vertex)), vertex, merge)
+            })
+            .min_by(|(a, ..), (b, ..)| a.total_cmp(b))
+            .unwrap()
+    }
+
+    fn plan(&mut self, into: usize, from: usize) {
+        let (cost, ..) = self.merged(into, from);
+        self.heap.push(Collapse {
+            cost,
+            into,
+            from,
+            versions: (self.versions[into], self.versions[from]),
+        });
+    }
+
+    /// The vertices that share a live triangle with `vertex`.
+    fn neighbours(&self, vertex: usize) -> Vec<usize> {
+        let mut neighbours = self.adjacency[vertex]
+            .iter()
+            .filter(|&&triangle| self.live[triangle])
+            .flat_map(|&triangle| self.triangles[triangle])
+            .filter(|&neighbour| neighbour != vertex)
+            .collect::<Vec<_>>();
+        neighbours.sort_unstable();
+        neighbours.dedup();
+        neighbours
+    }
+
+    /// Whether merging `into` and `from` keeps the surface a manifold: the only vertices
+    /// neighbouring both must be the corners of the triangles along their edge.
+    fn is_manifold(&self, into: usize, from: usize) -> bool {
+        let from_neighbours = self.neighbours(from);
+        let shared = self
+            .neighbours(into)
+            .into_iter()
+            .filter(|vertex| from_neighbours.binary_search(vertex).is_ok())
+            .count();
+        let along_edge = self.adjacency[into]
+            .iter()
+            .filter(|&&triangle| self.live[triangle] && self.triangles[triangle].contains(&from))
+            .count();
+        shared == along_edge
+    }
+
+    /// Whether moving `into` and `from` to `position` turns any of their triangles over.
+    fn flips(&self, into: usize, from: usize, position: Vector3<f64>) -> bool {
+        self.adjacency[into]
+            .iter()
+            .chain(&self.adjacency[from])
+            .filter(|&&triangle| self.live[triangle])
+            .map(|&triangle| self.triangles[triangle])
+            // The triangles along the edge disappear
+            .filter(|triangle| !(triangle.contains(&into) && triangle.contains(&from)))
+            .any(|triangle| {
+                let [a, b, c] = triangle.map(|i| self::position(&self.vertices[i]));
+                let [d, e, f] = triangle.map(|i| {
+                    if i == into || i == from {
+                        position
+                    } else {
+                        self::position(&self.vertices[i])
+                    }
+                });
+                let before = (b - a).cross(c - a);
+                let after = (e - d).cross(f - d);
+                // Triangles without an area have no side to turn over
+                before.magnitude2() > 0.0 && before.dot(after) <= 0.0
+            })
+    }
+
+    /// Applies `collapse` if it's still valid, returning how many triangles it removed.
+    fn collapse(&mut self, collapse: Collapse) -> usize {
+        let Collapse {
+            into,
+            from,
+            versions,
+            ..
+        } = collapse;
+        if self.removed[into]
+            || self.removed[from]
+            || versions != (self.versions[into], self.versions[from])
+        {
+            return 0;
+        }
+
+        let (_, vertex, merge) = self.merged(into, from);
+        if !self.is_manifold(into, from) || self.flips(into, from, position(&vertex)) {
+            return 0;
+        }
+
+        self.vertices[into] = vertex;
+        if let Some(colors) = &mut self.colors {
+            colors[into] = merge.apply(colors[into], colors[from]);
+        }
+        if let Some(tex_coords) = &mut self.tex_coords1 {
+            tex_coords[into] = merge.apply(tex_coords[into], tex_coords[from]);
+        }
+        if let Some(tangents) = &mut self.tangents {
+            // The shader only reads the sign of the average's w, and normalises its direction
+            tangents[into] = merge.apply(tangents[into], tangents[from]);
+        }
+        self.quadrics[into] = self.quadrics[into] + self.quadrics[from];
+        self.versions[into] += 1;
+        self.removed[from] = true;
+
+        let mut removed = 0;
+        for triangle in std::mem::take(&mut self.adjacency[from]) {
+            if !self.live[triangle] {
+                continue;
+            }
+            if self.triangles[triangle].contains(&into) {
+                self.live[triangle] = false;
+                removed += 1;
+            } else {
+                for vertex in self.triangles[triangle].iter_mut() {
+                    if *vertex == from {
+                        *vertex = into;
+                    }
+                }
+                self.adjacency[into].push(triangle);
+            }
+        }
+        let live = &self.live;
+        self.adjacency[into].retain(|&triangle| live[triangle]);
+
+        for neighbour in self.neighbours(into) {
+            self.plan(into, neighbour);
+        }
+
+        removed
+    }
+
+    /// The vertices still in use, and the indices of the live triangles into them.
+    fn finish(&self) -> (Vec<usize>, Vec<u32>) {
+        let mut remap = vec![None; self.vertices.len()];
+        let mut kept = Vec::new();
+        let mut indices = Vec::new();
+        for (triangle, &live) in self.triangles.iter().zip(&self.live) {
+            if !live {
+                continue;
+            }
+            for &vertex in triangle {
+                let index = *remap[vertex].get_or_insert_with(|| {
+                    kept.push(vertex);
+                    kept.len() as u32 - 1
+                });
+                indices.push(index);
+            }
+        }
+        (kept, indices)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::primitives;
+
+    fn assert_valid(mesh: &MeshData) {
+        assert_eq!(mesh.indices.len() % 3, 0);
+        for triangle in mesh.indices.chunks_exact(3) {
+            assert!(triangle.iter().all(|&i| (i as usize) < mesh.vertices.len()));
+            let [a, b, c] = [0, 1, 2].map(|i| position(&mesh.vertices[triangle[i] as usize]));
+            assert!(
+                (b - a).cross(c - a).magnitude2() > 0.0,
+                "degenerate triangle {triangle:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn halves_a_subdivided_plane() {
+        let plane = primitives::plane(16);
+        let triangles = plane.indices.len() / 3;
+        let simplified = simplify(&plane, 0.5);
+        let remaining = simplified.indices.len() / 3;
+        assert!(
+            remaining <= triangles / 2 && remaining >= triangles * 2 / 5,
+            "{remaining} of {triangles} triangles left"
+        );
+        assert_valid(&simplified);
+    }
+
+    #[test]
+    fn keeps_meshes_valid_down_to_few_triangles() {
+        for mesh in [primitives::icosphere(3), primitives::uv_sphere(32, 16)] {
+            for ratio in [0.5, 0.1, 0.0] {
+                let simplified = simplify(&mesh, ratio);
+                assert!(simplified.indices.len() < mesh.indices.len());
+                assert_valid(&simplified);
+            }
+        }
+    }
+
+    #[test]
+    fn keeps_everything_at_full_ratio() {
+        let plane = primitives::plane(4);
+        let simplified = simplify(&plane, 1.0);
+        assert_eq!(simplified.indices.len(), plane.indices.len());
+        assert_valid(&simplified);
+    }
+}
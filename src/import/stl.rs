@@ -0,0 +1,284 @@
+//! STL files, as exported by CAD software.
+
+use std::path::Path;
+
+use cgmath::{InnerSpace, Vector3};
+
+use super::{ImportError, ImportedModel, MeshData};
+use crate::components::rendering::Vertex;
+
+/// The size of a binary file's header, and of each of its triangles.
+const HEADER_SIZE: usize = 80;
+const TRIANGLE_SIZE: usize = 50;
+
+/// Loads an ASCII or binary STL file, with a mesh for each solid of an ASCII file. Every triangle
+/// has vertices of its own, so that the facets are shaded flat. Binary files' triangle colours
+/// are read in both the VisCAM and the Materialise conventions.
+pub fn load(path: &Path) -> Result<ImportedModel, ImportError> {
+    let source = std::fs::read(path)?;
+    let name = path.file_stem().map_or_else(
+        || "stl".to_string(),
+        |stem| stem.to_string_lossy().into_owned(),
+    );
+
+    Ok(ImportedModel {
+        meshes: parse(&source, name)?,
+        materials: Vec::new(),
+    })
+}
+
+fn parse(source: &[u8], name: String) -> Result<Vec<MeshData>, ImportError> {
+    // Some binary files start with "solid" too, so their size is checked first
+    if binary_size(source) == Some(source.len()) {
+        Ok(vec![parse_binary(source, name)])
+    } else if source.trim_ascii_start().starts_with(b"solid") {
+        parse_ascii(source, &name)
+    } else {
+        Err(ImportError::Stl(
+            "neither an ASCII nor a binary file".into(),
+        ))
+    }
+}
+
+/// The size a binary file should have, from the triangle count after its header.
+fn binary_size(source: &[u8]) -> Option<usize> {
+    let count = source.get(HEADER_SIZE..HEADER_SIZE + 4)?;
+    let count = u32::from_le_bytes(count.try_into().ok()?) as usize;
+    count
+        .checked_mul(TRIANGLE_SIZE)?
+        .checked_add(HEADER_SIZE + 4)
+}
+
+/// Adds a triangle's vertices, with its normal, or the normal of its corners' winding when the
+/// file doesn't give one.
+fn add_triangle(vertices: &mut Vec<Vertex>, normal: [f32; 3], corners: [[f32; 3]; 3]) {
+    let [a, b, c] = corners.map(Vector3::from);
+    let normal = match Vector3::from(normal) {
+        normal if normal.magnitude2() > 0.0 => normal.normalize(),
+        _ => (b - a).cross(c - a).normalize(),
+    };
+    let normal = if normal.x.is_finite() {
+        normal.into()
+    } else {
+        [0.0, 0.0, 1.0]
+    };
+
+    vertices.extend(corners.map(|position| Vertex {
+        position,
+        tex_coords: [0.0, 0.0],
+        normal,
+    }));
+}
+
+fn parse_binary(source: &[u8], name: String) -> MeshData {
+    let read_floats = |bytes: &[u8]| -> [f32; 3] {
+        [0, 4, 8].map(|i| f32::from_le_bytes(bytes[i..i + 4].try_into().unwrap()))
+    };
+    // Materialise's files name a colour for the whole part in the header, and mark the
+    // triangles that have their own colour with a clear top bit, where VisCAM's set it
+    let materialise = source[..HEADER_SIZE]
+        .windows(6)
+        .any(|window| window == b"COLOR=");
+
+    let mut vertices = Vec::new();
+    let mut colors = Vec::new();
+    let mut has_colors = false;
+    for triangle in source[HEADER_SIZE + 4..].chunks_exact(TRIANGLE_SIZE) {
+        add_triangle(
+            &mut vertices,
+            read_floats(&triangle[0..12]),
+            [12, 24, 36].map(|i| read_floats(&triangle[i..i + 12])),
+        );
+
+        let attribute = u16::from_le_bytes([triangle[48], triangle[49]]);
+        let channel = |shift: u16| f32::from((attribute >> shift) & 0x1f) / 31.0;
+        let color = match (materialise, attribute & 0x8000 != 0) {
+            (false, true) => Some([channel(10), channel(5), channel(0), 1.0]),
+            (true, false) => Some([channel(0), channel(5), channel(10), 1.0]),
+            _ => None,
+        };
+        has_colors |= color.is_some();
+        colors.extend([color.unwrap_or([1.0; 4]); 3]);
+    }
+
+    let indices = (0..vertices.len() as u32).collect();
+    let mesh = MeshData::new(name, vertices, indices, usize::MAX);
+    if has_colors {
+        mesh.with_colors(colors)
+    } else {
+        mesh
+    }
+}
+
+fn parse_ascii(source: &[u8], name: &str) -> Result<Vec<MeshData>, ImportError> {
+    let error = |message: &str| ImportError::Stl(message.into());
+    let source = std::str::from_utf8(source).map_err(|_| error("not a text file"))?;
+
+    let mut meshes = Vec::new();
+    let mut vertices = Vec::new();
+    let mut solid_name = String::new();
+    let mut normal = [0.0; 3];
+    let mut corners = Vec::new();
+
+    for line in source.lines() {
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some("solid") => {
+                solid_name = line.trim_start()["solid".len()..].trim().to_string();
+            }
+            // "facet normal x y z"
+            Some("facet") => {
+                normal = read_vector(words.skip(1)).ok_or_else(|| error("bad facet normal"))?;
+                corners.clear();
+            }
+            Some("vertex") => {
+                corners.push(read_vector(words).ok_or_else(|| error("bad vertex"))?);
+            }
+            // Loops with more than three corners are split into a fan of triangles
+            Some("endloop") => {
+                for i in 1..corners.len().saturating_sub(1) {
+                    add_triangle(
+                        &mut vertices,
+                        normal,
+                        [corners[0], corners[i], corners[i + 1]],
+                    );
+                }
+            }
+            Some("endsolid") => {
+                let indices = (0..vertices.len() as u32).collect();
+                let mesh_name = if solid_name.is_empty() {
+                    format!("{}/{}", name, meshes.len())
+                } else {
+                    solid_name.clone()
+                };
+                meshes.push(MeshData::new(
+                    mesh_name,
+                    std::mem::take(&mut vertices),
+                    indices,
+                    usize::MAX,
+                ));
+            }
+            _ => {}
+        }
+    }
+
+    if meshes.is_empty() {
+        return Err(error("no solid ends in the file"));
+    }
+    Ok(meshes)
+}
+
+fn read_vector<'a>(mut words: impl Iterator<Item = &'a str>) -> Option<[f32; 3]> {
+    let mut vector = [0.0; 3];
+    for value in vector.iter_mut() {
+        *value = words.next()?.parse().ok()?;
+    }
+    Some(vector)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A binary file with `header` and a triangle for each attribute, all in the XY plane.
+    fn binary(header: &str, attributes: &[u16]) -> Vec<u8> {
+        let mut source = header.as_bytes().to_vec();
+        source.resize(HEADER_SIZE, b' ');
+        source.extend_from_slice(&(attributes.len() as u32).to_le_bytes());
+        for attribute in attributes {
+            let floats = [
+                [0.0, 0.0, 1.0],
+                [0.0, 0.0, 0.0],
+                [1.0, 0.0, 0.0],
+                [0.0, 1.0, 0.0f32],
+            ];
+            source.extend_from_slice(bytemuck::cast_slice(&floats));
+            source.extend_from_slice(&attribute.to_le_bytes());
+        }
+        source
+    }
+
+    fn read_colors(source: &[u8]) -> Option<Vec<[f32; 4]>> {
+        let mut meshes = parse(source, "stl".into()).unwrap();
+        assert_eq!(meshes.len(), 1);
+        meshes.remove(0).colors
+    }
+
+    #[test]
+    fn binary_files_starting_with_solid_are_read_as_binary() {
+        let meshes = parse(&binary("solid exported by CAD", &[0, 0]), "part".into()).unwrap();
+        assert_eq!(meshes.len(), 1);
+        assert_eq!(meshes[0].name, "part");
+        assert_eq!(meshes[0].vertices.len(), 6);
+        assert_eq!(meshes[0].vertices[0].normal, [0.0, 0.0, 1.0]);
+        assert!(meshes[0].colors.is_none());
+    }
+
+    #[test]
+    fn reads_ascii_solids() {
+        // A quad with no normal given, then a named triangle
+        let source = "solid
+            facet normal 0 0 0
+                outer loop
+                    vertex 0 0 0
+                    vertex 1 0 0
+                    vertex 1 1 0
+                    vertex 0 1 0
+                endloop
+            endfacet
+            endsolid
+            solid lid
+            facet normal 0 0 -1
+                outer loop
+                    vertex 0 0 1
+                    vertex 0 1 1
+                    vertex 1 0 1
+                endloop
+            endfacet
+            endsolid lid
+        ";
+        let meshes = parse(source.as_bytes(), "part".into()).unwrap();
+        assert_eq!(meshes.len(), 2);
+        assert_eq!(meshes[0].name, "part/0");
+        assert_eq!(meshes[0].vertices.len(), 6);
+        assert_eq!(meshes[0].indices, [0, 1, 2, 3, 4, 5]);
+        assert_eq!(meshes[0].vertices[0].normal, [0.0, 0.0, 1.0]);
+        assert_eq!(meshes[1].name, "lid");
+        assert_eq!(meshes[1].vertices.len(), 3);
+        assert_eq!(meshes[1].vertices[0].normal, [0.0, 0.0, -1.0]);
+    }
+
+    #[test]
+    fn rejects_files_that_are_neither() {
+        assert!(parse(b"not a model", "stl".into()).is_err());
+        // Too short for its triangle count, and not text
+        let mut source = binary("", &[0, 0]);
+        source.pop();
+        assert!(parse(&source, "stl".into()).is_err());
+        assert!(parse(b"solid open\n", "stl".into()).is_err());
+    }
+
+    #[test]
+    fn reads_viscam_colors() {
+        // RGB from the high bits down, where the top bit is set
+        let red = 0x8000 | 31 << 10;
+        let blue = 0x8000 | 31;
+        let colors = read_colors(&binary("", &[red, blue, 31 << 10])).unwrap();
+        assert_eq!(colors.len(), 9);
+        assert_eq!(colors[0], [1.0, 0.0, 0.0, 1.0]);
+        assert_eq!(colors[3], [0.0, 0.0, 1.0, 1.0]);
+        assert_eq!(colors[6], [1.0; 4]);
+    }
+
+    #[test]
+    fn reads_materialise_colors() {
+        // BGR from the high bits down, where the top bit is clear
+        let header = "COLOR=";
+        let colors = read_colors(&binary(header, &[31, 31 << 10, 0x8000 | 31])).unwrap();
+        assert_eq!(colors[0], [1.0, 0.0, 0.0, 1.0]);
+        assert_eq!(colors[3], [0.0, 0.0, 1.0, 1.0]);
+        assert_eq!(colors[6], [1.0; 4]);
+        // Every triangle using the part's colour
+        assert!(read_colors(&binary(header, &[0x8000, 0x8000])).is_none());
+    }
+}
@@ -1,5 +1,12 @@
-use grt::run;
+use clap::Parser;
+use grt::{run_with_options, Options};
 
 fn main() {
-    pollster::block_on(run());
+    let options = Options::parse();
+    let config = options.engine_config();
+
+    if let Err(error) = pollster::block_on(run_with_options(options, config)) {
+        eprintln!("grt: {error}");
+        std::process::exit(1);
+    }
 }
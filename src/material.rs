@@ -0,0 +1,183 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::{
+    assets::AssetPaths,
+    material_manager::{MaterialManager, Texture},
+    mtl::TextureMap,
+    texture_data::ColorSpace,
+};
+
+/// How the alpha of a material's base colour is used.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum AlphaMode {
+    /// Alpha is ignored.
+    #[default]
+    Opaque,
+    /// Fragments whose alpha is below `cutoff` are discarded, and the rest are opaque.
+    Cutout { cutoff: f32 },
+    /// Blended over what's behind, in a pass after opaque geometry that draws back to front.
+    Blend,
+}
+
+impl AlphaMode {
+    /// The cutoff passed to the shader, which is 0 for modes that don't discard.
+    pub fn cutoff(&self) -> f32 {
+        match self {
+            AlphaMode::Cutout { cutoff } => *cutoff,
+            AlphaMode::Opaque | AlphaMode::Blend => 0.0,
+        }
+    }
+}
+
+/// A metallic-roughness material, as defined by glTF.
+///
+/// Each factor is multiplied by its map, and a missing map counts as white, or as a flat normal
+/// map. The metallic value is read from the blue channel of `metallic_texture` and roughness from
+/// the green channel of `roughness_texture`, so a glTF metallic-roughness texture can be used for
+/// both, and a greyscale map for either.
+#[derive(Clone)]
+pub struct PbrMaterial {
+    pub name: String,
+    /// Linear RGBA.
+    pub base_color: [f32; 4],
+    pub metallic: f32,
+    pub roughness: f32,
+    /// Linear RGB.
+    pub emissive: [f32; 3],
+    pub normal_scale: f32,
+    pub occlusion_strength: f32,
+    /// The set of texture coordinates the occlusion map is read with: 0, or 1 for the second
+    /// set, where baked occlusion usually has its own unwrap. Meshes without a second set use
+    /// the first.
+    pub occlusion_tex_coord: u32,
+    pub alpha_mode: AlphaMode,
+
+    pub base_color_texture: Option<Arc<Texture>>,
+    pub metallic_texture: Option<Arc<Texture>>,
+    pub roughness_texture: Option<Arc<Texture>>,
+    pub normal_texture: Option<Arc<Texture>>,
+    /// Ambient occlusion, read from the red channel.
+    pub occlusion_texture: Option<Arc<Texture>>,
+    pub emissive_texture: Option<Arc<Texture>>,
+}
+
+impl Default for PbrMaterial {
+    fn default() -> Self {
+        Self {
+            name: "default".to_string(),
+            base_color: [1.0; 4],
+            metallic: 0.0,
+            roughness: 1.0,
+            emissive: [0.0; 3],
+            normal_scale: 1.0,
+            occlusion_strength: 1.0,
+            occlusion_tex_coord: 0,
+            alpha_mode: AlphaMode::Opaque,
+            base_color_texture: None,
+            metallic_texture: None,
+            roughness_texture: None,
+            normal_texture: None,
+            occlusion_texture: None,
+            emissive_texture: None,
+        }
+    }
+}
+
+impl PbrMaterial {
+    /// Converts an MTL material, using the PBR extension statements (`Pr`, `Pm`, `Ke` and their
+    /// maps) where present. Without them, `Kd` and `map_Kd` become the base colour, the material
+    /// is a dielectric, and its roughness is derived from the `Ns` specular exponent.
+    ///
+    /// Materials with a dissolve (`d`, or `Tr` as its inverse) below 1 are blended. Materials
+    /// with a dissolve map are cut out instead, with alpha read from the diffuse map, as the
+    /// dissolve map is usually its alpha channel.
+    pub fn from_mtl(
+        material: &tobj::Material,
+        model_path: &Path,
+        asset_paths: &AssetPaths,
+        material_manager: &MaterialManager,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Self {
+        let parameter = |name: &str| material.unknown_param.get(name).map(String::as_str);
+        let scalar = |name: &str, default: f32| {
+            parameter(name)
+                .and_then(|value| value.trim().parse().ok())
+                .unwrap_or(default)
+        };
+
+        let load = |statement: &str, color_space: ColorSpace| {
+            if statement.is_empty() {
+                return None;
+            }
+
+            let map = TextureMap::parse(statement);
+            let path = asset_paths.resolve(&map.path, Some(model_path));
+            let texture = material_manager
+                .load_texture(&path, &map.options, color_space, device, queue)
+                .unwrap_or_else(|error| {
+                    log::error!("{:?}: {}", path, error);
+                    material_manager.missing_texture()
+                });
+            Some(texture)
+        };
+
+        // tobj reports a missing Kd as black, which would hide the diffuse map
+        let [r, g, b] = if material.diffuse == [0.0; 3] && !material.diffuse_texture.is_empty() {
+            [1.0; 3]
+        } else {
+            material.diffuse
+        };
+        // tobj parses `d`, but leaves `Tr` alone
+        let opacity = match parameter("Tr") {
+            Some(_) if material.dissolve == 1.0 => 1.0 - scalar("Tr", 0.0),
+            _ => material.dissolve,
+        };
+        let alpha_mode = if !material.dissolve_texture.is_empty() {
+            AlphaMode::Cutout { cutoff: 0.5 }
+        } else if opacity < 1.0 {
+            AlphaMode::Blend
+        } else {
+            AlphaMode::Opaque
+        };
+
+        let emissive = parameter("Ke")
+            .map(|value| {
+                let mut components = value
+                    .split_whitespace()
+                    .map(|c| c.parse::<f32>().unwrap_or(0.0));
+                let r = components.next().unwrap_or(0.0);
+                [
+                    r,
+                    components.next().unwrap_or(r),
+                    components.next().unwrap_or(r),
+                ]
+            })
+            .unwrap_or([0.0; 3]);
+        let emissive_texture = load(parameter("map_Ke").unwrap_or(""), ColorSpace::Srgb);
+
+        Self {
+            name: material.name.clone(),
+            base_color: [r, g, b, opacity],
+            metallic: scalar("Pm", 0.0),
+            roughness: scalar("Pr", (2.0 / (material.shininess + 2.0)).sqrt()),
+            // An emissive map with no Ke emits at full strength
+            emissive: if emissive_texture.is_some() && parameter("Ke").is_none() {
+                [1.0; 3]
+            } else {
+                emissive
+            },
+            alpha_mode,
+            base_color_texture: load(&material.diffuse_texture, ColorSpace::Srgb),
+            metallic_texture: load(parameter("map_Pm").unwrap_or(""), ColorSpace::Linear),
+            roughness_texture: load(parameter("map_Pr").unwrap_or(""), ColorSpace::Linear),
+            normal_texture: load(
+                parameter("norm").unwrap_or(&material.normal_texture),
+                ColorSpace::Linear,
+            ),
+            emissive_texture,
+            ..Default::default()
+        }
+    }
+}
@@ -1,16 +1,73 @@
-use image::GenericImageView;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
 
-use crate::components::rendering::Vertex;
+use wgpu::util::DeviceExt;
+
+use crate::{
+    components::rendering::{MaterialUniform, VertexLayout},
+    material::{AlphaMode, PbrMaterial},
+    texture_data::{ColorSpace, TextureData, TextureError},
+};
 
 pub struct MaterialManager {
     texture_bind_group_layout: wgpu::BindGroupLayout,
     camera_bind_group_layout: wgpu::BindGroupLayout,
+    light_bind_group_layout: wgpu::BindGroupLayout,
+    blit_shader: wgpu::ShaderModule,
+    blit_pipelines: Mutex<HashMap<wgpu::TextureFormat, wgpu::RenderPipeline>>,
+    /// Built into the binary, so that it doesn't depend on the working directory.
+    scene_shader: wgpu::ShaderModule,
+    scene_pipelines: Mutex<HashMap<ScenePipelineKey, [wgpu::RenderPipeline; 3]>>,
+    mipmap_sampler: wgpu::Sampler,
+    supports_anisotropy: bool,
+    /// The GL backend can't sample from one mip level while rendering to another.
+    gpu_mipmaps: bool,
+    textures: HashMap<String, Arc<Texture>>,
+    /// Textures loaded from files, so that materials sharing a file share the texture.
+    loaded_textures: Mutex<HashMap<TextureKey, Weak<Texture>>>,
 }
 
-pub struct Shader {
-    pub shader: wgpu::ShaderModule,
-    pub layout: wgpu::PipelineLayout,
-    pub pipeline: wgpu::RenderPipeline,
+type TextureKey = (PathBuf, ColorSpace, SamplerOptions);
+
+/// Opaque white, multiplied with a material's colour when it has no diffuse map.
+pub const WHITE_TEXTURE: &str = "builtin:white";
+/// A normal map that leaves normals unchanged.
+pub const FLAT_NORMAL_TEXTURE: &str = "builtin:flat_normal";
+pub const BLACK_TEXTURE: &str = "builtin:black";
+/// A magenta checkerboard used in place of textures that failed to load.
+pub const MISSING_TEXTURE: &str = "builtin:missing";
+
+/// The format of the depth buffer every scene pipeline tests against.
+pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+
+/// What the scene shader's pipelines are built for: the opaque, cutout and blend pipelines of
+/// each are kept together.
+type ScenePipelineKey = (VertexLayout, wgpu::TextureFormat, u32);
+
+/// The scene shader's pipelines for one target format and sample count, held for as long as
+/// they're drawn with.
+pub struct ScenePipelines<'a> {
+    pipelines: MutexGuard<'a, HashMap<ScenePipelineKey, [wgpu::RenderPipeline; 3]>>,
+    format: wgpu::TextureFormat,
+    sample_count: u32,
+}
+
+impl ScenePipelines<'_> {
+    /// The pipeline variant that draws meshes with the given vertex layout, with materials of
+    /// the given alpha mode. The layout must be one the pipelines were fetched for.
+    pub fn pipeline_for(
+        &self,
+        alpha_mode: AlphaMode,
+        layout: VertexLayout,
+    ) -> &wgpu::RenderPipeline {
+        let [opaque, cutout, blend] = &self.pipelines[&(layout, self.format, self.sample_count)];
+        match alpha_mode {
+            AlphaMode::Opaque => opaque,
+            AlphaMode::Cutout { .. } => cutout,
+            AlphaMode::Blend => blend,
+        }
+    }
 }
 
 pub struct Texture {
@@ -19,193 +76,602 @@ pub struct Texture {
     pub sampler: wgpu::Sampler,
 }
 
-impl MaterialManager {
-    pub fn new(device: &wgpu::Device) -> Self {
+/// How a texture is sampled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SamplerOptions {
+    pub address_mode: wgpu::AddressMode,
+    pub mag_filter: wgpu::FilterMode,
+    pub min_filter: wgpu::FilterMode,
+    pub mipmap_filter: wgpu::FilterMode,
+    /// Maximum anisotropy. Only used when the adapter supports anisotropic filtering and every
+    /// filter is linear.
+    pub anisotropy: u8,
+}
+
+impl Default for SamplerOptions {
+    fn default() -> Self {
+        Self {
+            address_mode: wgpu::AddressMode::Repeat,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            anisotropy: 16,
+        }
+    }
+}
+
+impl SamplerOptions {
+    pub fn clamped() -> Self {
         Self {
+            address_mode: wgpu::AddressMode::ClampToEdge,
+            ..Default::default()
+        }
+    }
+}
+
+impl MaterialManager {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, adapter: &wgpu::Adapter) -> Self {
+        let supports_anisotropy = adapter
+            .get_downlevel_capabilities()
+            .flags
+            .contains(wgpu::DownlevelFlags::ANISOTROPIC_FILTERING);
+
+        let mut manager = Self {
+            textures: HashMap::new(),
+            loaded_textures: Mutex::new(HashMap::new()),
+            blit_shader: device.create_shader_module(wgpu::include_wgsl!("shaders/blit.wgsl")),
+            blit_pipelines: Mutex::new(HashMap::new()),
+            scene_shader: device.create_shader_module(wgpu::include_wgsl!("shaders/default.wgsl")),
+            scene_pipelines: Mutex::new(HashMap::new()),
+            mipmap_sampler: device.create_sampler(&wgpu::SamplerDescriptor {
+                label: Some("Mipmap Sampler"),
+                mag_filter: wgpu::FilterMode::Linear,
+                min_filter: wgpu::FilterMode::Linear,
+                ..Default::default()
+            }),
+            supports_anisotropy,
+            gpu_mipmaps: adapter.get_info().backend != wgpu::Backend::Gl,
             texture_bind_group_layout: device.create_bind_group_layout(
                 &wgpu::BindGroupLayoutDescriptor {
                     entries: &[
-                        wgpu::BindGroupLayoutEntry {
-                            binding: 0,
-                            visibility: wgpu::ShaderStages::FRAGMENT,
-                            ty: wgpu::BindingType::Texture {
-                                multisampled: false,
-                                view_dimension: wgpu::TextureViewDimension::D2,
-                                sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                            },
-                            count: None,
-                        },
+                        texture_entry(0, wgpu::TextureViewDimension::D2),
                         wgpu::BindGroupLayoutEntry {
                             binding: 1,
                             visibility: wgpu::ShaderStages::FRAGMENT,
                             ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
                             count: None,
                         },
+                        uniform_entry(2, wgpu::ShaderStages::FRAGMENT),
+                        texture_entry(3, wgpu::TextureViewDimension::D2),
+                        texture_entry(4, wgpu::TextureViewDimension::D2),
+                        texture_entry(5, wgpu::TextureViewDimension::D2),
+                        texture_entry(6, wgpu::TextureViewDimension::D2),
+                        texture_entry(7, wgpu::TextureViewDimension::D2),
                     ],
                     label: Some("texture_bind_group_layout"),
                 },
             ),
             camera_bind_group_layout: device.create_bind_group_layout(
                 &wgpu::BindGroupLayoutDescriptor {
-                    entries: &[wgpu::BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: wgpu::ShaderStages::VERTEX,
-                        ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Uniform,
-                            has_dynamic_offset: false,
-                            min_binding_size: None,
-                        },
-                        count: None,
-                    }],
+                    entries: &[uniform_entry(0, wgpu::ShaderStages::VERTEX_FRAGMENT)],
                     label: Some("camera_bind_group_layout"),
                 },
             ),
+            light_bind_group_layout: device.create_bind_group_layout(
+                &wgpu::BindGroupLayoutDescriptor {
+                    entries: &[
+                        uniform_entry(0, wgpu::ShaderStages::FRAGMENT),
+                        texture_entry(1, wgpu::TextureViewDimension::Cube),
+                        texture_entry(2, wgpu::TextureViewDimension::Cube),
+                        texture_entry(3, wgpu::TextureViewDimension::D2),
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 4,
+                            visibility: wgpu::ShaderStages::FRAGMENT,
+                            ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                            count: None,
+                        },
+                    ],
+                    label: Some("light_bind_group_layout"),
+                },
+            ),
+        };
+
+        manager.register_builtin_textures(device, queue);
+        manager
+    }
+
+    fn register_builtin_textures(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        let solid = |color: [u8; 4]| {
+            image::DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(1, 1, image::Rgba(color)))
+        };
+
+        let checkerboard =
+            image::DynamicImage::ImageRgba8(image::RgbaImage::from_fn(8, 8, |x, y| {
+                if (x + y) % 2 == 0 {
+                    image::Rgba([255, 0, 255, 255])
+                } else {
+                    image::Rgba([0, 0, 0, 255])
+                }
+            }));
+
+        let nearest = SamplerOptions {
+            mag_filter: wgpu::FilterMode::Nearest,
+            min_filter: wgpu::FilterMode::Nearest,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        };
+
+        let builtins = [
+            (WHITE_TEXTURE, solid([255, 255, 255, 255]), ColorSpace::Srgb),
+            (
+                FLAT_NORMAL_TEXTURE,
+                solid([128, 128, 255, 255]),
+                ColorSpace::Linear,
+            ),
+            (BLACK_TEXTURE, solid([0, 0, 0, 255]), ColorSpace::Srgb),
+            (MISSING_TEXTURE, checkerboard, ColorSpace::Srgb),
+        ];
+
+        for (name, img, color_space) in builtins {
+            let texture = self.add_texture(
+                &img,
+                &name.to_string(),
+                &nearest,
+                color_space,
+                device,
+                queue,
+            );
+            self.textures.insert(name.to_string(), Arc::new(texture));
         }
     }
 
-    pub fn add_shader(
+    /// A texture registered under a name, such as one of the built-in textures.
+    pub fn get_texture(&self, name: &str) -> Option<Arc<Texture>> {
+        self.textures.get(name).cloned()
+    }
+
+    pub fn missing_texture(&self) -> Arc<Texture> {
+        self.get_texture(MISSING_TEXTURE)
+            .expect("built-in textures are registered on creation")
+    }
+
+    /// Loads a texture file, or returns the texture already loaded from it with the same
+    /// settings.
+    pub fn load_texture(
         &self,
-        name: impl Into<String>,
+        path: &Path,
+        options: &SamplerOptions,
+        color_space: ColorSpace,
         device: &wgpu::Device,
-        config: &wgpu::SurfaceConfiguration,
-    ) -> Shader {
-        let name = name.into();
+        queue: &wgpu::Queue,
+    ) -> Result<Arc<Texture>, TextureError> {
+        let key = (path.to_path_buf(), color_space, *options);
+        if let Some(texture) = self
+            .loaded_textures
+            .lock()
+            .unwrap()
+            .get(&key)
+            .and_then(Weak::upgrade)
+        {
+            return Ok(texture);
+        }
 
-        let file = format!("{name}.wgsl");
-        let source = std::fs::read_to_string(file).expect("Couldn't read shader file.");
+        let texture = Arc::new(self.add_texture_from_path(
+            &path.to_string_lossy().into_owned(),
+            options,
+            color_space,
+            device,
+            queue,
+        )?);
+        self.loaded_textures
+            .lock()
+            .unwrap()
+            .insert(key, Arc::downgrade(&texture));
+        Ok(texture)
+    }
+
+    /// Creates the bind group of a material, substituting the built-in textures for missing
+    /// maps.
+    pub fn create_material_bind_group(
+        &self,
+        material: &PbrMaterial,
+        device: &wgpu::Device,
+    ) -> wgpu::BindGroup {
+        let uniform = MaterialUniform {
+            base_color: material.base_color,
+            emissive: material.emissive,
+            normal_scale: material.normal_scale,
+            metallic: material.metallic,
+            roughness: material.roughness,
+            occlusion_strength: material.occlusion_strength,
+            alpha_cutoff: material.alpha_mode.cutoff(),
+            occlusion_tex_coord: material.occlusion_tex_coord,
+            _padding: [0; 3],
+        };
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Material Buffer"),
+            contents: bytemuck::cast_slice(&[uniform]),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+
+        let or_builtin = |texture: &Option<Arc<Texture>>, builtin: &str| match texture {
+            Some(texture) => texture.clone(),
+            None => self
+                .get_texture(builtin)
+                .expect("built-in textures are registered on creation"),
+        };
+        let base_color = or_builtin(&material.base_color_texture, WHITE_TEXTURE);
+        let metallic = or_builtin(&material.metallic_texture, WHITE_TEXTURE);
+        let roughness = or_builtin(&material.roughness_texture, WHITE_TEXTURE);
+        let normal = or_builtin(&material.normal_texture, FLAT_NORMAL_TEXTURE);
+        let occlusion = or_builtin(&material.occlusion_texture, WHITE_TEXTURE);
+        let emissive = or_builtin(&material.emissive_texture, WHITE_TEXTURE);
+
+        fn view(binding: u32, texture: &Texture) -> wgpu::BindGroupEntry<'_> {
+            wgpu::BindGroupEntry {
+                binding,
+                resource: wgpu::BindingResource::TextureView(&texture.view),
+            }
+        }
 
-        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: Some(&name),
-            source: wgpu::ShaderSource::Wgsl(source.into()),
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.texture_bind_group_layout,
+            entries: &[
+                view(0, &base_color),
+                // Every map is sampled with the base colour map's sampler
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&base_color.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: buffer.as_entire_binding(),
+                },
+                view(3, &metallic),
+                view(4, &roughness),
+                view(5, &normal),
+                view(6, &occlusion),
+                view(7, &emissive),
+            ],
+            label: Some(&material.name),
+        })
+    }
+
+    /// The scene shader's pipelines for meshes of each of `vertex_layouts`, drawn into targets of
+    /// `format` with `sample_count` samples. They're only built the first time they're needed,
+    /// and built again when the format or sample count changes.
+    pub fn scene_pipelines(
+        &self,
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+        vertex_layouts: impl IntoIterator<Item = VertexLayout>,
+    ) -> ScenePipelines<'_> {
+        let mut pipelines = self.scene_pipelines.lock().unwrap();
+        pipelines.retain(|&(_, other_format, other_sample_count), _| {
+            other_format == format && other_sample_count == sample_count
         });
+        for vertex_layout in vertex_layouts {
+            pipelines
+                .entry((vertex_layout, format, sample_count))
+                .or_insert_with(|| {
+                    self.create_scene_pipelines(device, vertex_layout, format, sample_count)
+                });
+        }
+
+        ScenePipelines {
+            pipelines,
+            format,
+            sample_count,
+        }
+    }
 
-        let layout_name = format!("{} Layout", name);
+    /// The opaque, cutout and blend pipelines of the scene shader for one vertex layout.
+    fn create_scene_pipelines(
+        &self,
+        device: &wgpu::Device,
+        vertex_layout: VertexLayout,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+    ) -> [wgpu::RenderPipeline; 3] {
+        let shader = &self.scene_shader;
         let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-            label: Some(&layout_name),
+            label: Some("Scene Pipeline Layout"),
             bind_group_layouts: &[
                 &self.texture_bind_group_layout,
                 &self.camera_bind_group_layout,
+                &self.light_bind_group_layout,
             ],
             push_constant_ranges: &[],
         });
 
-        let pipeline_name = format!("{} Pipeline", name);
-        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some(&pipeline_name),
-            layout: Some(&layout),
-            vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: "vs_main",           // 1.
-                buffers: &[Vertex::descriptor()], // 2.
-            },
-            fragment: Some(wgpu::FragmentState {
-                // 3.
-                module: &shader,
-                entry_point: "fs_main",
-                targets: &[Some(wgpu::ColorTargetState {
-                    // 4.
-                    format: config.format,
-                    blend: Some(wgpu::BlendState::REPLACE),
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
-            }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList, // 1.
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw, // 2.
-                cull_mode: Some(wgpu::Face::Back),
-                // Setting this to anything other than Fill requires Features::NON_FILL_POLYGON_MODE
-                polygon_mode: wgpu::PolygonMode::Fill,
-                // Requires Features::DEPTH_CLIP_CONTROL
-                unclipped_depth: false,
-                // Requires Features::CONSERVATIVE_RASTERIZATION
-                conservative: false,
-            },
-            depth_stencil: None, // 1.
-            multisample: wgpu::MultisampleState {
-                count: 1,                         // 2.
-                mask: !0,                         // 3.
-                alpha_to_coverage_enabled: false, // 4.
-            },
-            multiview: None, // 5.
-        });
+        // Points have no back to cull
+        let (topology, cull_mode) = if vertex_layout.points {
+            (wgpu::PrimitiveTopology::PointList, None)
+        } else {
+            (
+                wgpu::PrimitiveTopology::TriangleList,
+                Some(wgpu::Face::Back),
+            )
+        };
+        let create_pipeline = |variant: &str, entry_point, blend, depth_write_enabled| {
+            let pipeline_name = format!("Scene {} {:?} Pipeline", variant, vertex_layout);
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(&pipeline_name),
+                layout: Some(&layout),
+                vertex: wgpu::VertexState {
+                    module: shader,
+                    entry_point: vertex_layout.entry_point(),
+                    buffers: &vertex_layout.buffers(),
+                },
+                fragment: Some(wgpu::FragmentState {
+                    // 3.
+                    module: shader,
+                    entry_point,
+                    targets: &[Some(wgpu::ColorTargetState {
+                        // 4.
+                        format,
+                        blend: Some(blend),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                primitive: wgpu::PrimitiveState {
+                    topology, // 1.
+                    strip_index_format: None,
+                    front_face: wgpu::FrontFace::Ccw, // 2.
+                    cull_mode,
+                    // Setting this to anything other than Fill requires Features::NON_FILL_POLYGON_MODE
+                    polygon_mode: wgpu::PolygonMode::Fill,
+                    // Requires Features::DEPTH_CLIP_CONTROL
+                    unclipped_depth: false,
+                    // Requires Features::CONSERVATIVE_RASTERIZATION
+                    conservative: false,
+                },
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: DEPTH_FORMAT,
+                    depth_write_enabled,
+                    depth_compare: wgpu::CompareFunction::Less,
+                    stencil: wgpu::StencilState::default(),
+                    bias: wgpu::DepthBiasState::default(),
+                }),
+                multisample: wgpu::MultisampleState {
+                    count: sample_count,              // 2.
+                    mask: !0,                         // 3.
+                    alpha_to_coverage_enabled: false, // 4.
+                },
+                multiview: None, // 5.
+            })
+        };
 
-        Shader {
-            shader,
-            layout,
-            pipeline,
-        }
+        [
+            create_pipeline("Opaque", "fs_main", wgpu::BlendState::REPLACE, true),
+            create_pipeline("Cutout", "fs_cutout", wgpu::BlendState::REPLACE, true),
+            // Blended surfaces don't hide what's behind them, so they don't write depth
+            create_pipeline("Blend", "fs_blend", wgpu::BlendState::ALPHA_BLENDING, false),
+        ]
     }
 
+    /// Loads and uploads a texture file. See `TextureData::load` for the supported formats.
     pub fn add_texture_from_path(
         &self,
         path: &String,
+        options: &SamplerOptions,
+        color_space: ColorSpace,
         device: &wgpu::Device,
         queue: &wgpu::Queue,
-    ) -> Texture {
-        let img = image::open(path).expect("Couldn't open texture file.");
-        self.add_texture(&img, path, device, queue)
+    ) -> Result<Texture, TextureError> {
+        let data = TextureData::load(path, color_space, !self.gpu_mipmaps)?;
+        self.add_texture_data(&data, path, options, device, queue)
     }
 
     pub fn add_texture(
         &self,
         img: &image::DynamicImage,
         name: &String,
+        options: &SamplerOptions,
+        color_space: ColorSpace,
         device: &wgpu::Device,
         queue: &wgpu::Queue,
     ) -> Texture {
-        let label = format!("{} Texture", name);
-        let rgba = img.to_rgba8();
-        let dimensions = img.dimensions();
+        let data = TextureData::from_image(img, color_space, !self.gpu_mipmaps);
+        self.add_texture_data(&data, name, options, device, queue)
+            .expect("Decoded images use uncompressed formats")
+    }
 
-        let size = wgpu::Extent3d {
-            width: dimensions.0,
-            height: dimensions.1,
-            depth_or_array_layers: 1,
+    /// Uploads texels with a full mip chain. Missing mip levels of uncompressed textures are
+    /// generated on the GPU.
+    pub fn add_texture_data(
+        &self,
+        data: &TextureData,
+        name: &String,
+        options: &SamplerOptions,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Result<Texture, TextureError> {
+        let missing = data.format.describe().required_features - device.features();
+        if !missing.is_empty() {
+            return Err(TextureError::MissingFeatures {
+                format: data.format,
+                missing,
+            });
+        }
+
+        let label = format!("{} Texture", name);
+        let mip_level_count = if data.generate_mipmaps {
+            data.size.max_mips(wgpu::TextureDimension::D2)
+        } else {
+            data.levels.len() as u32
         };
+
+        let mut usage = wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST;
+        if data.generate_mipmaps {
+            usage |= wgpu::TextureUsages::RENDER_ATTACHMENT;
+        }
+
         let texture = device.create_texture(&wgpu::TextureDescriptor {
             label: Some(&label),
-            size,
-            mip_level_count: 1,
+            size: data.size,
+            mip_level_count,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba8UnormSrgb,
-            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            format: data.format,
+            usage,
             view_formats: &[],
         });
 
-        queue.write_texture(
-            wgpu::ImageCopyTexture {
-                aspect: wgpu::TextureAspect::All,
-                texture: &texture,
-                mip_level: 0,
-                origin: wgpu::Origin3d::ZERO,
-            },
-            &rgba,
-            wgpu::ImageDataLayout {
-                offset: 0,
-                bytes_per_row: std::num::NonZeroU32::new(4 * dimensions.0),
-                rows_per_image: std::num::NonZeroU32::new(dimensions.1),
-            },
-            size,
-        );
+        for (mip, level) in data.levels.iter().enumerate() {
+            let mip = mip as u32;
+            let (bytes_per_row, rows) = data.level_layout(mip);
+
+            queue.write_texture(
+                wgpu::ImageCopyTexture {
+                    aspect: wgpu::TextureAspect::All,
+                    texture: &texture,
+                    mip_level: mip,
+                    origin: wgpu::Origin3d::ZERO,
+                },
+                level,
+                wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: std::num::NonZeroU32::new(bytes_per_row),
+                    rows_per_image: std::num::NonZeroU32::new(rows),
+                },
+                data.size
+                    .mip_level_size(mip, wgpu::TextureDimension::D2)
+                    .physical_size(data.format),
+            );
+        }
+
+        if data.generate_mipmaps {
+            self.generate_mipmaps(&texture, data.format, mip_level_count, device, queue);
+        }
 
         let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
-        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
-            address_mode_u: wgpu::AddressMode::ClampToEdge,
-            address_mode_v: wgpu::AddressMode::ClampToEdge,
-            address_mode_w: wgpu::AddressMode::ClampToEdge,
-            mag_filter: wgpu::FilterMode::Linear,
-            min_filter: wgpu::FilterMode::Nearest,
-            mipmap_filter: wgpu::FilterMode::Nearest,
-            ..Default::default()
-        });
+        let sampler = self.create_sampler(options, device);
 
-        Texture {
+        Ok(Texture {
             texture,
             view,
             sampler,
+        })
+    }
+
+    pub fn create_sampler(&self, options: &SamplerOptions, device: &wgpu::Device) -> wgpu::Sampler {
+        let all_linear = options.mag_filter == wgpu::FilterMode::Linear
+            && options.min_filter == wgpu::FilterMode::Linear
+            && options.mipmap_filter == wgpu::FilterMode::Linear;
+
+        let anisotropy_clamp = if self.supports_anisotropy && all_linear {
+            std::num::NonZeroU8::new(options.anisotropy).filter(|a| a.get() > 1)
+        } else {
+            None
+        };
+
+        device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: options.address_mode,
+            address_mode_v: options.address_mode,
+            address_mode_w: options.address_mode,
+            mag_filter: options.mag_filter,
+            min_filter: options.min_filter,
+            mipmap_filter: options.mipmap_filter,
+            anisotropy_clamp,
+            ..Default::default()
+        })
+    }
+
+    /// Fills mip levels 1 and up by repeatedly downsampling the previous level.
+    fn generate_mipmaps(
+        &self,
+        texture: &wgpu::Texture,
+        format: wgpu::TextureFormat,
+        mip_level_count: u32,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) {
+        if mip_level_count <= 1 {
+            return;
         }
+
+        let mut pipelines = self.blit_pipelines.lock().unwrap();
+        let pipeline = pipelines
+            .entry(format)
+            .or_insert_with(|| self.create_blit_pipeline(format, device));
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Mipmap Encoder"),
+        });
+
+        let views = (0..mip_level_count)
+            .map(|mip| {
+                texture.create_view(&wgpu::TextureViewDescriptor {
+                    label: Some("Mipmap View"),
+                    base_mip_level: mip,
+                    mip_level_count: std::num::NonZeroU32::new(1),
+                    ..Default::default()
+                })
+            })
+            .collect::<Vec<_>>();
+
+        for target in 1..mip_level_count as usize {
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                layout: &pipeline.get_bind_group_layout(0),
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&views[target - 1]),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Sampler(&self.mipmap_sampler),
+                    },
+                ],
+                label: None,
+            });
+
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Mipmap Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &views[target],
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: None,
+            });
+
+            render_pass.set_pipeline(pipeline);
+            render_pass.set_bind_group(0, &bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+
+        queue.submit(std::iter::once(encoder.finish()));
+    }
+
+    fn create_blit_pipeline(
+        &self,
+        format: wgpu::TextureFormat,
+        device: &wgpu::Device,
+    ) -> wgpu::RenderPipeline {
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Blit Pipeline"),
+            layout: None,
+            vertex: wgpu::VertexState {
+                module: &self.blit_shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &self.blit_shader,
+                entry_point: "fs_main",
+                targets: &[Some(format.into())],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        })
     }
 
     pub fn get_texture_bind_group_layout(&self) -> &wgpu::BindGroupLayout {
@@ -215,4 +681,37 @@ impl MaterialManager {
     pub fn get_camera_bind_group_layout(&self) -> &wgpu::BindGroupLayout {
         &self.camera_bind_group_layout
     }
+
+    pub fn get_light_bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.light_bind_group_layout
+    }
+}
+
+fn texture_entry(
+    binding: u32,
+    view_dimension: wgpu::TextureViewDimension,
+) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Texture {
+            multisampled: false,
+            view_dimension,
+            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+        },
+        count: None,
+    }
+}
+
+fn uniform_entry(binding: u32, visibility: wgpu::ShaderStages) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    }
 }
@@ -0,0 +1,157 @@
+use crate::material_manager::SamplerOptions;
+
+/// A texture statement from an MTL file, such as `map_Kd -clamp on diffuse.png`, split into the
+/// file name and the sampler options it implies.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextureMap {
+    pub path: String,
+    pub options: SamplerOptions,
+}
+
+impl TextureMap {
+    /// Parses the arguments of a texture statement. Options that grt has no use for are skipped
+    /// along with their values, and everything after the options is the path, spaces included.
+    pub fn parse(statement: &str) -> Self {
+        let mut options = SamplerOptions::default();
+        let mut tokens = tokens(statement).peekable();
+        let mut path = "";
+
+        while let Some((offset, token)) = tokens.next() {
+            if !token.starts_with('-') {
+                path = statement[offset..].trim_end();
+                break;
+            }
+
+            // Options with values that are only sometimes given, such as the components of `-s`,
+            // take as many as look like values
+            let mut skip_values = |count: usize, is_value: fn(&str) -> bool| {
+                for _ in 0..count {
+                    match tokens.peek() {
+                        Some((_, value)) if is_value(value) => {
+                            tokens.next();
+                        }
+                        _ => break,
+                    }
+                }
+            };
+
+            match token {
+                "-clamp" => {
+                    if let Some((_, "on")) = tokens.next_if(|(_, value)| is_switch(value)) {
+                        options.address_mode = wgpu::AddressMode::ClampToEdge;
+                    }
+                }
+                "-blendu" | "-blendv" | "-cc" => skip_values(1, is_switch),
+                "-boost" | "-texres" | "-bm" => skip_values(1, is_number),
+                "-mm" => skip_values(2, is_number),
+                "-o" | "-s" | "-t" => skip_values(3, is_number),
+                "-imfchan" => skip_values(1, |value| {
+                    matches!(value, "r" | "g" | "b" | "m" | "l" | "z")
+                }),
+                "-type" => {
+                    tokens.next();
+                }
+                _ => {
+                    log::warn!("Unknown texture option {:?} in {:?}", token, statement);
+                    skip_values(usize::MAX, |value| is_number(value) || is_switch(value));
+                }
+            }
+        }
+
+        Self {
+            path: path.to_string(),
+            options,
+        }
+    }
+}
+
+/// The whitespace-separated tokens of a statement, with the byte offsets they start at.
+fn tokens(statement: &str) -> impl Iterator<Item = (usize, &str)> {
+    statement
+        .split_whitespace()
+        .map(move |token| (token.as_ptr() as usize - statement.as_ptr() as usize, token))
+}
+
+fn is_number(token: &str) -> bool {
+    token.parse::<f32>().is_ok()
+}
+
+fn is_switch(token: &str) -> bool {
+    token == "on" || token == "off"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(statement: &str) -> (String, wgpu::AddressMode) {
+        let map = TextureMap::parse(statement);
+        (map.path, map.options.address_mode)
+    }
+
+    #[test]
+    fn options_are_skipped_with_their_values() {
+        let repeat = wgpu::AddressMode::Repeat;
+        for statement in [
+            "-s 2 2 1 wood.png",
+            "-s 2 wood.png",
+            "-o 0.5 0.5 wood.png",
+            "-s 2 2 -o 0.1 0.2 0.3 -t 0.01 wood.png",
+            "-mm 0 1 -boost 1.5 -texres 512 wood.png",
+            "-blendu off -blendv on -cc on wood.png",
+            "-bm 0.5 -imfchan r -type sphere wood.png",
+        ] {
+            assert_eq!(
+                parse(statement),
+                ("wood.png".to_string(), repeat),
+                "{statement}"
+            );
+        }
+    }
+
+    #[test]
+    fn clamp_sets_the_address_mode() {
+        let clamped = wgpu::AddressMode::ClampToEdge;
+        let repeat = wgpu::AddressMode::Repeat;
+        assert_eq!(
+            parse("-clamp on wood.png"),
+            ("wood.png".to_string(), clamped)
+        );
+        assert_eq!(
+            parse("-clamp off wood.png"),
+            ("wood.png".to_string(), repeat)
+        );
+        assert_eq!(
+            parse("-s 2 -clamp on -o 1 wood.png"),
+            ("wood.png".to_string(), clamped)
+        );
+    }
+
+    #[test]
+    fn unknown_options_are_skipped_with_their_values() {
+        let repeat = wgpu::AddressMode::Repeat;
+        for statement in [
+            "-halo wood.png",
+            "-halo 0.5 wood.png",
+            "-halo 1 2 3 on wood.png",
+            "-halo -0.5 -clamp off wood.png",
+        ] {
+            assert_eq!(
+                parse(statement),
+                ("wood.png".to_string(), repeat),
+                "{statement}"
+            );
+        }
+    }
+
+    #[test]
+    fn paths_keep_their_spaces() {
+        assert_eq!(
+            parse("textures/old  oak wood.png").0,
+            "textures/old  oak wood.png"
+        );
+        assert_eq!(parse("-s 2 2 old oak.png  ").0, "old oak.png");
+        assert_eq!(parse("-clamp on my -s.png").0, "my -s.png");
+        assert_eq!(parse("-clamp on").0, "");
+    }
+}
@@ -0,0 +1,163 @@
+use clap::{Parser, ValueEnum};
+
+use crate::config::EngineConfig;
+
+/// Command-line options of the grt binary.
+#[derive(Parser, Debug, Clone)]
+#[command(name = "grt", version, about = "A small wgpu renderer")]
+pub struct Options {
+    /// The scene (.ron) or model (.obj, .gltf, .glb, .ply or .stl) to open
+    #[arg(default_value = "cube.ron")]
+    pub path: String,
+
+    /// A directory to search for models and textures; may be given several times
+    #[arg(long = "asset-path", value_name = "DIR")]
+    pub asset_paths: Vec<String>,
+
+    /// An equirectangular HDR image that lights the scene, replacing the scene's own environment
+    #[arg(long, value_name = "FILE")]
+    pub environment: Option<String>,
+
+    /// Where pressing F5 saves the running scene
+    #[arg(long, default_value = "saved.ron")]
+    pub save_path: String,
+
+    /// Width of the window, or of the output image when headless
+    #[arg(long, default_value_t = 800)]
+    pub width: u32,
+
+    /// Height of the window, or of the output image when headless
+    #[arg(long, default_value_t = 600)]
+    pub height: u32,
+
+    /// Samples per pixel for multisample anti-aliasing: 1, 2, 4 or 8. Lowered to what the GPU
+    /// supports
+    #[arg(long = "msaa", value_name = "SAMPLES", default_value_t = 4, value_parser = parse_sample_count)]
+    pub sample_count: u32,
+
+    /// Weld duplicate vertices and reorder the triangles of models as they're loaded, so that
+    /// they draw faster
+    #[arg(long)]
+    pub optimize_meshes: bool,
+
+    /// Cache parsed OBJ files in this directory, so that they load faster when they haven't
+    /// changed. Without it, they're parsed every time
+    #[arg(long, value_name = "DIR")]
+    pub mesh_cache: Option<String>,
+
+    /// How frames are presented to the window
+    #[arg(long, value_enum, default_value_t = PresentMode::Fifo)]
+    pub present_mode: PresentMode,
+
+    /// Which graphics API to render with
+    #[arg(long, value_enum, default_value_t = Backend::All)]
+    pub backend: Backend,
+
+    /// Which kind of GPU to prefer when several are available
+    #[arg(long, value_enum, default_value_t = PowerPreference::Default)]
+    pub power_preference: PowerPreference,
+
+    /// Use a software adapter instead of a GPU
+    #[arg(long)]
+    pub fallback_adapter: bool,
+
+    /// Render this many frames without opening a window, then exit
+    #[arg(long, value_name = "FRAMES")]
+    pub headless: Option<u32>,
+
+    /// Where the last headless frame is written as a PNG
+    #[arg(long, default_value = "output.png", requires = "headless")]
+    pub output: String,
+
+    /// Log filter, in env_logger syntax
+    #[arg(long, env = "RUST_LOG", default_value = "warn,grt=debug")]
+    pub log_level: String,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self::parse_from(["grt"])
+    }
+}
+
+impl Options {
+    pub fn engine_config(&self) -> EngineConfig {
+        EngineConfig {
+            present_modes: self.present_mode.to_wgpu(),
+            backends: self.backend.to_wgpu(),
+            power_preference: self.power_preference.to_wgpu(),
+            force_fallback_adapter: self.fallback_adapter,
+            ..Default::default()
+        }
+    }
+}
+
+fn parse_sample_count(value: &str) -> Result<u32, String> {
+    match value.parse() {
+        Ok(count @ (1 | 2 | 4 | 8)) => Ok(count),
+        _ => Err("must be 1, 2, 4 or 8".to_string()),
+    }
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PresentMode {
+    /// Vsync, never tears
+    Fifo,
+    /// Low latency without tearing, if supported
+    Mailbox,
+    /// No vsync, may tear
+    Immediate,
+}
+
+impl PresentMode {
+    /// The present modes to try, in order of preference. Fifo is always the last resort.
+    pub fn to_wgpu(self) -> Vec<wgpu::PresentMode> {
+        match self {
+            PresentMode::Fifo => vec![wgpu::PresentMode::Fifo],
+            PresentMode::Mailbox => vec![wgpu::PresentMode::Mailbox],
+            PresentMode::Immediate => vec![wgpu::PresentMode::Immediate],
+        }
+    }
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    All,
+    Primary,
+    Vulkan,
+    Metal,
+    Dx12,
+    Dx11,
+    Gl,
+}
+
+impl Backend {
+    pub fn to_wgpu(self) -> wgpu::Backends {
+        match self {
+            Backend::All => wgpu::Backends::all(),
+            Backend::Primary => wgpu::Backends::PRIMARY,
+            Backend::Vulkan => wgpu::Backends::VULKAN,
+            Backend::Metal => wgpu::Backends::METAL,
+            Backend::Dx12 => wgpu::Backends::DX12,
+            Backend::Dx11 => wgpu::Backends::DX11,
+            Backend::Gl => wgpu::Backends::GL,
+        }
+    }
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerPreference {
+    Default,
+    LowPower,
+    HighPerformance,
+}
+
+impl PowerPreference {
+    pub fn to_wgpu(self) -> wgpu::PowerPreference {
+        match self {
+            PowerPreference::Default => wgpu::PowerPreference::default(),
+            PowerPreference::LowPower => wgpu::PowerPreference::LowPower,
+            PowerPreference::HighPerformance => wgpu::PowerPreference::HighPerformance,
+        }
+    }
+}
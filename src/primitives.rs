@@ -0,0 +1,526 @@
+//! Meshes of simple shapes, generated instead of loaded from a file.
+//!
+//! Every shape fits in a unit cube centred on the origin, except the capsule, which is as tall as
+//! it's told to be. Texture coordinates run to the right and down across a surface seen from
+//! outside, and seams and poles have vertices of their own, so that textures and tangents are
+//! continuous everywhere else.
+
+use std::collections::HashMap;
+use std::f32::consts::{PI, TAU};
+
+use cgmath::{InnerSpace, Vector3};
+
+use crate::{
+    components::{primitive::Primitive, rendering::Vertex},
+    import::{compute_tangents, MeshData},
+};
+
+/// The most squares across each face of a cube or plane, about 130 thousand triangles a face.
+pub const MAX_GRID_SUBDIVISIONS: u32 = 256;
+/// The most times an icosphere's triangles are split, about 330 thousand triangles.
+pub const MAX_ICOSPHERE_SUBDIVISIONS: u32 = 7;
+/// The most segments, rings or sides around a shape, about half a million triangles for a
+/// sphere or torus with as many of both.
+pub const MAX_SEGMENTS: u32 = 512;
+
+/// Generates the mesh of a `Primitive` component.
+pub fn generate(primitive: &Primitive) -> MeshData {
+    match *primitive {
+        Primitive::Cube { subdivisions } => cube(subdivisions),
+        Primitive::UvSphere { segments, rings } => uv_sphere(segments, rings),
+        Primitive::Icosphere { subdivisions } => icosphere(subdivisions),
+        Primitive::Plane { subdivisions } => plane(subdivisions),
+        Primitive::Cylinder { segments } => cylinder(segments),
+        Primitive::Cone { segments } => cone(segments),
+        Primitive::Torus {
+            segments,
+            sides,
+            radius,
+            tube_radius,
+        } => torus(segments, sides, radius, tube_radius),
+        Primitive::Capsule {
+            segments,
+            rings,
+            height,
+        } => capsule(segments, rings, height),
+    }
+}
+
+/// A cube with each face divided into a grid of `subdivisions` by `subdivisions` squares.
+pub fn cube(subdivisions: u32) -> MeshData {
+    let subdivisions = clamp("cube subdivisions", subdivisions, 1, MAX_GRID_SUBDIVISIONS);
+    let mut builder = Builder::default();
+    // Each face's normal, and the directions its texture's right and down point in
+    let faces = [
+        ([1.0, 0.0, 0.0], [0.0, 0.0, -1.0], [0.0, -1.0, 0.0]),
+        ([-1.0, 0.0, 0.0], [0.0, 0.0, 1.0], [0.0, -1.0, 0.0]),
+        ([0.0, 1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]),
+        ([0.0, -1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, -1.0]),
+        ([0.0, 0.0, 1.0], [1.0, 0.0, 0.0], [0.0, -1.0, 0.0]),
+        ([0.0, 0.0, -1.0], [-1.0, 0.0, 0.0], [0.0, -1.0, 0.0]),
+    ];
+    for (normal, right, down) in faces {
+        let [normal, right, down] = [normal, right, down].map(Vector3::from);
+        builder.grid(subdivisions, subdivisions, |column, row| {
+            let u = column as f32 / subdivisions as f32;
+            let v = row as f32 / subdivisions as f32;
+            let position = (normal + right * (2.0 * u - 1.0) + down * (2.0 * v - 1.0)) * 0.5;
+            vertex(position, normal, [u, v])
+        });
+    }
+    builder.finish("cube")
+}
+
+/// A sphere of `segments` around its axis and `rings` from pole to pole.
+pub fn uv_sphere(segments: u32, rings: u32) -> MeshData {
+    let segments = clamp("uv sphere segments", segments, 3, MAX_SEGMENTS);
+    let rings = clamp("uv sphere rings", rings, 2, MAX_SEGMENTS);
+    let mut builder = Builder::default();
+    builder.grid(segments, rings, |column, row| {
+        let u = column as f32 / segments as f32;
+        let v = row as f32 / rings as f32;
+        let normal = spherical(u * TAU, v * PI);
+        vertex(normal * 0.5, normal, [u, v])
+    });
+    builder.finish("uv_sphere")
+}
+
+/// A sphere of evenly sized triangles, from an icosahedron whose triangles are each split into
+/// four `subdivisions` times.
+pub fn icosphere(subdivisions: u32) -> MeshData {
+    let subdivisions = clamp(
+        "icosphere subdivisions",
+        subdivisions,
+        0,
+        MAX_ICOSPHERE_SUBDIVISIONS,
+    );
+    let t = (1.0 + 5.0f32.sqrt()) / 2.0;
+    let mut points = [
+        [-1.0, t, 0.0],
+        [1.0, t, 0.0],
+        [-1.0, -t, 0.0],
+        [1.0, -t, 0.0],
+        [0.0, -1.0, t],
+        [0.0, 1.0, t],
+        [0.0, -1.0, -t],
+        [0.0, 1.0, -t],
+        [t, 0.0, -1.0],
+        [t, 0.0, 1.0],
+        [-t, 0.0, -1.0],
+        [-t, 0.0, 1.0],
+    ]
+    .map(|point| Vector3::from(point).normalize())
+    .to_vec();
+    let mut triangles = vec![
+        [0, 11, 5],
+        [0, 5, 1],
+        [0, 1, 7],
+        [0, 7, 10],
+        [0, 10, 11],
+        [1, 5, 9],
+        [5, 11, 4],
+        [11, 10, 2],
+        [10, 7, 6],
+        [7, 1, 8],
+        [3, 9, 4],
+        [3, 4, 2],
+        [3, 2, 6],
+        [3, 6, 8],
+        [3, 8, 9],
+        [4, 9, 5],
+        [2, 4, 11],
+        [6, 2, 10],
+        [8, 6, 7],
+        [9, 8, 1],
+    ];
+
+    for _ in 0..subdivisions {
+        // Shared, so that neighbouring triangles split their edge at the same point
+        let mut midpoints = HashMap::new();
+        let mut midpoint = |a: usize, b: usize| {
+            *midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                points.push((points[a] + points[b]).normalize());
+                points.len() - 1
+            })
+        };
+        triangles = triangles
+            .iter()
+            .flat_map(|&[a, b, c]| {
+                let [ab, bc, ca] = [midpoint(a, b), midpoint(b, c), midpoint(c, a)];
+                [[a, ab, ca], [b, bc, ab], [c, ca, bc], [ab, bc, ca]]
+            })
+            .collect();
+    }
+
+    // Mapped like the UV sphere. Triangles across the seam are split along it, and the seam's
+    // vertices have `u` of 1 on its east (+X) side and 0 on its west. The poles, where `u` is
+    // undefined, take the middle of the triangle's other corners
+    let mut builder = Builder::default();
+    let mut shared = HashMap::new();
+    let mut add = |normals: [Vector3<f32>; 3], east: bool| {
+        let is_pole = normals.map(|n| n.x.abs() < 1e-6 && n.z.abs() < 1e-6);
+        let mut us = normals.map(|n| match n.x == 0.0 && n.z < 0.0 {
+            true if east => 1.0,
+            true => 0.0,
+            false => (-n.x).atan2(-n.z).rem_euclid(TAU) / TAU,
+        });
+        if let Some(pole) = is_pole.iter().position(|&is_pole| is_pole) {
+            us[pole] = (0..3).filter(|&i| i != pole).map(|i| us[i]).sum::<f32>() / 2.0;
+        }
+
+        let indices = [0, 1, 2].map(|i| {
+            let normal = normals[i];
+            let u = us[i];
+            let v = normal.y.clamp(-1.0, 1.0).acos() / PI;
+            let key = (
+                [normal.x, normal.y, normal.z].map(f32::to_bits),
+                u.to_bits(),
+            );
+            *shared
+                .entry(key)
+                .or_insert_with(|| builder.vertex(vertex(normal * 0.5, normal, [u, v])))
+        });
+        builder.indices.extend(indices);
+    };
+    for triangle in triangles {
+        let normals = triangle.map(|i| points[i]);
+        let east = normals.iter().any(|n| n.x > 0.0);
+        let west = normals.iter().any(|n| n.x < 0.0);
+        let pieces = [true, false].map(|east| split(normals, east));
+        let crosses_seam = pieces[0].iter().any(|n| n.x == 0.0 && n.z < 0.0);
+        if east && west && crosses_seam {
+            for (piece, east) in pieces.iter().zip([true, false]) {
+                for i in 1..piece.len().saturating_sub(1) {
+                    add([piece[0], piece[i], piece[i + 1]], east);
+                }
+            }
+        } else {
+            add(normals, east);
+        }
+    }
+    builder.finish("icosphere")
+}
+
+/// The part of the triangle of unit vectors `corners` on the east (+X) or west side of the YZ
+/// plane, in order, with corners on the sphere where its edges cross the plane.
+fn split(corners: [Vector3<f32>; 3], east: bool) -> Vec<Vector3<f32>> {
+    let side = |corner: Vector3<f32>| if east { corner.x } else { -corner.x };
+    let mut piece = Vec::new();
+    for i in 0..3 {
+        let (a, b) = (corners[i], corners[(i + 1) % 3]);
+        if side(a) >= 0.0 {
+            piece.push(a);
+        }
+        if side(a) * side(b) < 0.0 {
+            // From the west end, so that the triangles on both sides of the edge split it at the
+            // same point
+            let (west, east) = if a.x < b.x { (a, b) } else { (b, a) };
+            let crossing = west + (east - west) * (west.x / (west.x - east.x));
+            piece.push(Vector3::new(0.0, crossing.y, crossing.z).normalize());
+        }
+    }
+    piece
+}
+
+/// A square in the XZ plane facing up, divided into a grid of `subdivisions` by `subdivisions`.
+pub fn plane(subdivisions: u32) -> MeshData {
+    let subdivisions = clamp("plane subdivisions", subdivisions, 1, MAX_GRID_SUBDIVISIONS);
+    let mut builder = Builder::default();
+    builder.grid(subdivisions, subdivisions, |column, row| {
+        let u = column as f32 / subdivisions as f32;
+        let v = row as f32 / subdivisions as f32;
+        vertex(
+            Vector3::new(u - 0.5, 0.0, v - 0.5),
+            Vector3::unit_y(),
+            [u, v],
+        )
+    });
+    builder.finish("plane")
+}
+
+/// A cylinder along Y, with `segments` around it and flat caps.
+pub fn cylinder(segments: u32) -> MeshData {
+    let segments = clamp("cylinder segments", segments, 3, MAX_SEGMENTS);
+    let mut builder = Builder::default();
+    builder.grid(segments, 1, |column, row| {
+        let u = column as f32 / segments as f32;
+        let normal = spherical(u * TAU, PI / 2.0);
+        let position = normal * 0.5 + Vector3::new(0.0, 0.5 - row as f32, 0.0);
+        vertex(position, normal, [u, row as f32])
+    });
+    builder.cap(segments, 0.5, true);
+    builder.cap(segments, -0.5, false);
+    builder.finish("cylinder")
+}
+
+/// A cone along Y with its point at the top, with `segments` around it and a flat base.
+pub fn cone(segments: u32) -> MeshData {
+    let segments = clamp("cone segments", segments, 3, MAX_SEGMENTS);
+    let mut builder = Builder::default();
+    builder.grid(segments, 1, |column, row| {
+        let u = column as f32 / segments as f32;
+        let around = spherical(u * TAU, PI / 2.0);
+        // The slope rises one unit over half a unit out
+        let normal = (around + Vector3::new(0.0, 0.5, 0.0)).normalize();
+        let position = around * 0.5 * row as f32 + Vector3::new(0.0, 0.5 - row as f32, 0.0);
+        vertex(position, normal, [u, row as f32])
+    });
+    builder.cap(segments, -0.5, false);
+    builder.finish("cone")
+}
+
+/// A ring around Y, `radius` from its centre to the middle of its tube, with `segments` around
+/// the ring and `sides` around the tube.
+pub fn torus(segments: u32, sides: u32, radius: f32, tube_radius: f32) -> MeshData {
+    let segments = clamp("torus segments", segments, 3, MAX_SEGMENTS);
+    let sides = clamp("torus sides", sides, 3, MAX_SEGMENTS);
+    let mut builder = Builder::default();
+    builder.grid(segments, sides, |column, row| {
+        let u = column as f32 / segments as f32;
+        let v = row as f32 / sides as f32;
+        // Around the tube from its outside edge, going down first
+        let (sin, cos) = (v * TAU).sin_cos();
+        let around = spherical(u * TAU, PI / 2.0);
+        let normal = around * cos - Vector3::unit_y() * sin;
+        vertex(around * radius + normal * tube_radius, normal, [u, v])
+    });
+    builder.finish("torus")
+}
+
+/// A cylinder along Y with a hemisphere on each end, half a unit wide and `height` tall, with
+/// `segments` around it and `rings` on each hemisphere.
+pub fn capsule(segments: u32, rings: u32, height: f32) -> MeshData {
+    let segments = clamp("capsule segments", segments, 3, MAX_SEGMENTS);
+    let rings = clamp("capsule rings", rings, 1, MAX_SEGMENTS);
+    let half = (height / 2.0 - 0.5).max(0.0);
+    // Each row's angle from the top and its hemisphere's centre, with the cylinder between the
+    // last row of the top and the first of the bottom
+    let rows = (0..=rings)
+        .map(|ring| (ring as f32 / rings as f32 * PI / 2.0, half))
+        .chain((0..=rings).map(|ring| ((1.0 + ring as f32 / rings as f32) * PI / 2.0, -half)))
+        .collect::<Vec<_>>();
+    // v follows the length of the outline, so that textures aren't stretched along the cylinder
+    let length = PI / 2.0 + 2.0 * half;
+    let v = |(angle, centre): (f32, f32)| (angle / 2.0 + half - centre) / length;
+
+    let mut builder = Builder::default();
+    builder.grid(segments, rows.len() as u32 - 1, |column, row| {
+        let u = column as f32 / segments as f32;
+        let (angle, centre) = rows[row as usize];
+        let normal = spherical(u * TAU, angle);
+        let position = normal * 0.5 + Vector3::new(0.0, centre, 0.0);
+        vertex(position, normal, [u, v(rows[row as usize])])
+    });
+    builder.finish("capsule")
+}
+
+/// Raises `count` to `min`, or lowers it to `max` with a warning, since scenes can ask for
+/// meshes far too large to generate.
+fn clamp(what: &str, count: u32, min: u32, max: u32) -> u32 {
+    if count > max {
+        log::warn!("Asked for {count} {what}, more than the most allowed, so {max} are used");
+    }
+    count.clamp(min, max)
+}
+
+/// The direction `polar` from +Y, and `azimuth` around it from -Z towards -X, so that the seams
+/// of shapes wrapped around Y face away from +Z.
+fn spherical(azimuth: f32, polar: f32) -> Vector3<f32> {
+    let (sin_azimuth, cos_azimuth) = azimuth.sin_cos();
+    let (sin_polar, cos_polar) = polar.sin_cos();
+    Vector3::new(
+        -sin_polar * sin_azimuth,
+        cos_polar,
+        -sin_polar * cos_azimuth,
+    )
+}
+
+fn vertex(position: Vector3<f32>, normal: Vector3<f32>, tex_coords: [f32; 2]) -> Vertex {
+    Vertex {
+        position: position.into(),
+        tex_coords,
+        normal: normal.into(),
+    }
+}
+
+#[derive(Default)]
+struct Builder {
+    vertices: Vec<Vertex>,
+    indices: Vec<u32>,
+}
+
+impl Builder {
+    fn vertex(&mut self, vertex: Vertex) -> u32 {
+        self.vertices.push(vertex);
+        self.vertices.len() as u32 - 1
+    }
+
+    /// Adds `columns + 1` by `rows + 1` vertices from `vertex`, with two triangles for each
+    /// square between them. Seen from the front, columns should run right and rows down.
+    fn grid(&mut self, columns: u32, rows: u32, vertex: impl Fn(u32, u32) -> Vertex) {
+        let first = self.vertices.len() as u32;
+        for row in 0..=rows {
+            for column in 0..=columns {
+                self.vertices.push(vertex(column, row));
+            }
+        }
+
+        let index = |column: u32, row: u32| first + row * (columns + 1) + column;
+        for row in 0..rows {
+            for column in 0..columns {
+                let [top_left, top_right] = [column, column + 1].map(|c| index(c, row));
+                let [bottom_left, bottom_right] = [column, column + 1].map(|c| index(c, row + 1));
+                self.indices.extend([top_left, bottom_left, bottom_right]);
+                self.indices.extend([top_left, bottom_right, top_right]);
+            }
+        }
+    }
+
+    /// Adds a flat disc of half a unit across at `height`, facing up or down, with its texture
+    /// mapped like a plane's.
+    fn cap(&mut self, segments: u32, height: f32, up: bool) {
+        let normal = if up {
+            Vector3::unit_y()
+        } else {
+            -Vector3::unit_y()
+        };
+        let point = |position: Vector3<f32>| {
+            // Seen from below, the texture's down runs towards -Z
+            let v = if up { position.z } else { -position.z };
+            vertex(position, normal, [position.x + 0.5, v + 0.5])
+        };
+
+        let centre = self.vertex(point(Vector3::new(0.0, height, 0.0)));
+        let first = self.vertices.len() as u32;
+        for segment in 0..segments {
+            let around = spherical(segment as f32 / segments as f32 * TAU, PI / 2.0);
+            self.vertex(point(around * 0.5 + Vector3::new(0.0, height, 0.0)));
+        }
+        for segment in 0..segments {
+            let [a, b] = [segment, (segment + 1) % segments].map(|s| first + s);
+            let triangle = if up { [centre, a, b] } else { [centre, b, a] };
+            self.indices.extend(triangle);
+        }
+    }
+
+    /// Drops the triangles without an area, such as those at the poles of a grid wrapped around
+    /// a sphere, and works out the tangents.
+    fn finish(mut self, name: &str) -> MeshData {
+        let position = |index: u32| Vector3::from(self.vertices[index as usize].position);
+        let indices = self
+            .indices
+            .chunks_exact(3)
+            .filter(|triangle| {
+                let [a, b, c] = [0, 1, 2].map(|i| position(triangle[i]));
+                (b - a).cross(c - a).magnitude2() > 1e-12
+            })
+            .flatten()
+            .copied()
+            .collect::<Vec<_>>();
+        self.indices = indices;
+
+        let tangents = compute_tangents(&self.vertices, &self.indices);
+        let mut mesh = MeshData::new(name.to_string(), self.vertices, self.indices, 0);
+        mesh.tangents = Some(tangents);
+        mesh
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every shape, at a few counts each, with the least it can have.
+    fn shapes() -> Vec<MeshData> {
+        let mut shapes = Vec::new();
+        for count in [1, 3, 4, 16] {
+            shapes.extend([
+                cube(count),
+                uv_sphere(count, count),
+                icosphere(count.min(3)),
+                plane(count),
+                cylinder(count),
+                cone(count),
+                torus(count, count, 0.375, 0.125),
+                capsule(count, count, 2.0),
+                capsule(count, count, 0.5),
+            ]);
+        }
+        shapes
+    }
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-4
+    }
+
+    #[test]
+    fn triangles_wind_the_way_their_normals_face() {
+        for mesh in shapes() {
+            for triangle in mesh.indices.chunks_exact(3) {
+                let [a, b, c] = [0, 1, 2].map(|i| &mesh.vertices[triangle[i] as usize]);
+                let [pa, pb, pc] = [a, b, c].map(|v| Vector3::from(v.position));
+                let winding = (pb - pa).cross(pc - pa).normalize();
+                for vertex in [a, b, c] {
+                    assert!(
+                        winding.dot(Vector3::from(vertex.normal)) > 0.0,
+                        "{}: {triangle:?} winds against its normals",
+                        mesh.name
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn normals_are_unit_length() {
+        for mesh in shapes() {
+            for vertex in &mesh.vertices {
+                let length = Vector3::from(vertex.normal).magnitude();
+                assert!(close(length, 1.0), "{}: {vertex:?}", mesh.name);
+            }
+        }
+    }
+
+    #[test]
+    fn texture_coordinates_are_within_the_texture() {
+        for mesh in shapes() {
+            for vertex in &mesh.vertices {
+                assert!(
+                    vertex.tex_coords.iter().all(|t| (0.0..=1.0).contains(t)),
+                    "{}: {vertex:?}",
+                    mesh.name
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn tangents_are_perpendicular_to_normals() {
+        for mesh in shapes() {
+            let tangents = mesh.tangents.as_ref().unwrap();
+            assert_eq!(tangents.len(), mesh.vertices.len());
+            for (vertex, &[x, y, z, w]) in mesh.vertices.iter().zip(tangents) {
+                let tangent = Vector3::new(x, y, z);
+                assert!(close(tangent.magnitude(), 1.0), "{}", mesh.name);
+                assert!(
+                    close(tangent.dot(Vector3::from(vertex.normal)), 0.0),
+                    "{}: {vertex:?} {tangent:?}",
+                    mesh.name
+                );
+                assert!(w == 1.0 || w == -1.0, "{}", mesh.name);
+            }
+        }
+    }
+
+    #[test]
+    fn counts_past_the_maximum_are_clamped() {
+        let most = plane(MAX_GRID_SUBDIVISIONS);
+        let past = plane(u32::MAX);
+        assert_eq!(past.vertices.len(), most.vertices.len());
+        assert_eq!(past.indices.len(), most.indices.len());
+
+        let most = uv_sphere(MAX_SEGMENTS, 2);
+        let past = uv_sphere(u32::MAX, 2);
+        assert_eq!(past.indices.len(), most.indices.len());
+    }
+}
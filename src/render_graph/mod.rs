@@ -0,0 +1,705 @@
+//! The passes that draw a frame, ordered by the textures they read and write.
+//!
+//! Every frame each node declares the textures it uses. A texture that has been written is only
+//! read once every node writing it has run, and nodes writing the same texture run in the order
+//! they were added. Textures a node creates live only as long as the nodes using them, and are
+//! shared with other such textures whose uses don't overlap.
+
+pub mod nodes;
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+/// The surface texture, or the `OffscreenTarget` when headless.
+pub const OUTPUT: &str = "output";
+/// The single-sampled HDR target of `RenderTargets`, which the scene is resolved into.
+pub const HDR: &str = "hdr";
+/// The depth buffer of `RenderTargets`.
+pub const DEPTH: &str = "depth";
+
+/// A pass of the render graph.
+///
+/// Nodes are given the world to fetch what they draw, so they mustn't fetch the `RenderGraph`
+/// itself.
+pub trait RenderNode: Send + Sync + 'static {
+    /// Declares the textures the node uses this frame. The graph is only rebuilt when the
+    /// declarations of some node, or the size of the output, change.
+    fn setup(&self, builder: &mut PassBuilder, world: &specs::World);
+
+    /// Records the node's passes.
+    fn run(&mut self, context: &mut RenderContext, world: &specs::World);
+}
+
+/// How big a texture created by a node is.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TextureSize {
+    /// A fraction of the output's size, at least one pixel.
+    Relative(f32),
+    Absolute(u32, u32),
+}
+
+/// A texture created by a node, which can be drawn into and sampled.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextureDescriptor {
+    pub format: wgpu::TextureFormat,
+    pub size: TextureSize,
+}
+
+impl TextureDescriptor {
+    fn resolve(&self, (width, height): (u32, u32)) -> TextureKey {
+        let (width, height) = match self.size {
+            TextureSize::Relative(scale) => (
+                ((width as f32 * scale) as u32).max(1),
+                ((height as f32 * scale) as u32).max(1),
+            ),
+            TextureSize::Absolute(width, height) => (width, height),
+        };
+        TextureKey {
+            format: self.format,
+            width,
+            height,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct TextureKey {
+    format: wgpu::TextureFormat,
+    width: u32,
+    height: u32,
+}
+
+/// Collects the textures a node uses.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PassBuilder {
+    output_size: (u32, u32),
+    reads: Vec<String>,
+    writes: Vec<String>,
+    creates: Vec<(String, TextureDescriptor)>,
+}
+
+impl PassBuilder {
+    fn new(output_size: (u32, u32)) -> Self {
+        Self {
+            output_size,
+            reads: Vec::new(),
+            writes: Vec::new(),
+            creates: Vec::new(),
+        }
+    }
+
+    /// The width and height of the output.
+    pub fn output_size(&self) -> (u32, u32) {
+        self.output_size
+    }
+
+    pub fn read(&mut self, texture: &str) -> &mut Self {
+        self.reads.push(texture.to_string());
+        self
+    }
+
+    pub fn write(&mut self, texture: &str) -> &mut Self {
+        self.writes.push(texture.to_string());
+        self
+    }
+
+    /// Creates a texture that this node writes first and later nodes may use.
+    pub fn create(&mut self, texture: &str, descriptor: TextureDescriptor) -> &mut Self {
+        self.creates.push((texture.to_string(), descriptor));
+        self
+    }
+
+    fn writes(&self, texture: &str) -> bool {
+        self.writes.iter().any(|name| name == texture)
+            || self.creates.iter().any(|(name, _)| name == texture)
+    }
+
+    fn uses(&self) -> impl Iterator<Item = &str> {
+        self.reads
+            .iter()
+            .chain(&self.writes)
+            .chain(self.creates.iter().map(|(name, _)| name))
+            .map(String::as_str)
+    }
+}
+
+/// What a node records its passes with.
+pub struct RenderContext<'a> {
+    pub device: &'a wgpu::Device,
+    pub encoder: &'a mut wgpu::CommandEncoder,
+    output_size: (u32, u32),
+    textures: &'a HashMap<&'a str, &'a wgpu::TextureView>,
+}
+
+impl<'a> RenderContext<'a> {
+    /// The width and height of the output.
+    pub fn output_size(&self) -> (u32, u32) {
+        self.output_size
+    }
+
+    /// A texture the node declared.
+    pub fn texture(&self, name: &str) -> &'a wgpu::TextureView {
+        self.textures
+            .get(name)
+            .unwrap_or_else(|| panic!("Render graph texture {name:?} wasn't declared"))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RenderGraphError {
+    /// Nodes that depend on each other through the textures they use.
+    Cycle(Vec<String>),
+    MissingTexture {
+        node: String,
+        texture: String,
+    },
+    DuplicateTexture {
+        texture: String,
+    },
+}
+
+impl std::fmt::Display for RenderGraphError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RenderGraphError::Cycle(nodes) => {
+                write!(f, "Nodes {} depend on each other", nodes.join(", "))
+            }
+            RenderGraphError::MissingTexture { node, texture } => write!(
+                f,
+                "Node {node:?} uses texture {texture:?}, which no node creates"
+            ),
+            RenderGraphError::DuplicateTexture { texture } => {
+                write!(f, "Texture {texture:?} is created by more than one node")
+            }
+        }
+    }
+}
+
+impl std::error::Error for RenderGraphError {}
+
+struct Node {
+    name: String,
+    node: Box<dyn RenderNode>,
+}
+
+struct PhysicalTexture {
+    key: TextureKey,
+    view: wgpu::TextureView,
+}
+
+/// The result of ordering the nodes and placing their textures.
+#[derive(Debug)]
+struct Compiled {
+    order: Vec<usize>,
+    /// The physical texture of each created texture.
+    placement: HashMap<String, usize>,
+    /// The size and format of each physical texture.
+    textures: Vec<TextureKey>,
+}
+
+/// The nodes that draw each frame, run by the `RenderSystem`.
+#[derive(Default)]
+pub struct RenderGraph {
+    nodes: Vec<Node>,
+    /// What the graph was last compiled from, and the result.
+    compiled: Option<(Vec<PassBuilder>, Result<Compiled, RenderGraphError>)>,
+    textures: Vec<PhysicalTexture>,
+}
+
+impl RenderGraph {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a node after every other one, so that it writes textures after them.
+    pub fn add_node(&mut self, name: impl Into<String>, node: impl RenderNode) {
+        self.insert(self.nodes.len(), name.into(), Box::new(node));
+    }
+
+    /// Adds a node just before the node named `before`, so that it writes textures before it.
+    /// The node is added last if there's no such node.
+    pub fn add_node_before(
+        &mut self,
+        before: &str,
+        name: impl Into<String>,
+        node: impl RenderNode,
+    ) {
+        let index = self
+            .nodes
+            .iter()
+            .position(|node| node.name == before)
+            .unwrap_or(self.nodes.len());
+        self.insert(index, name.into(), Box::new(node));
+    }
+
+    pub fn remove_node(&mut self, name: &str) -> bool {
+        let count = self.nodes.len();
+        self.nodes.retain(|node| node.name != name);
+        self.compiled = None;
+        self.nodes.len() != count
+    }
+
+    fn insert(&mut self, index: usize, name: String, node: Box<dyn RenderNode>) {
+        self.nodes.insert(index, Node { name, node });
+        self.compiled = None;
+    }
+
+    /// The names of the nodes in the order they ran in the last frame.
+    pub fn order(&self) -> Vec<&str> {
+        match &self.compiled {
+            Some((_, Ok(compiled))) => compiled
+                .order
+                .iter()
+                .map(|&index| self.nodes[index].name.as_str())
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Records every node into `encoder`. `external` are the textures that live outside the
+    /// graph, such as `OUTPUT`. Nothing is drawn while the graph is invalid.
+    pub fn run(
+        &mut self,
+        world: &specs::World,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        output_size: (u32, u32),
+        external: &[(&str, &wgpu::TextureView)],
+    ) {
+        let declarations = self
+            .nodes
+            .iter()
+            .map(|node| {
+                let mut builder = PassBuilder::new(output_size);
+                node.node.setup(&mut builder, world);
+                builder
+            })
+            .collect::<Vec<_>>();
+
+        let outdated = self
+            .compiled
+            .as_ref()
+            .is_none_or(|(previous, _)| *previous != declarations);
+        if outdated {
+            let nodes = self
+                .nodes
+                .iter()
+                .map(|node| node.name.as_str())
+                .collect::<Vec<_>>();
+            let names = external.iter().map(|(name, _)| *name).collect::<Vec<_>>();
+            match compile(&nodes, &declarations, &names) {
+                Ok(compiled) => {
+                    self.allocate_textures(&compiled.textures, device);
+                    self.compiled = Some((declarations, Ok(compiled)));
+                    log::debug!("Render graph order: {}", self.order().join(", "));
+                }
+                Err(error) => {
+                    log::error!("Invalid render graph: {}", error);
+                    self.compiled = Some((declarations, Err(error)));
+                }
+            }
+        }
+
+        let Some((_, Ok(compiled))) = &self.compiled else {
+            return;
+        };
+
+        let mut textures = external.iter().copied().collect::<HashMap<_, _>>();
+        for (name, &index) in &compiled.placement {
+            textures.insert(name.as_str(), &self.textures[index].view);
+        }
+
+        for &index in &compiled.order {
+            let mut context = RenderContext {
+                device,
+                encoder,
+                output_size,
+                textures: &textures,
+            };
+            self.nodes[index].node.run(&mut context, world);
+        }
+    }
+
+    /// Creates the physical textures, reusing the ones from before where they fit, so that a
+    /// change to one node doesn't reallocate every texture.
+    fn allocate_textures(&mut self, keys: &[TextureKey], device: &wgpu::Device) {
+        let mut previous = std::mem::take(&mut self.textures)
+            .into_iter()
+            .map(Some)
+            .collect::<Vec<_>>();
+        self.textures = keys
+            .iter()
+            .map(|&key| {
+                previous
+                    .iter_mut()
+                    .find(|texture| texture.as_ref().is_some_and(|t| t.key == key))
+                    .and_then(Option::take)
+                    .unwrap_or_else(|| create_texture(device, key))
+            })
+            .collect();
+    }
+}
+
+/// Checks the declarations of the nodes named `nodes`, orders them, and places their textures.
+fn compile(
+    nodes: &[&str],
+    declarations: &[PassBuilder],
+    external: &[&str],
+) -> Result<Compiled, RenderGraphError> {
+    let mut created = HashSet::new();
+    for declaration in declarations {
+        for (name, _) in &declaration.creates {
+            if !created.insert(name.as_str()) || external.contains(&name.as_str()) {
+                return Err(RenderGraphError::DuplicateTexture {
+                    texture: name.clone(),
+                });
+            }
+        }
+    }
+
+    for (node, declaration) in nodes.iter().zip(declarations) {
+        if let Some(texture) = declaration
+            .uses()
+            .find(|name| !created.contains(name) && !external.contains(name))
+        {
+            return Err(RenderGraphError::MissingTexture {
+                node: node.to_string(),
+                texture: texture.to_string(),
+            });
+        }
+    }
+
+    let order = order_nodes(declarations).map_err(|remaining| {
+        RenderGraphError::Cycle(
+            remaining
+                .into_iter()
+                .map(|index| nodes[index].to_string())
+                .collect(),
+        )
+    })?;
+
+    let (placement, textures) = place_textures(declarations, &order);
+    Ok(Compiled {
+        order,
+        placement,
+        textures,
+    })
+}
+
+/// Gives each created texture a physical texture, sharing those whose uses don't overlap.
+/// Returns the physical texture of each, and the size and format of every physical texture.
+fn place_textures(
+    declarations: &[PassBuilder],
+    order: &[usize],
+) -> (HashMap<String, usize>, Vec<TextureKey>) {
+    let mut last_use = HashMap::new();
+    for (position, &index) in order.iter().enumerate() {
+        for name in declarations[index].uses() {
+            last_use.insert(name, position);
+        }
+    }
+
+    let mut textures = Vec::new();
+    let mut free = Vec::new();
+    let mut placement = HashMap::new();
+
+    for (position, &index) in order.iter().enumerate() {
+        let declaration = &declarations[index];
+        for (name, descriptor) in &declaration.creates {
+            let key = descriptor.resolve(declaration.output_size);
+            let physical = match free
+                .iter()
+                .position(|&physical: &usize| textures[physical] == key)
+            {
+                Some(slot) => free.swap_remove(slot),
+                None => {
+                    textures.push(key);
+                    textures.len() - 1
+                }
+            };
+            placement.insert(name.clone(), physical);
+        }
+
+        // Released after the node, so that textures it reads aren't given to ones it creates
+        for (name, &physical) in &placement {
+            if last_use.get(name.as_str()) == Some(&position) {
+                free.push(physical);
+            }
+        }
+    }
+
+    log::debug!(
+        "Render graph placed {} textures in {}",
+        placement.len(),
+        textures.len()
+    );
+    (placement, textures)
+}
+
+/// Orders nodes so that writers of a texture run in the order they were added and before its
+/// readers, preferring the order they were added in. Fails with the nodes left in a cycle.
+fn order_nodes(declarations: &[PassBuilder]) -> Result<Vec<usize>, Vec<usize>> {
+    let mut edges = BTreeSet::new();
+    let textures = declarations
+        .iter()
+        .flat_map(PassBuilder::uses)
+        .collect::<BTreeSet<_>>();
+
+    for texture in textures {
+        // The creator comes first, then the other writers
+        let mut writers = (0..declarations.len())
+            .filter(|&index| declarations[index].writes(texture))
+            .collect::<Vec<_>>();
+        writers.sort_by_key(|&index| {
+            !declarations[index]
+                .creates
+                .iter()
+                .any(|(name, _)| name == texture)
+        });
+
+        for pair in writers.windows(2) {
+            edges.insert((pair[0], pair[1]));
+        }
+        let readers = (0..declarations.len()).filter(|&index| {
+            !writers.contains(&index) && declarations[index].reads.iter().any(|n| n == texture)
+        });
+        for reader in readers {
+            for &writer in &writers {
+                edges.insert((writer, reader));
+            }
+        }
+    }
+
+    let mut incoming = vec![0; declarations.len()];
+    for &(_, to) in &edges {
+        incoming[to] += 1;
+    }
+
+    let mut ready = (0..declarations.len())
+        .filter(|&index| incoming[index] == 0)
+        .collect::<BTreeSet<_>>();
+    let mut order = Vec::with_capacity(declarations.len());
+    while let Some(index) = ready.pop_first() {
+        order.push(index);
+        for &(_, to) in edges.range((index, 0)..(index + 1, 0)) {
+            incoming[to] -= 1;
+            if incoming[to] == 0 {
+                ready.insert(to);
+            }
+        }
+    }
+
+    if order.len() == declarations.len() {
+        Ok(order)
+    } else {
+        Err((0..declarations.len())
+            .filter(|&index| incoming[index] > 0)
+            .collect())
+    }
+}
+
+fn create_texture(device: &wgpu::Device, key: TextureKey) -> PhysicalTexture {
+    let view = device
+        .create_texture(&wgpu::TextureDescriptor {
+            label: Some("Render Graph Texture"),
+            size: wgpu::Extent3d {
+                width: key.width,
+                height: key.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: key.format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        })
+        .create_view(&wgpu::TextureViewDescriptor::default());
+    PhysicalTexture { key, view }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZE: (u32, u32) = (640, 480);
+
+    fn texture(size: TextureSize) -> TextureDescriptor {
+        TextureDescriptor {
+            format: wgpu::TextureFormat::Rgba16Float,
+            size,
+        }
+    }
+
+    fn full() -> TextureDescriptor {
+        texture(TextureSize::Relative(1.0))
+    }
+
+    fn pass(declare: impl FnOnce(&mut PassBuilder)) -> PassBuilder {
+        let mut builder = PassBuilder::new(SIZE);
+        declare(&mut builder);
+        builder
+    }
+
+    fn compile_nodes(declarations: &[PassBuilder]) -> Result<Compiled, RenderGraphError> {
+        let names = ["a", "b", "c", "d", "e"];
+        compile(&names[..declarations.len()], declarations, &[OUTPUT])
+    }
+
+    #[test]
+    fn readers_run_after_writers_added_later() {
+        let declarations = [
+            pass(|p| {
+                p.read("blurred").write(OUTPUT);
+            }),
+            pass(|p| {
+                p.read("scene").create("blurred", full());
+            }),
+            pass(|p| {
+                p.create("scene", full());
+            }),
+        ];
+        assert_eq!(compile_nodes(&declarations).unwrap().order, [2, 1, 0]);
+    }
+
+    #[test]
+    fn writers_keep_the_order_they_were_added_in() {
+        let declarations = [
+            pass(|p| {
+                p.create("scene", full());
+            }),
+            pass(|p| {
+                p.write("scene");
+            }),
+            pass(|p| {
+                p.write("scene");
+            }),
+            pass(|p| {
+                p.read("scene").write(OUTPUT);
+            }),
+        ];
+        assert_eq!(compile_nodes(&declarations).unwrap().order, [0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn independent_nodes_keep_the_order_they_were_added_in() {
+        let declarations = [
+            pass(|p| {
+                p.create("x", full());
+            }),
+            pass(|p| {
+                p.create("y", full());
+            }),
+            pass(|p| {
+                p.read("y").read("x").write(OUTPUT);
+            }),
+        ];
+        assert_eq!(compile_nodes(&declarations).unwrap().order, [0, 1, 2]);
+    }
+
+    #[test]
+    fn cycles_are_rejected() {
+        let declarations = [
+            pass(|p| {
+                p.read("y").create("x", full());
+            }),
+            pass(|p| {
+                p.read("x").create("y", full());
+            }),
+            pass(|p| {
+                p.write(OUTPUT);
+            }),
+        ];
+        assert_eq!(
+            compile_nodes(&declarations).unwrap_err(),
+            RenderGraphError::Cycle(vec!["a".into(), "b".into()])
+        );
+    }
+
+    #[test]
+    fn undeclared_textures_are_rejected() {
+        let declarations = [pass(|p| {
+            p.read("nothing").write(OUTPUT);
+        })];
+        assert_eq!(
+            compile_nodes(&declarations).unwrap_err(),
+            RenderGraphError::MissingTexture {
+                node: "a".into(),
+                texture: "nothing".into(),
+            }
+        );
+    }
+
+    #[test]
+    fn textures_created_twice_are_rejected() {
+        let twice = [
+            pass(|p| {
+                p.create("x", full());
+            }),
+            pass(|p| {
+                p.create("x", full());
+            }),
+        ];
+        let external = [pass(|p| {
+            p.create(OUTPUT, full());
+        })];
+        for declarations in [&twice[..], &external[..]] {
+            assert!(matches!(
+                compile_nodes(declarations),
+                Err(RenderGraphError::DuplicateTexture { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn textures_whose_uses_dont_overlap_share_memory() {
+        let declarations = [
+            pass(|p| {
+                p.create("first", full());
+            }),
+            pass(|p| {
+                p.read("first").create("second", full());
+            }),
+            pass(|p| {
+                p.read("second").create("third", full());
+            }),
+            pass(|p| {
+                p.read("third").write(OUTPUT);
+            }),
+        ];
+        let compiled = compile_nodes(&declarations).unwrap();
+        let placement = &compiled.placement;
+        assert_eq!(compiled.textures.len(), 2);
+        assert_eq!(placement["first"], placement["third"]);
+        // Read while the second is written, so they can't share
+        assert_ne!(placement["first"], placement["second"]);
+    }
+
+    #[test]
+    fn textures_of_different_sizes_dont_share_memory() {
+        let declarations = [
+            pass(|p| {
+                p.create("full", full());
+            }),
+            pass(|p| {
+                p.read("full")
+                    .create("half", texture(TextureSize::Relative(0.5)));
+            }),
+            pass(|p| {
+                p.read("half")
+                    .create("fixed", texture(TextureSize::Absolute(64, 64)));
+            }),
+            pass(|p| {
+                p.read("fixed").write(OUTPUT);
+            }),
+        ];
+        let compiled = compile_nodes(&declarations).unwrap();
+        let size = |name: &str| {
+            let key = compiled.textures[compiled.placement[name]];
+            (key.width, key.height)
+        };
+        assert_eq!(compiled.textures.len(), 3);
+        assert_eq!(size("full"), (640, 480));
+        assert_eq!(size("half"), (320, 240));
+        assert_eq!(size("fixed"), (64, 64));
+    }
+}
@@ -0,0 +1,329 @@
+//! The nodes the engine draws a frame with.
+
+use std::collections::HashSet;
+
+use specs::{Join, ReadExpect, ReadStorage, WorldExt};
+
+use super::{
+    PassBuilder, RenderContext, RenderNode, TextureDescriptor, TextureSize, DEPTH, HDR, OUTPUT,
+};
+use crate::{
+    components::{
+        background::Sky,
+        hierarchy::GlobalTransform,
+        lighting::Lighting,
+        postprocessing::PostProcessing,
+        rendering::{Camera, Material, Mesh, Renderer, Transform},
+        tonemapping::{ToneMapping, ToneMappingUniform},
+    },
+    culling::{CullingStats, Frustum},
+    material::AlphaMode,
+    material_manager::MaterialManager,
+    render_target::{RenderTargets, HDR_FORMAT},
+};
+
+/// Names of the engine's nodes, for placing custom nodes with `RenderGraph::add_node_before`.
+pub mod names {
+    /// Draws the meshes and the background into `HDR`.
+    pub const SCENE: &str = "scene";
+    /// Adds bloom onto `HDR`.
+    pub const BLOOM: &str = "bloom";
+    /// Maps `HDR` into the first post-processing image, or into `OUTPUT` without effects.
+    pub const TONE_MAPPING: &str = "tone_mapping";
+    /// Applies the effects after tone mapping, the last of them drawing into `OUTPUT`.
+    pub const POST_PROCESSING: &str = "post_processing";
+}
+
+/// The images the effects after tone mapping ping-pong between.
+const POST_IMAGES: [&str; 2] = ["post_a", "post_b"];
+
+fn bloom_level(level: u32) -> String {
+    format!("bloom_{level}")
+}
+
+/// Draws opaque and cut out meshes, then the sky, then blended meshes from back to front.
+pub struct SceneNode;
+
+impl RenderNode for SceneNode {
+    fn setup(&self, builder: &mut PassBuilder, _world: &specs::World) {
+        builder.write(HDR).write(DEPTH);
+    }
+
+    fn run(&mut self, context: &mut RenderContext, world: &specs::World) {
+        use cgmath::MetricSpace;
+
+        let (renderers, transforms, globals, cameras, material_manager, lighting, sky, targets) =
+            world.system_data::<(
+                ReadStorage<Renderer>,
+                ReadStorage<Transform>,
+                ReadStorage<GlobalTransform>,
+                ReadStorage<Camera>,
+                ReadExpect<MaterialManager>,
+                ReadExpect<Lighting>,
+                ReadExpect<Sky>,
+                ReadExpect<RenderTargets>,
+            )>();
+
+        let hdr = context.texture(HDR);
+        // Only the layouts of meshes that may be drawn get pipelines
+        let vertex_layouts = renderers
+            .join()
+            .flat_map(|renderer| renderer.drawn_meshes().iter().map(Mesh::layout))
+            .collect::<HashSet<_>>();
+        let pipelines = material_manager.scene_pipelines(
+            context.device,
+            HDR_FORMAT,
+            targets.sample_count,
+            vertex_layouts,
+        );
+        let mut render_pass = context
+            .encoder
+            .begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Render Pass"),
+                // With multisampling, the samples are resolved into the HDR target and then
+                // discarded
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: targets.color.as_ref().unwrap_or(hdr),
+                    resolve_target: targets.color.as_ref().map(|_| hdr),
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(sky.clear_color()),
+                        store: targets.color.is_none(),
+                    },
+                })],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: context.texture(DEPTH),
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: false,
+                    }),
+                    stencil_ops: None,
+                }),
+            });
+
+        render_pass.set_bind_group(2, &lighting.bind, &[]);
+
+        let camera = (&cameras, &transforms, &globals).join().next();
+        let camera_position = camera.map(|(_, _, global)| global.position());
+        let frustum = camera.map(|(camera, transform, global)| {
+            Frustum::from_view_projection(
+                &(camera.projection_matrix() * camera.view_matrix(transform, global)),
+            )
+        });
+
+        // Opaque and cut out meshes are drawn as they come, and blended meshes afterwards
+        let mut blended = Vec::new();
+        let mut stats = CullingStats::default();
+        for (renderer, transform, global) in (&renderers, &transforms, &globals).join() {
+            // Transforms are bound by the camera, so without one there's nothing to draw them
+            // with, and after the last one is removed only stale bind groups
+            let (Some(_), Some(transform_bind)) = (camera, &transform.bind) else {
+                continue;
+            };
+
+            let mut drawn = false;
+            for mesh in renderer.drawn_meshes() {
+                let bounds = mesh.bounds.transformed(&global.matrix);
+                if frustum.is_some_and(|frustum| !frustum.intersects(&bounds)) {
+                    continue;
+                }
+                drawn = true;
+
+                let Some(material) = renderer
+                    .materials
+                    .get(mesh.material)
+                    .or_else(|| renderer.materials.first())
+                else {
+                    continue;
+                };
+
+                if material.alpha_mode == AlphaMode::Blend {
+                    let center = cgmath::Point3::from(bounds.center());
+                    let distance = camera_position.map_or(0.0, |p| center.distance2(p));
+                    blended.push((distance, transform_bind, mesh, material));
+                } else {
+                    render_pass
+                        .set_pipeline(pipelines.pipeline_for(material.alpha_mode, mesh.layout()));
+                    draw_mesh(&mut render_pass, transform_bind, mesh, material);
+                }
+            }
+
+            if drawn {
+                stats.drawn += 1;
+            } else {
+                stats.culled += 1;
+            }
+        }
+
+        // Drawn after opaque geometry, so that it's only shaded where nothing is in front of it
+        if sky.is_drawn() {
+            render_pass.set_pipeline(&sky.pipeline);
+            render_pass.set_bind_group(0, &sky.bind, &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+
+        // Back to front, so that each blended mesh covers the ones behind it
+        blended.sort_by(|(a, ..), (b, ..)| b.total_cmp(a));
+        // The sky's pipeline layout doesn't share the lights' bind group
+        render_pass.set_bind_group(2, &lighting.bind, &[]);
+        for (_, transform_bind, mesh, material) in blended {
+            render_pass.set_pipeline(pipelines.pipeline_for(AlphaMode::Blend, mesh.layout()));
+            draw_mesh(&mut render_pass, transform_bind, mesh, material);
+        }
+
+        let mut last_stats = world.write_resource::<CullingStats>();
+        if *last_stats != stats {
+            log::debug!("Drew {} objects and culled {}", stats.drawn, stats.culled);
+            *last_stats = stats;
+        }
+    }
+}
+
+fn draw_mesh<'a>(
+    render_pass: &mut wgpu::RenderPass<'a>,
+    transform_bind: &'a wgpu::BindGroup,
+    mesh: &'a Mesh,
+    material: &'a Material,
+) {
+    render_pass.set_bind_group(1, transform_bind, &[]);
+    render_pass.set_bind_group(0, material.bind.as_ref().unwrap(), &[]);
+    for (slot, buffer) in mesh.vertex_buffers().enumerate() {
+        render_pass.set_vertex_buffer(slot as u32, buffer.slice(..));
+    }
+    render_pass.set_index_buffer(
+        mesh.index_buffer.as_ref().unwrap().slice(..),
+        mesh.index_format,
+    );
+
+    render_pass.draw_indexed(0..mesh.num_elements, 0, 0..1);
+}
+
+/// Draws the bloom effects of the post-processing stack.
+pub struct BloomNode;
+
+impl RenderNode for BloomNode {
+    fn setup(&self, builder: &mut PassBuilder, world: &specs::World) {
+        if !world.read_resource::<PostProcessing>().has_bloom() {
+            return;
+        }
+
+        let (width, height) = builder.output_size();
+        builder.read(HDR).write(HDR);
+        for level in 1..=PostProcessing::bloom_levels(width, height) {
+            let descriptor = TextureDescriptor {
+                format: HDR_FORMAT,
+                size: TextureSize::Relative(0.5f32.powi(level as i32)),
+            };
+            builder.create(&bloom_level(level), descriptor);
+        }
+    }
+
+    fn run(&mut self, context: &mut RenderContext, world: &specs::World) {
+        let post_processing = world.read_resource::<PostProcessing>();
+        if !post_processing.has_bloom() {
+            return;
+        }
+
+        let (width, height) = context.output_size();
+        let levels = (1..=PostProcessing::bloom_levels(width, height))
+            .map(|level| context.texture(&bloom_level(level)))
+            .collect::<Vec<_>>();
+        let hdr = context.texture(HDR);
+        post_processing.draw_bloom(context.encoder, context.device, hdr, &levels);
+    }
+}
+
+/// Maps the HDR scene with the first camera's exposure and tone mapper.
+pub struct ToneMappingNode;
+
+impl RenderNode for ToneMappingNode {
+    fn setup(&self, builder: &mut PassBuilder, world: &specs::World) {
+        builder.read(HDR);
+        if world.read_resource::<PostProcessing>().screen_pass_count() > 0 {
+            let format = world.read_resource::<wgpu::SurfaceConfiguration>().format;
+            let descriptor = TextureDescriptor {
+                format,
+                size: TextureSize::Relative(1.0),
+            };
+            builder.create(POST_IMAGES[0], descriptor);
+        } else {
+            builder.write(OUTPUT);
+        }
+    }
+
+    fn run(&mut self, context: &mut RenderContext, world: &specs::World) {
+        let (cameras, tone_mapping, post_processing, queue) = world.system_data::<(
+            ReadStorage<Camera>,
+            ReadExpect<ToneMapping>,
+            ReadExpect<PostProcessing>,
+            ReadExpect<wgpu::Queue>,
+        )>();
+
+        let uniform = cameras.join().next().map_or_else(
+            || ToneMappingUniform::new(0.0, Default::default()),
+            |camera| ToneMappingUniform::new(camera.exposure, camera.tone_mapper),
+        );
+        queue.write_buffer(&tone_mapping.buffer, 0, bytemuck::cast_slice(&[uniform]));
+
+        let target = if post_processing.screen_pass_count() > 0 {
+            context.texture(POST_IMAGES[0])
+        } else {
+            context.texture(OUTPUT)
+        };
+        let mut render_pass = context
+            .encoder
+            .begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Tone Mapping Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: target,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: None,
+            });
+        render_pass.set_pipeline(&tone_mapping.pipeline);
+        render_pass.set_bind_group(0, &tone_mapping.bind, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+/// Draws the effects of the post-processing stack that follow tone mapping.
+pub struct PostProcessingNode;
+
+impl RenderNode for PostProcessingNode {
+    fn setup(&self, builder: &mut PassBuilder, world: &specs::World) {
+        let count = world.read_resource::<PostProcessing>().screen_pass_count();
+        if count == 0 {
+            return;
+        }
+
+        builder.read(POST_IMAGES[0]).write(OUTPUT);
+        if count > 1 {
+            let format = world.read_resource::<wgpu::SurfaceConfiguration>().format;
+            let descriptor = TextureDescriptor {
+                format,
+                size: TextureSize::Relative(1.0),
+            };
+            builder
+                .write(POST_IMAGES[0])
+                .create(POST_IMAGES[1], descriptor);
+        }
+    }
+
+    fn run(&mut self, context: &mut RenderContext, world: &specs::World) {
+        let post_processing = world.read_resource::<PostProcessing>();
+        let count = post_processing.screen_pass_count();
+        if count == 0 {
+            return;
+        }
+
+        let images = POST_IMAGES[..count.min(2)]
+            .iter()
+            .map(|name| context.texture(name))
+            .collect::<Vec<_>>();
+        let output = context.texture(OUTPUT);
+        post_processing.draw(context.encoder, context.device, &images, output);
+    }
+}
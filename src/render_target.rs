@@ -0,0 +1,111 @@
+use crate::material_manager::DEPTH_FORMAT;
+
+/// The format the scene is drawn in, so that light brighter than the display can show survives
+/// until tone mapping.
+pub const HDR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+/// The buffers the scene is drawn into before it reaches the surface, sized like the surface and
+/// recreated by the `ResizingSystem`.
+pub struct RenderTargets {
+    pub sample_count: u32,
+    /// The multisampled colour buffer, resolved into `hdr`. `None` without multisampling, when
+    /// the scene is drawn straight into `hdr`.
+    pub color: Option<wgpu::TextureView>,
+    /// The scene in linear light, which the tone mapping pass reads.
+    pub hdr: wgpu::TextureView,
+    pub depth: wgpu::TextureView,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl RenderTargets {
+    pub fn new(device: &wgpu::Device, sample_count: u32, width: u32, height: u32) -> Self {
+        let create = |label, format, sample_count, usage| {
+            device
+                .create_texture(&wgpu::TextureDescriptor {
+                    label: Some(label),
+                    size: wgpu::Extent3d {
+                        width,
+                        height,
+                        depth_or_array_layers: 1,
+                    },
+                    mip_level_count: 1,
+                    sample_count,
+                    dimension: wgpu::TextureDimension::D2,
+                    format,
+                    usage: wgpu::TextureUsages::RENDER_ATTACHMENT | usage,
+                    view_formats: &[],
+                })
+                .create_view(&wgpu::TextureViewDescriptor::default())
+        };
+
+        // Only the resolved colour buffer is read from
+        let attachment_only = wgpu::TextureUsages::empty();
+        Self {
+            sample_count,
+            color: (sample_count > 1).then(|| {
+                create(
+                    "Multisampled Colour Buffer",
+                    HDR_FORMAT,
+                    sample_count,
+                    attachment_only,
+                )
+            }),
+            hdr: create(
+                "HDR Colour Buffer",
+                HDR_FORMAT,
+                1,
+                wgpu::TextureUsages::TEXTURE_BINDING,
+            ),
+            depth: create("Depth Buffer", DEPTH_FORMAT, sample_count, attachment_only),
+            width,
+            height,
+        }
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        *self = Self::new(device, self.sample_count, width, height);
+    }
+
+    /// The highest sample count up to `requested` that the device can render and resolve the
+    /// scene with, along with depth.
+    pub fn supported_sample_count(
+        requested: u32,
+        adapter: &wgpu::Adapter,
+        device: &wgpu::Device,
+    ) -> u32 {
+        // Without this feature only the counts every adapter supports are allowed
+        let adapter_specific = device
+            .features()
+            .contains(wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES);
+        let flags = |format: wgpu::TextureFormat| {
+            if adapter_specific {
+                adapter.get_texture_format_features(format).flags
+            } else {
+                format.describe().guaranteed_format_features.flags
+            }
+        };
+
+        let supported = |count: u32| {
+            count == 1
+                || (flags(HDR_FORMAT).sample_count_supported(count)
+                    && flags(HDR_FORMAT)
+                        .contains(wgpu::TextureFormatFeatureFlags::MULTISAMPLE_RESOLVE)
+                    && flags(DEPTH_FORMAT).sample_count_supported(count))
+        };
+
+        let count = [8, 4, 2, 1]
+            .into_iter()
+            .find(|&count| count <= requested && supported(count))
+            .unwrap_or(1);
+        if count != requested {
+            log::warn!(
+                "{}x MSAA isn't supported for {:?}, using {}x",
+                requested,
+                HDR_FORMAT,
+                count
+            );
+        }
+        count
+    }
+}
@@ -0,0 +1,879 @@
+use std::collections::HashMap;
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use specs::{Builder, Join, WorldExt};
+
+use crate::{
+    assets::AssetPaths,
+    components::{
+        background::Background,
+        hierarchy::Parent,
+        lighting::{Light, LightKind},
+        lod::{Lod, LodLevel},
+        postprocessing::PostEffect,
+        primitive::Primitive,
+        rendering::{Camera, Model, Renderer, Transform},
+        tonemapping::ToneMapper,
+    },
+    environment::Environment,
+    material_manager::MaterialManager,
+    texture_data::TextureError,
+};
+
+/// A declarative description of the entities in a world, stored as RON.
+///
+/// Each entity is a list of components. Unknown components are rejected when the scene is
+/// parsed, and `Parent` refers to another entity by its index in the list.
+///
+/// An application's own components are described as `Custom(name: "Spin", value: (speed: 2.0))`,
+/// and must be registered under that name in the world's `SceneComponents`, which
+/// `AppBuilder::with_scene_component` does. Unregistered names are rejected when the scene is
+/// instantiated.
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Scene {
+    pub entities: Vec<Vec<ComponentDescription>>,
+    /// Image-based lighting for the whole scene.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub environment: Option<EnvironmentDescription>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum ComponentDescription {
+    Model(ModelDescription),
+    Primitive(Primitive),
+    Transform(TransformDescription),
+    Camera(CameraDescription),
+    Light(LightDescription),
+    Lod(LodDescription),
+    Parent(usize),
+    /// A component registered in `SceneComponents`.
+    Custom {
+        name: String,
+        value: ron::Value,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct ModelDescription {
+    pub file: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct TransformDescription {
+    pub position: [f32; 3],
+    /// Quaternion as `[x, y, z, w]`.
+    pub rotation: [f32; 4],
+    pub scale: [f32; 3],
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct CameraDescription {
+    pub target: [f32; 3],
+    pub up: [f32; 3],
+    pub fovy: f32,
+    pub znear: f32,
+    pub zfar: f32,
+    pub background: Background,
+    pub exposure: f32,
+    pub tone_mapper: ToneMapper,
+    pub post_effects: Vec<PostEffect>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct LightDescription {
+    pub kind: LightKind,
+    pub color: [f32; 3],
+    pub intensity: f32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct LodDescription {
+    pub levels: Vec<LodLevel>,
+    pub hysteresis: f32,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct EnvironmentDescription {
+    /// An equirectangular image, usually HDR.
+    pub file: String,
+    pub intensity: f32,
+}
+
+impl Default for EnvironmentDescription {
+    fn default() -> Self {
+        Self {
+            file: String::new(),
+            intensity: 1.0,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io(std::io::Error),
+    Parse(ron::error::SpannedError),
+    Serialize(ron::Error),
+    InvalidParent {
+        entity: usize,
+        parent: usize,
+    },
+    ParentCycle {
+        entity: usize,
+    },
+    DuplicateComponent {
+        entity: usize,
+        component: String,
+    },
+    UnknownComponent {
+        entity: usize,
+        component: String,
+    },
+    InvalidComponent {
+        entity: usize,
+        component: String,
+        error: ron::Error,
+    },
+    Environment(TextureError),
+}
+
+impl std::fmt::Display for SceneError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SceneError::Io(error) => write!(f, "Couldn't access scene file: {error}"),
+            SceneError::Parse(error) => write!(f, "Couldn't parse scene file: {error}"),
+            SceneError::Serialize(error) => write!(f, "Couldn't serialize scene: {error}"),
+            SceneError::InvalidParent { entity, parent } => write!(
+                f,
+                "Entity {entity} has parent {parent}, which is not an entity in the scene"
+            ),
+            SceneError::ParentCycle { entity } => {
+                write!(f, "Entity {entity} is its own ancestor")
+            }
+            SceneError::DuplicateComponent { entity, component } => {
+                write!(f, "Entity {entity} has more than one {component} component")
+            }
+            SceneError::UnknownComponent { entity, component } => write!(
+                f,
+                "Entity {entity} has a {component} component, which isn't registered"
+            ),
+            SceneError::InvalidComponent {
+                entity,
+                component,
+                error,
+            } => write!(
+                f,
+                "Entity {entity} has an invalid {component} component: {error}"
+            ),
+            SceneError::Environment(error) => write!(f, "Couldn't load environment: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+impl Scene {
+    pub fn load(path: impl AsRef<std::path::Path>) -> Result<Self, SceneError> {
+        let text = std::fs::read_to_string(path).map_err(SceneError::Io)?;
+        Self::from_ron(&text)
+    }
+
+    pub fn from_ron(text: &str) -> Result<Self, SceneError> {
+        ron_options().from_str(text).map_err(SceneError::Parse)
+    }
+
+    pub fn save(&self, path: impl AsRef<std::path::Path>) -> Result<(), SceneError> {
+        let text = self.to_ron()?;
+        std::fs::write(path, text).map_err(SceneError::Io)
+    }
+
+    pub fn to_ron(&self) -> Result<String, SceneError> {
+        let config = ron::ser::PrettyConfig::default()
+            .struct_names(true)
+            .extensions(ron::extensions::Extensions::UNWRAP_VARIANT_NEWTYPES);
+
+        ron::ser::to_string_pretty(self, config).map_err(SceneError::Serialize)
+    }
+
+    /// A scene with a single model in front of a camera, lit from the camera's direction.
+    pub fn from_model(file: impl Into<String>) -> Self {
+        Self {
+            entities: vec![
+                vec![
+                    ComponentDescription::Model(ModelDescription { file: file.into() }),
+                    ComponentDescription::Transform(TransformDescription::default()),
+                ],
+                vec![
+                    ComponentDescription::Camera(CameraDescription::default()),
+                    ComponentDescription::Transform(TransformDescription {
+                        position: [0.0, 0.0, 10.0],
+                        ..Default::default()
+                    }),
+                ],
+                vec![
+                    ComponentDescription::Light(LightDescription::default()),
+                    ComponentDescription::Transform(TransformDescription::default()),
+                ],
+            ],
+            environment: None,
+        }
+    }
+
+    /// Creates the entities described by this scene in the world.
+    ///
+    /// The scene is validated before any entity is created, so an invalid scene leaves the world
+    /// untouched.
+    pub fn instantiate(&self, world: &mut specs::World) -> Result<Vec<specs::Entity>, SceneError> {
+        let registered = world
+            .try_fetch::<SceneComponents>()
+            .map(|components| components.components.clone())
+            .unwrap_or_default();
+        self.validate(&registered)?;
+
+        if let Some(description) = &self.environment {
+            let mut environment =
+                load_environment(world, &description.file).map_err(SceneError::Environment)?;
+            environment.intensity = description.intensity;
+            world.insert(environment);
+        }
+
+        let entities = self
+            .entities
+            .iter()
+            .map(|_| world.create_entity().build())
+            .collect::<Vec<_>>();
+
+        for (entity, components) in entities.iter().zip(self.entities.iter()) {
+            for component in components {
+                match component {
+                    ComponentDescription::Model(model) => {
+                        insert(world, *entity, Model::from(model));
+                        insert(world, *entity, Renderer::default());
+                    }
+                    ComponentDescription::Primitive(primitive) => {
+                        insert(world, *entity, primitive.clone());
+                        insert(world, *entity, Renderer::default());
+                    }
+                    ComponentDescription::Transform(transform) => {
+                        insert(world, *entity, Transform::from(transform));
+                    }
+                    ComponentDescription::Camera(camera) => {
+                        insert(world, *entity, Camera::from(camera));
+                    }
+                    ComponentDescription::Light(light) => {
+                        insert(world, *entity, Light::from(light));
+                    }
+                    ComponentDescription::Lod(lod) => {
+                        insert(world, *entity, Lod::from(lod));
+                    }
+                    ComponentDescription::Parent(parent) => {
+                        insert(
+                            world,
+                            *entity,
+                            Parent {
+                                entity: entities[*parent],
+                            },
+                        );
+                    }
+                    ComponentDescription::Custom { name, value } => {
+                        let component = registered
+                            .iter()
+                            .find(|component| component.name == *name)
+                            .expect("Scene was validated");
+                        (component.insert)(world, *entity, value.clone())
+                            .expect("Scene was validated");
+                    }
+                }
+            }
+        }
+
+        Ok(entities)
+    }
+
+    /// Captures the entities of a running world that have at least one saveable component.
+    pub fn from_world(world: &specs::World) -> Self {
+        let entities = world.entities();
+        let models = world.read_storage::<Model>();
+        let primitives = world.read_storage::<Primitive>();
+        let transforms = world.read_storage::<Transform>();
+        let cameras = world.read_storage::<Camera>();
+        let lights = world.read_storage::<Light>();
+        let lods = world.read_storage::<Lod>();
+        let parents = world.read_storage::<Parent>();
+        let registered = world
+            .try_fetch::<SceneComponents>()
+            .map(|components| components.components.clone())
+            .unwrap_or_default();
+
+        // Each entity's registered components, in the order they were registered
+        let mut custom = (&entities)
+            .join()
+            .map(|entity| {
+                let components = registered
+                    .iter()
+                    .filter_map(|component| match (component.save)(world, entity)? {
+                        Ok(value) => Some(ComponentDescription::Custom {
+                            name: component.name.clone(),
+                            value,
+                        }),
+                        Err(error) => {
+                            log::warn!(
+                                "Couldn't save the {} component of {:?}: {}",
+                                component.name,
+                                entity,
+                                error
+                            );
+                            None
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                (entity, components)
+            })
+            .collect::<HashMap<_, _>>();
+
+        let saved = (&entities)
+            .join()
+            .filter(|e| {
+                models.contains(*e)
+                    || primitives.contains(*e)
+                    || transforms.contains(*e)
+                    || cameras.contains(*e)
+                    || lights.contains(*e)
+                    || lods.contains(*e)
+                    || parents.contains(*e)
+                    || !custom[e].is_empty()
+            })
+            .collect::<Vec<_>>();
+
+        let indices = saved
+            .iter()
+            .enumerate()
+            .map(|(index, entity)| (*entity, index))
+            .collect::<HashMap<_, _>>();
+
+        let entities = saved
+            .iter()
+            .map(|entity| {
+                let mut components = Vec::new();
+
+                if let Some(model) = models.get(*entity) {
+                    components.push(ComponentDescription::Model(model.into()));
+                }
+                if let Some(primitive) = primitives.get(*entity) {
+                    components.push(ComponentDescription::Primitive(primitive.clone()));
+                }
+                if let Some(transform) = transforms.get(*entity) {
+                    components.push(ComponentDescription::Transform(transform.into()));
+                }
+                if let Some(camera) = cameras.get(*entity) {
+                    components.push(ComponentDescription::Camera(camera.into()));
+                }
+                if let Some(light) = lights.get(*entity) {
+                    components.push(ComponentDescription::Light(light.into()));
+                }
+                if let Some(lod) = lods.get(*entity) {
+                    components.push(ComponentDescription::Lod(lod.into()));
+                }
+                if let Some(parent) = parents.get(*entity) {
+                    match indices.get(&parent.entity) {
+                        Some(index) => components.push(ComponentDescription::Parent(*index)),
+                        None => log::warn!(
+                            "Parent of {:?} is not saveable, saving it without a parent",
+                            entity
+                        ),
+                    }
+                }
+                components.extend(custom.remove(entity).unwrap_or_default());
+
+                components
+            })
+            .collect();
+
+        let environment = world.try_fetch::<Environment>().and_then(|environment| {
+            Some(EnvironmentDescription {
+                file: environment.source.clone()?,
+                intensity: environment.intensity,
+            })
+        });
+
+        Self {
+            entities,
+            environment,
+        }
+    }
+
+    fn validate(&self, registered: &[SceneComponent]) -> Result<(), SceneError> {
+        let mut parents = vec![None; self.entities.len()];
+
+        for (entity, components) in self.entities.iter().enumerate() {
+            let mut seen = Vec::new();
+
+            for component in components {
+                let name = component.name();
+                if seen.contains(&name) {
+                    return Err(SceneError::DuplicateComponent {
+                        entity,
+                        component: name.to_string(),
+                    });
+                }
+                seen.push(name);
+
+                match component {
+                    ComponentDescription::Parent(parent) => {
+                        if *parent >= self.entities.len() || *parent == entity {
+                            return Err(SceneError::InvalidParent {
+                                entity,
+                                parent: *parent,
+                            });
+                        }
+                        parents[entity] = Some(*parent);
+                    }
+                    ComponentDescription::Custom { name, value } => {
+                        let component = registered
+                            .iter()
+                            .find(|component| component.name == *name)
+                            .ok_or_else(|| SceneError::UnknownComponent {
+                                entity,
+                                component: name.clone(),
+                            })?;
+                        (component.check)(value.clone()).map_err(|error| {
+                            SceneError::InvalidComponent {
+                                entity,
+                                component: name.clone(),
+                                error,
+                            }
+                        })?;
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        // Walks up from every entity, stopping at a root or at an entity an earlier walk went
+        // through, so each entity is visited once
+        let mut walked_from = vec![None; self.entities.len()];
+        for start in 0..self.entities.len() {
+            let mut current = Some(start);
+            while let Some(entity) = current {
+                match walked_from[entity] {
+                    Some(walk) if walk == start => return Err(SceneError::ParentCycle { entity }),
+                    Some(_) => break,
+                    None => walked_from[entity] = Some(start),
+                }
+                current = parents[entity];
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl ComponentDescription {
+    fn name(&self) -> &str {
+        match self {
+            ComponentDescription::Model(_) => "Model",
+            ComponentDescription::Primitive(_) => "Primitive",
+            ComponentDescription::Transform(_) => "Transform",
+            ComponentDescription::Camera(_) => "Camera",
+            ComponentDescription::Light(_) => "Light",
+            ComponentDescription::Lod(_) => "Lod",
+            ComponentDescription::Parent(_) => "Parent",
+            ComponentDescription::Custom { name, .. } => name,
+        }
+    }
+}
+
+/// The application's components that scenes can describe, by the name they're given in scenes.
+///
+/// Components are converted through `ron::Value`, which has no enums, so only components made of
+/// numbers, strings, options, sequences, maps and structs can be registered.
+#[derive(Default)]
+pub struct SceneComponents {
+    components: Vec<SceneComponent>,
+}
+
+#[derive(Clone)]
+struct SceneComponent {
+    name: String,
+    check: fn(ron::Value) -> Result<(), ron::Error>,
+    insert: fn(&mut specs::World, specs::Entity, ron::Value) -> Result<(), ron::Error>,
+    save: fn(&specs::World, specs::Entity) -> Option<Result<ron::Value, ron::Error>>,
+}
+
+impl SceneComponents {
+    /// Lets scenes describe `C` as `Custom(name: <name>, value: ...)`. The component's storage
+    /// must already be registered in the world.
+    pub fn register<C>(&mut self, name: impl Into<String>)
+    where
+        C: specs::Component + Serialize + DeserializeOwned,
+    {
+        let name = name.into();
+        self.components.retain(|component| component.name != name);
+        self.components.push(SceneComponent {
+            name,
+            check: |value| value.into_rust::<C>().map(drop),
+            insert: |world, entity, value| {
+                insert(world, entity, value.into_rust::<C>()?);
+                Ok(())
+            },
+            save: |world, entity| {
+                let storage = world.read_storage::<C>();
+                let component = storage.get(entity)?;
+                Some(to_value(component).and_then(|value| {
+                    // An enum anywhere in the component is lost on the way to a value
+                    value.clone().into_rust::<C>()?;
+                    Ok(value)
+                }))
+            },
+        });
+    }
+}
+
+fn to_value<T: Serialize>(value: &T) -> Result<ron::Value, ron::Error> {
+    let text = ron::to_string(value)?;
+    ron::from_str(&text).map_err(|error| error.code)
+}
+
+/// Lets components be written as `Model(file: "cube.obj")` instead of `Model((file: "cube.obj"))`.
+fn ron_options() -> ron::Options {
+    ron::Options::default()
+        .with_default_extension(ron::extensions::Extensions::UNWRAP_VARIANT_NEWTYPES)
+}
+
+/// Loads an environment with the world's asset paths and GPU resources. The environment keeps
+/// `file` as its source, so that saving the world doesn't make the path absolute.
+pub fn load_environment(world: &specs::World, file: &str) -> Result<Environment, TextureError> {
+    let path = world.read_resource::<AssetPaths>().resolve(file, None);
+    let mut environment = Environment::load(
+        &path,
+        &world.read_resource::<MaterialManager>(),
+        &world.read_resource::<wgpu::Device>(),
+        &world.read_resource::<wgpu::Queue>(),
+    )?;
+    environment.source = Some(file.to_string());
+    Ok(environment)
+}
+
+fn insert<C: specs::Component>(world: &mut specs::World, entity: specs::Entity, component: C) {
+    world
+        .write_storage::<C>()
+        .insert(entity, component)
+        .expect("Entity was just created");
+}
+
+impl From<&ModelDescription> for Model {
+    fn from(description: &ModelDescription) -> Self {
+        Self {
+            file: description.file.clone(),
+        }
+    }
+}
+
+impl From<&Model> for ModelDescription {
+    fn from(model: &Model) -> Self {
+        Self {
+            file: model.file.clone(),
+        }
+    }
+}
+
+impl Default for TransformDescription {
+    fn default() -> Self {
+        (&Transform::default()).into()
+    }
+}
+
+impl From<&TransformDescription> for Transform {
+    fn from(description: &TransformDescription) -> Self {
+        let [x, y, z, w] = description.rotation;
+
+        Self {
+            position: description.position.into(),
+            rotation: cgmath::Quaternion::new(w, x, y, z),
+            scale: description.scale.into(),
+            ..Default::default()
+        }
+    }
+}
+
+impl From<&Transform> for TransformDescription {
+    fn from(transform: &Transform) -> Self {
+        let rotation = transform.rotation;
+
+        Self {
+            position: transform.position.into(),
+            rotation: [rotation.v.x, rotation.v.y, rotation.v.z, rotation.s],
+            scale: transform.scale.into(),
+        }
+    }
+}
+
+impl Default for CameraDescription {
+    fn default() -> Self {
+        (&Camera::default()).into()
+    }
+}
+
+impl From<&CameraDescription> for Camera {
+    fn from(description: &CameraDescription) -> Self {
+        Self {
+            target: description.target.into(),
+            up: description.up.into(),
+            fovy: description.fovy,
+            znear: description.znear,
+            zfar: description.zfar,
+            background: description.background.clone(),
+            exposure: description.exposure,
+            tone_mapper: description.tone_mapper,
+            post_effects: description.post_effects.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+impl From<&Camera> for CameraDescription {
+    fn from(camera: &Camera) -> Self {
+        Self {
+            target: camera.target.into(),
+            up: camera.up.into(),
+            fovy: camera.fovy,
+            znear: camera.znear,
+            zfar: camera.zfar,
+            background: camera.background.clone(),
+            exposure: camera.exposure,
+            tone_mapper: camera.tone_mapper,
+            post_effects: camera.post_effects.clone(),
+        }
+    }
+}
+
+impl Default for LightDescription {
+    fn default() -> Self {
+        (&Light::default()).into()
+    }
+}
+
+impl From<&LightDescription> for Light {
+    fn from(description: &LightDescription) -> Self {
+        Self {
+            kind: description.kind,
+            color: description.color,
+            intensity: description.intensity,
+        }
+    }
+}
+
+impl From<&Light> for LightDescription {
+    fn from(light: &Light) -> Self {
+        Self {
+            kind: light.kind,
+            color: light.color,
+            intensity: light.intensity,
+        }
+    }
+}
+
+impl From<&LodDescription> for Lod {
+    fn from(description: &LodDescription) -> Self {
+        Self {
+            levels: description.levels.clone(),
+            hysteresis: description.hysteresis,
+        }
+    }
+}
+
+impl From<&Lod> for LodDescription {
+    fn from(lod: &Lod) -> Self {
+        Self {
+            levels: lod.levels.clone(),
+            hysteresis: lod.hysteresis,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use specs::{Component, VecStorage};
+
+    use super::*;
+
+    #[derive(Component, Serialize, Deserialize, Debug, PartialEq)]
+    #[storage(VecStorage)]
+    struct Spin {
+        speed: f32,
+        axis: [f32; 3],
+    }
+
+    fn world() -> specs::World {
+        let mut world = specs::World::new();
+        world.register::<Model>();
+        world.register::<Renderer>();
+        world.register::<Primitive>();
+        world.register::<Transform>();
+        world.register::<Camera>();
+        world.register::<Light>();
+        world.register::<Lod>();
+        world.register::<Parent>();
+        world.register::<Spin>();
+        let mut components = SceneComponents::default();
+        components.register::<Spin>("Spin");
+        world.insert(components);
+        world
+    }
+
+    /// The scene's entities, each with the given parent.
+    fn with_parents(parents: &[Option<usize>]) -> Scene {
+        Scene {
+            entities: parents
+                .iter()
+                .map(|parent| {
+                    parent
+                        .map(ComponentDescription::Parent)
+                        .into_iter()
+                        .collect()
+                })
+                .collect(),
+            environment: None,
+        }
+    }
+
+    fn validate(scene: &Scene) -> Result<(), SceneError> {
+        scene.validate(&world().fetch::<SceneComponents>().components)
+    }
+
+    #[test]
+    fn round_trips_through_ron() {
+        let mut scene = Scene::from_model("cube.obj");
+        scene.entities[1].push(ComponentDescription::Parent(0));
+        scene.entities[2].push(ComponentDescription::Primitive(Primitive::Torus {
+            segments: 12,
+            sides: 8,
+            radius: 0.5,
+            tube_radius: 0.25,
+        }));
+        scene.environment = Some(EnvironmentDescription {
+            file: "sky.hdr".to_string(),
+            intensity: 2.0,
+        });
+
+        let text = scene.to_ron().unwrap();
+        let parsed = Scene::from_ron(&text).unwrap();
+        assert_eq!(parsed.to_ron().unwrap(), text);
+        assert_eq!(format!("{parsed:?}"), format!("{scene:?}"));
+    }
+
+    #[test]
+    fn unknown_components_and_fields_are_rejected() {
+        for text in [
+            "(entities: [[Spin(speed: 1.0)]])",
+            "(entities: [[Model(file: \"cube.obj\", scale: 2.0)]])",
+            "(entities: [], lights: [])",
+        ] {
+            assert!(
+                matches!(Scene::from_ron(text), Err(SceneError::Parse(_))),
+                "{text}"
+            );
+        }
+    }
+
+    #[test]
+    fn duplicate_components_are_rejected() {
+        let scene = Scene::from_ron("(entities: [[Transform(), Camera(), Transform()]])").unwrap();
+        assert!(matches!(
+            validate(&scene),
+            Err(SceneError::DuplicateComponent { entity: 0, component }) if component == "Transform"
+        ));
+    }
+
+    #[test]
+    fn parents_must_be_other_entities_in_the_scene() {
+        assert!(validate(&with_parents(&[None, Some(0), Some(1)])).is_ok());
+        assert!(matches!(
+            validate(&with_parents(&[None, Some(1)])),
+            Err(SceneError::InvalidParent {
+                entity: 1,
+                parent: 1
+            })
+        ));
+        assert!(matches!(
+            validate(&with_parents(&[None, Some(2)])),
+            Err(SceneError::InvalidParent {
+                entity: 1,
+                parent: 2
+            })
+        ));
+    }
+
+    #[test]
+    fn parent_cycles_are_rejected() {
+        for parents in [
+            &[Some(1), Some(0)][..],
+            &[None, Some(2), Some(3), Some(1)],
+            &[Some(3), None, Some(0), Some(2), Some(1)],
+        ] {
+            assert!(
+                matches!(
+                    validate(&with_parents(parents)),
+                    Err(SceneError::ParentCycle { .. })
+                ),
+                "{parents:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn registered_components_are_loaded_and_saved() {
+        let mut world = world();
+        let scene = Scene::from_ron(
+            "(entities: [[Transform(), Custom(name: \"Spin\", value: (speed: 2, axis: (0, 1, 0)))]])",
+        )
+        .unwrap();
+        let entities = scene.instantiate(&mut world).unwrap();
+        assert_eq!(
+            world.read_storage::<Spin>().get(entities[0]),
+            Some(&Spin {
+                speed: 2.0,
+                axis: [0.0, 1.0, 0.0]
+            })
+        );
+
+        let saved = Scene::from_world(&world).to_ron().unwrap();
+        let mut reloaded = self::world();
+        let entities = Scene::from_ron(&saved)
+            .unwrap()
+            .instantiate(&mut reloaded)
+            .unwrap();
+        assert_eq!(
+            reloaded.read_storage::<Spin>().get(entities[0]),
+            world.read_storage::<Spin>().get(entities[0])
+        );
+    }
+
+    #[test]
+    fn unregistered_or_invalid_components_leave_the_world_untouched() {
+        let mut world = world();
+        let unknown =
+            Scene::from_ron("(entities: [[Transform()], [Custom(name: \"Orbit\", value: ())]])")
+                .unwrap();
+        assert!(matches!(
+            unknown.instantiate(&mut world),
+            Err(SceneError::UnknownComponent { entity: 1, component }) if component == "Orbit"
+        ));
+
+        let invalid =
+            Scene::from_ron("(entities: [[Custom(name: \"Spin\", value: (speed: \"fast\"))]])")
+                .unwrap();
+        assert!(matches!(
+            invalid.instantiate(&mut world),
+            Err(SceneError::InvalidComponent { entity: 0, .. })
+        ));
+
+        assert_eq!((&world.entities()).join().count(), 0);
+    }
+}
@@ -0,0 +1,320 @@
+// Vertex shader
+struct CameraUniform {
+    view_proj: mat4x4<f32>,
+    model: mat4x4<f32>,
+    normal: mat4x4<f32>,
+    view_position: vec4<f32>,
+};
+@group(1) @binding(0) // 1.
+var<uniform> camera: CameraUniform;
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) tex_coords: vec2<f32>,
+    @location(2) normal: vec3<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+    @location(1) world_position: vec3<f32>,
+    @location(2) world_normal: vec3<f32>,
+    @location(3) color: vec4<f32>,
+    @location(4) tex_coords1: vec2<f32>,
+    // Zero for meshes without tangents
+    @location(5) world_tangent: vec4<f32>,
+}
+
+fn transform_vertex(model: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+    out.tex_coords = model.tex_coords;
+    out.world_position = (camera.model * vec4<f32>(model.position, 1.0)).xyz;
+    out.world_normal = (camera.normal * vec4<f32>(model.normal, 0.0)).xyz;
+    out.clip_position = camera.view_proj * vec4<f32>(model.position, 1.0); // 2.
+    out.color = vec4<f32>(1.0);
+    out.tex_coords1 = model.tex_coords;
+    out.world_tangent = vec4<f32>(0.0);
+    return out;
+}
+
+fn transform_tangent(tangent: vec4<f32>) -> vec4<f32> {
+    return vec4<f32>((camera.model * vec4<f32>(tangent.xyz, 0.0)).xyz, tangent.w);
+}
+
+@vertex
+fn vs_main(
+    model: VertexInput,
+) -> VertexOutput {
+    return transform_vertex(model);
+}
+
+// The optional attributes are read from buffers of their own, with an entry point for each
+// combination of them.
+@vertex
+fn vs_colored(
+    model: VertexInput,
+    @location(3) color: vec4<f32>,
+) -> VertexOutput {
+    var out = transform_vertex(model);
+    out.color = color;
+    return out;
+}
+
+@vertex
+fn vs_tex_coords1(
+    model: VertexInput,
+    @location(4) tex_coords1: vec2<f32>,
+) -> VertexOutput {
+    var out = transform_vertex(model);
+    out.tex_coords1 = tex_coords1;
+    return out;
+}
+
+@vertex
+fn vs_colored_tex_coords1(
+    model: VertexInput,
+    @location(3) color: vec4<f32>,
+    @location(4) tex_coords1: vec2<f32>,
+) -> VertexOutput {
+    var out = transform_vertex(model);
+    out.color = color;
+    out.tex_coords1 = tex_coords1;
+    return out;
+}
+
+@vertex
+fn vs_tangents(
+    model: VertexInput,
+    @location(5) tangent: vec4<f32>,
+) -> VertexOutput {
+    var out = transform_vertex(model);
+    out.world_tangent = transform_tangent(tangent);
+    return out;
+}
+
+@vertex
+fn vs_colored_tangents(
+    model: VertexInput,
+    @location(3) color: vec4<f32>,
+    @location(5) tangent: vec4<f32>,
+) -> VertexOutput {
+    var out = transform_vertex(model);
+    out.color = color;
+    out.world_tangent = transform_tangent(tangent);
+    return out;
+}
+
+@vertex
+fn vs_tex_coords1_tangents(
+    model: VertexInput,
+    @location(4) tex_coords1: vec2<f32>,
+    @location(5) tangent: vec4<f32>,
+) -> VertexOutput {
+    var out = transform_vertex(model);
+    out.tex_coords1 = tex_coords1;
+    out.world_tangent = transform_tangent(tangent);
+    return out;
+}
+
+@vertex
+fn vs_colored_tex_coords1_tangents(
+    model: VertexInput,
+    @location(3) color: vec4<f32>,
+    @location(4) tex_coords1: vec2<f32>,
+    @location(5) tangent: vec4<f32>,
+) -> VertexOutput {
+    var out = transform_vertex(model);
+    out.color = color;
+    out.tex_coords1 = tex_coords1;
+    out.world_tangent = transform_tangent(tangent);
+    return out;
+}
+
+
+// Fragment shader
+@group(0) @binding(0)
+var t_base_color: texture_2d<f32>;
+@group(0)@binding(1)
+var s_material: sampler;
+
+struct MaterialUniform {
+    base_color: vec4<f32>,
+    emissive: vec3<f32>,
+    normal_scale: f32,
+    metallic: f32,
+    roughness: f32,
+    occlusion_strength: f32,
+    alpha_cutoff: f32,
+    occlusion_tex_coord: u32,
+};
+@group(0) @binding(2)
+var<uniform> material: MaterialUniform;
+
+@group(0) @binding(3)
+var t_metallic: texture_2d<f32>;
+@group(0) @binding(4)
+var t_roughness: texture_2d<f32>;
+@group(0) @binding(5)
+var t_normal: texture_2d<f32>;
+@group(0) @binding(6)
+var t_occlusion: texture_2d<f32>;
+@group(0) @binding(7)
+var t_emissive: texture_2d<f32>;
+
+struct Light {
+    // w is 0 for directional lights, whose xyz points towards the light
+    position: vec4<f32>,
+    color: vec4<f32>,
+};
+
+struct Lights {
+    ambient: vec3<f32>,
+    count: u32,
+    environment_intensity: f32,
+    max_reflection_lod: f32,
+    lights: array<Light, 8>,
+};
+@group(2) @binding(0)
+var<uniform> lights: Lights;
+@group(2) @binding(1)
+var t_irradiance: texture_cube<f32>;
+@group(2) @binding(2)
+var t_prefiltered: texture_cube<f32>;
+@group(2) @binding(3)
+var t_brdf_lut: texture_2d<f32>;
+@group(2) @binding(4)
+var s_environment: sampler;
+
+const PI: f32 = 3.14159265359;
+
+// Uses the mesh's tangents where it has them, and otherwise builds a tangent frame from
+// screen-space derivatives, so meshes don't need tangents.
+fn perturb_normal(normal: vec3<f32>, position: vec3<f32>, uv: vec2<f32>, sampled: vec3<f32>, vertex_tangent: vec4<f32>) -> vec3<f32> {
+    let tangent_normal = (sampled * 2.0 - 1.0) * vec3<f32>(material.normal_scale, material.normal_scale, 1.0);
+
+    // Taken before branching, as derivatives are undefined in non-uniform control flow
+    let dp1 = dpdx(position);
+    let dp2 = dpdy(position);
+    let duv1 = dpdx(uv);
+    let duv2 = dpdy(uv);
+
+    if dot(vertex_tangent.xyz, vertex_tangent.xyz) > 1e-12 {
+        // Re-orthogonalised, as interpolation bends it away from the normal
+        let t = normalize(vertex_tangent.xyz - normal * dot(normal, vertex_tangent.xyz));
+        let b = cross(normal, t) * select(1.0, -1.0, vertex_tangent.w < 0.0);
+        return normalize(mat3x3<f32>(t, b, normal) * tangent_normal);
+    }
+
+    let dp2perp = cross(dp2, normal);
+    let dp1perp = cross(normal, dp1);
+    let tangent = dp2perp * duv1.x + dp1perp * duv2.x;
+    let bitangent = dp2perp * duv1.y + dp1perp * duv2.y;
+
+    let length_squared = max(dot(tangent, tangent), dot(bitangent, bitangent));
+    if length_squared < 1e-12 {
+        return normal;
+    }
+    let scale = inverseSqrt(length_squared);
+    let frame = mat3x3<f32>(tangent * scale, bitangent * scale, normal);
+    return normalize(frame * tangent_normal);
+}
+
+fn distribution_ggx(n_dot_h: f32, roughness: f32) -> f32 {
+    let a = roughness * roughness;
+    let a2 = a * a;
+    let d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    return a2 / (PI * d * d);
+}
+
+fn geometry_schlick_ggx(n_dot_x: f32, roughness: f32) -> f32 {
+    let r = roughness + 1.0;
+    let k = r * r / 8.0;
+    return n_dot_x / (n_dot_x * (1.0 - k) + k);
+}
+
+fn fresnel_schlick(cos_theta: f32, f0: vec3<f32>) -> vec3<f32> {
+    return f0 + (1.0 - f0) * pow(clamp(1.0 - cos_theta, 0.0, 1.0), 5.0);
+}
+
+// Rough surfaces reflect less at grazing angles, as their microfacets face many directions.
+fn fresnel_schlick_roughness(cos_theta: f32, f0: vec3<f32>, roughness: f32) -> vec3<f32> {
+    return f0 + (max(vec3<f32>(1.0 - roughness), f0) - f0) * pow(clamp(1.0 - cos_theta, 0.0, 1.0), 5.0);
+}
+
+// Lights a fragment, returning its colour and the alpha of its base colour.
+fn shade(in: VertexOutput) -> vec4<f32> {
+    let base_color = textureSample(t_base_color, s_material, in.tex_coords) * material.base_color * in.color;
+    let metallic = textureSample(t_metallic, s_material, in.tex_coords).b * material.metallic;
+    let roughness = clamp(textureSample(t_roughness, s_material, in.tex_coords).g * material.roughness, 0.04, 1.0);
+    let sampled_normal = textureSample(t_normal, s_material, in.tex_coords).rgb;
+    let occlusion_uv = select(in.tex_coords, in.tex_coords1, material.occlusion_tex_coord == 1u);
+    let occlusion = 1.0 + material.occlusion_strength * (textureSample(t_occlusion, s_material, occlusion_uv).r - 1.0);
+    let emissive = textureSample(t_emissive, s_material, in.tex_coords).rgb * material.emissive;
+
+    let n = perturb_normal(normalize(in.world_normal), in.world_position, in.tex_coords, sampled_normal, in.world_tangent);
+    let v = normalize(camera.view_position.xyz - in.world_position);
+    let n_dot_v = max(dot(n, v), 1e-4);
+
+    let albedo = base_color.rgb;
+    let f0 = mix(vec3<f32>(0.04), albedo, metallic);
+
+    var radiance_out = vec3<f32>(0.0);
+    for (var i = 0u; i < min(lights.count, 8u); i += 1u) {
+        let light = lights.lights[i];
+
+        var l: vec3<f32>;
+        var radiance = light.color.rgb;
+        if light.position.w == 0.0 {
+            l = normalize(light.position.xyz);
+        } else {
+            let to_light = light.position.xyz - in.world_position;
+            l = normalize(to_light);
+            radiance /= max(dot(to_light, to_light), 1e-4);
+        }
+
+        let h = normalize(v + l);
+        let n_dot_l = max(dot(n, l), 0.0);
+        let n_dot_h = max(dot(n, h), 0.0);
+
+        let d = distribution_ggx(n_dot_h, roughness);
+        let g = geometry_schlick_ggx(n_dot_v, roughness) * geometry_schlick_ggx(n_dot_l, roughness);
+        let f = fresnel_schlick(max(dot(h, v), 0.0), f0);
+
+        let specular = d * g * f / (4.0 * n_dot_v * n_dot_l + 1e-4);
+        let diffuse = (1.0 - f) * (1.0 - metallic) * albedo / PI;
+
+        radiance_out += (diffuse + specular) * radiance * n_dot_l;
+    }
+
+    // Image-based lighting, with the split-sum approximation for specular reflections
+    let f = fresnel_schlick_roughness(n_dot_v, f0, roughness);
+    let irradiance = textureSample(t_irradiance, s_environment, n).rgb;
+    let reflection = reflect(-v, n);
+    let prefiltered = textureSampleLevel(t_prefiltered, s_environment, reflection, roughness * lights.max_reflection_lod).rgb;
+    let brdf = textureSample(t_brdf_lut, s_environment, vec2<f32>(n_dot_v, roughness)).rg;
+    let environment_diffuse = (1.0 - f) * (1.0 - metallic) * irradiance * albedo;
+    let environment_specular = prefiltered * (f * brdf.x + brdf.y);
+    let environment = (environment_diffuse + environment_specular) * lights.environment_intensity;
+
+    let ambient = (lights.ambient * albedo + environment) * occlusion;
+    return vec4<f32>(ambient + radiance_out + emissive, base_color.a);
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(shade(in).rgb, 1.0);
+}
+
+@fragment
+fn fs_cutout(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = shade(in);
+    if color.a < material.alpha_cutoff {
+        discard;
+    }
+    return vec4<f32>(color.rgb, 1.0);
+}
+
+@fragment
+fn fs_blend(in: VertexOutput) -> @location(0) vec4<f32> {
+    return shade(in);
+}
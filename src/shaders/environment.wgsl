@@ -0,0 +1,190 @@
+// Precomputes image-based lighting: the environment cubemap, its diffuse irradiance and
+// prefiltered specular convolutions, and the split-sum BRDF lookup table.
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    // (0, 0), (2, 0), (0, 2) covers the whole screen once clipped
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    out.tex_coords = uv;
+    return out;
+}
+
+struct Parameters {
+    face: u32,
+    roughness: f32,
+    // Mip level of the source to sample
+    lod: f32,
+    // Size of the source cubemap's faces
+    source_size: f32,
+};
+
+// Or the image of a single face, for fs_face
+@group(0) @binding(0)
+var t_equirectangular: texture_2d<f32>;
+@group(0) @binding(1)
+var s_source: sampler;
+@group(0) @binding(2)
+var<uniform> parameters: Parameters;
+@group(0) @binding(3)
+var t_cube: texture_cube<f32>;
+
+const PI: f32 = 3.14159265359;
+
+// The direction through a texel of a cubemap face, in the order +X, -X, +Y, -Y, +Z, -Z.
+fn face_direction(face: u32, uv: vec2<f32>) -> vec3<f32> {
+    let p = uv * 2.0 - 1.0;
+    switch face {
+        case 0u: { return normalize(vec3<f32>(1.0, -p.y, -p.x)); }
+        case 1u: { return normalize(vec3<f32>(-1.0, -p.y, p.x)); }
+        case 2u: { return normalize(vec3<f32>(p.x, 1.0, p.y)); }
+        case 3u: { return normalize(vec3<f32>(p.x, -1.0, -p.y)); }
+        case 4u: { return normalize(vec3<f32>(p.x, -p.y, 1.0)); }
+        default: { return normalize(vec3<f32>(-p.x, -p.y, -1.0)); }
+    }
+}
+
+@fragment
+fn fs_equirectangular(in: VertexOutput) -> @location(0) vec4<f32> {
+    let direction = face_direction(parameters.face, in.tex_coords);
+    let uv = vec2<f32>(
+        atan2(direction.z, direction.x) / (2.0 * PI) + 0.5,
+        acos(clamp(direction.y, -1.0, 1.0)) / PI,
+    );
+    return vec4<f32>(textureSampleLevel(t_equirectangular, s_source, uv, parameters.lod).rgb, 1.0);
+}
+
+// Copies an image onto a single face.
+@fragment
+fn fs_face(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(textureSampleLevel(t_equirectangular, s_source, in.tex_coords, parameters.lod).rgb, 1.0);
+}
+
+fn tangent_frame(normal: vec3<f32>) -> mat3x3<f32> {
+    var up = vec3<f32>(0.0, 1.0, 0.0);
+    if abs(normal.y) > 0.999 {
+        up = vec3<f32>(0.0, 0.0, 1.0);
+    }
+    let tangent = normalize(cross(up, normal));
+    let bitangent = cross(normal, tangent);
+    return mat3x3<f32>(tangent, bitangent, normal);
+}
+
+@fragment
+fn fs_irradiance(in: VertexOutput) -> @location(0) vec4<f32> {
+    let frame = tangent_frame(face_direction(parameters.face, in.tex_coords));
+
+    var irradiance = vec3<f32>(0.0);
+    var count = 0.0;
+    let step = 0.05;
+    for (var phi = 0.0; phi < 2.0 * PI; phi += step) {
+        for (var theta = 0.0; theta < 0.5 * PI; theta += step) {
+            let direction = frame * vec3<f32>(sin(theta) * cos(phi), sin(theta) * sin(phi), cos(theta));
+            irradiance += textureSampleLevel(t_cube, s_source, direction, parameters.lod).rgb * cos(theta) * sin(theta);
+            count += 1.0;
+        }
+    }
+
+    return vec4<f32>(PI * irradiance / count, 1.0);
+}
+
+fn radical_inverse(index: u32) -> f32 {
+    var bits = index;
+    bits = (bits << 16u) | (bits >> 16u);
+    bits = ((bits & 0x55555555u) << 1u) | ((bits & 0xAAAAAAAAu) >> 1u);
+    bits = ((bits & 0x33333333u) << 2u) | ((bits & 0xCCCCCCCCu) >> 2u);
+    bits = ((bits & 0x0F0F0F0Fu) << 4u) | ((bits & 0xF0F0F0F0u) >> 4u);
+    bits = ((bits & 0x00FF00FFu) << 8u) | ((bits & 0xFF00FF00u) >> 8u);
+    return f32(bits) * 2.3283064365386963e-10;
+}
+
+fn hammersley(index: u32, count: u32) -> vec2<f32> {
+    return vec2<f32>(f32(index) / f32(count), radical_inverse(index));
+}
+
+// A half vector around `normal`, distributed like the GGX lobe of the given roughness.
+fn importance_sample_ggx(xi: vec2<f32>, normal: vec3<f32>, roughness: f32) -> vec3<f32> {
+    let a = roughness * roughness;
+    let phi = 2.0 * PI * xi.x;
+    let cos_theta = sqrt((1.0 - xi.y) / (1.0 + (a * a - 1.0) * xi.y));
+    let sin_theta = sqrt(1.0 - cos_theta * cos_theta);
+    return normalize(tangent_frame(normal) * vec3<f32>(cos(phi) * sin_theta, sin(phi) * sin_theta, cos_theta));
+}
+
+fn distribution_ggx(n_dot_h: f32, roughness: f32) -> f32 {
+    let a = roughness * roughness;
+    let a2 = a * a;
+    let d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    return a2 / (PI * d * d);
+}
+
+const PREFILTER_SAMPLES: u32 = 128u;
+
+@fragment
+fn fs_prefilter(in: VertexOutput) -> @location(0) vec4<f32> {
+    let normal = face_direction(parameters.face, in.tex_coords);
+    let roughness = parameters.roughness;
+    if roughness <= 0.0 {
+        return vec4<f32>(textureSampleLevel(t_cube, s_source, normal, parameters.lod).rgb, 1.0);
+    }
+
+    // Sampling a lower mip for unlikely directions avoids bright speckles
+    let texel_solid_angle = 4.0 * PI / (6.0 * parameters.source_size * parameters.source_size);
+
+    var color = vec3<f32>(0.0);
+    var weight = 0.0;
+    for (var i = 0u; i < PREFILTER_SAMPLES; i += 1u) {
+        let h = importance_sample_ggx(hammersley(i, PREFILTER_SAMPLES), normal, roughness);
+        let l = normalize(2.0 * dot(normal, h) * h - normal);
+        let n_dot_l = dot(normal, l);
+        if n_dot_l > 0.0 {
+            let n_dot_h = max(dot(normal, h), 0.0);
+            let pdf = distribution_ggx(n_dot_h, roughness) * 0.25 + 1e-4;
+            let sample_solid_angle = 1.0 / (f32(PREFILTER_SAMPLES) * pdf);
+            let lod = 0.5 * log2(sample_solid_angle / texel_solid_angle);
+
+            color += textureSampleLevel(t_cube, s_source, l, max(lod, 0.0)).rgb * n_dot_l;
+            weight += n_dot_l;
+        }
+    }
+
+    return vec4<f32>(color / max(weight, 1e-4), 1.0);
+}
+
+const BRDF_SAMPLES: u32 = 256u;
+
+// x is the cosine between the normal and the view direction, y the roughness.
+@fragment
+fn fs_brdf(in: VertexOutput) -> @location(0) vec4<f32> {
+    let n_dot_v = max(in.tex_coords.x, 1e-3);
+    let roughness = in.tex_coords.y;
+    let v = vec3<f32>(sqrt(1.0 - n_dot_v * n_dot_v), 0.0, n_dot_v);
+    let normal = vec3<f32>(0.0, 0.0, 1.0);
+    let k = roughness * roughness / 2.0;
+
+    var scale = 0.0;
+    var bias = 0.0;
+    for (var i = 0u; i < BRDF_SAMPLES; i += 1u) {
+        let h = importance_sample_ggx(hammersley(i, BRDF_SAMPLES), normal, roughness);
+        let l = normalize(2.0 * dot(v, h) * h - v);
+        let n_dot_l = max(l.z, 0.0);
+        if n_dot_l > 0.0 {
+            let n_dot_h = max(h.z, 0.0);
+            let v_dot_h = max(dot(v, h), 0.0);
+            let g = (n_dot_v / (n_dot_v * (1.0 - k) + k)) * (n_dot_l / (n_dot_l * (1.0 - k) + k));
+            let g_visible = g * v_dot_h / (n_dot_h * n_dot_v);
+            let fresnel = pow(1.0 - v_dot_h, 5.0);
+
+            scale += (1.0 - fresnel) * g_visible;
+            bias += fresnel * g_visible;
+        }
+    }
+
+    return vec4<f32>(scale, bias, 0.0, 1.0) / vec4<f32>(f32(BRDF_SAMPLES), f32(BRDF_SAMPLES), 1.0, 1.0);
+}
@@ -0,0 +1,172 @@
+// Full-screen effects that read the previous step's image and write the next one.
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    // (0, 0), (2, 0), (0, 2) covers the whole screen once clipped
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    out.tex_coords = uv;
+    return out;
+}
+
+struct Effect {
+    intensity: f32,
+    threshold: f32,
+    smoothness: f32,
+    // Changes every frame, so that grain doesn't stand still
+    seed: f32,
+};
+
+@group(0) @binding(0)
+var t_source: texture_2d<f32>;
+@group(0) @binding(1)
+var s_source: sampler;
+
+@group(1) @binding(0)
+var<uniform> effect: Effect;
+@group(1) @binding(1)
+var t_lut: texture_2d<f32>;
+@group(1) @binding(2)
+var s_lut: sampler;
+
+fn source(uv: vec2<f32>) -> vec3<f32> {
+    return textureSampleLevel(t_source, s_source, uv, 0.0).rgb;
+}
+
+fn texel_size() -> vec2<f32> {
+    return 1.0 / vec2<f32>(textureDimensions(t_source));
+}
+
+// The images after tone mapping are stored as sRGB, so the shaders see linear values. Effects
+// that are defined on what the eye sees work on encoded values instead.
+fn to_srgb(color: vec3<f32>) -> vec3<f32> {
+    let c = clamp(color, vec3<f32>(0.0), vec3<f32>(1.0));
+    return select(1.055 * pow(c, vec3<f32>(1.0 / 2.4)) - 0.055, c * 12.92, c <= vec3<f32>(0.0031308));
+}
+
+fn to_linear(color: vec3<f32>) -> vec3<f32> {
+    let c = clamp(color, vec3<f32>(0.0), vec3<f32>(1.0));
+    return select(pow((c + 0.055) / 1.055, vec3<f32>(2.4)), c / 12.92, c <= vec3<f32>(0.04045));
+}
+
+// Averages 4x4 texels of the source with four bilinear samples.
+fn downsample(uv: vec2<f32>) -> vec3<f32> {
+    let offset = texel_size();
+    return (source(uv + vec2<f32>(-offset.x, -offset.y))
+        + source(uv + vec2<f32>(offset.x, -offset.y))
+        + source(uv + vec2<f32>(-offset.x, offset.y))
+        + source(uv + vec2<f32>(offset.x, offset.y))) * 0.25;
+}
+
+// Keeps what's brighter than the threshold, fading in over a knee of half the threshold.
+@fragment
+fn fs_bloom_prefilter(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = downsample(in.tex_coords);
+    let brightness = max(color.r, max(color.g, color.b));
+    let knee = effect.threshold * 0.5;
+    var soft = clamp(brightness - effect.threshold + knee, 0.0, 2.0 * knee);
+    soft = soft * soft / (4.0 * knee + 1e-4);
+    let contribution = max(soft, brightness - effect.threshold) / max(brightness, 1e-4);
+    return vec4<f32>(color * contribution, 1.0);
+}
+
+@fragment
+fn fs_bloom_downsample(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(downsample(in.tex_coords), 1.0);
+}
+
+// A 3x3 tent filter, added onto the larger level by the pipeline's blending.
+@fragment
+fn fs_bloom_upsample(in: VertexOutput) -> @location(0) vec4<f32> {
+    let offset = texel_size();
+    let uv = in.tex_coords;
+    var color = source(uv) * 4.0;
+    color += (source(uv + vec2<f32>(-offset.x, 0.0)) + source(uv + vec2<f32>(offset.x, 0.0))
+        + source(uv + vec2<f32>(0.0, -offset.y)) + source(uv + vec2<f32>(0.0, offset.y))) * 2.0;
+    color += source(uv + vec2<f32>(-offset.x, -offset.y)) + source(uv + vec2<f32>(offset.x, -offset.y))
+        + source(uv + vec2<f32>(-offset.x, offset.y)) + source(uv + vec2<f32>(offset.x, offset.y));
+    return vec4<f32>(color / 16.0, 1.0);
+}
+
+fn luma(color: vec3<f32>) -> f32 {
+    return dot(to_srgb(color), vec3<f32>(0.299, 0.587, 0.114));
+}
+
+// Timothy Lottes' FXAA, in the short form that blurs along the edge through each pixel.
+@fragment
+fn fs_fxaa(in: VertexOutput) -> @location(0) vec4<f32> {
+    let reduce_min = 1.0 / 128.0;
+    let reduce_mul = 1.0 / 8.0;
+    let span_max = 8.0;
+
+    let texel = texel_size();
+    let uv = in.tex_coords;
+    let rgb_m = source(uv);
+    let luma_nw = luma(source(uv + vec2<f32>(-1.0, -1.0) * texel));
+    let luma_ne = luma(source(uv + vec2<f32>(1.0, -1.0) * texel));
+    let luma_sw = luma(source(uv + vec2<f32>(-1.0, 1.0) * texel));
+    let luma_se = luma(source(uv + vec2<f32>(1.0, 1.0) * texel));
+    let luma_m = luma(rgb_m);
+    let luma_min = min(luma_m, min(min(luma_nw, luma_ne), min(luma_sw, luma_se)));
+    let luma_max = max(luma_m, max(max(luma_nw, luma_ne), max(luma_sw, luma_se)));
+
+    // Perpendicular to the gradient, so along the edge
+    var dir = vec2<f32>(-((luma_nw + luma_ne) - (luma_sw + luma_se)), (luma_nw + luma_sw) - (luma_ne + luma_se));
+    let dir_reduce = max((luma_nw + luma_ne + luma_sw + luma_se) * 0.25 * reduce_mul, reduce_min);
+    let rcp_dir_min = 1.0 / (min(abs(dir.x), abs(dir.y)) + dir_reduce);
+    dir = clamp(dir * rcp_dir_min, vec2<f32>(-span_max), vec2<f32>(span_max)) * texel;
+
+    let rgb_a = 0.5 * (source(uv + dir * (1.0 / 3.0 - 0.5)) + source(uv + dir * (2.0 / 3.0 - 0.5)));
+    let rgb_b = rgb_a * 0.5 + 0.25 * (source(uv - dir * 0.5) + source(uv + dir * 0.5));
+    let luma_b = luma(rgb_b);
+
+    // The wider blur crossed another edge if it left the local range
+    if luma_b < luma_min || luma_b > luma_max {
+        return vec4<f32>(rgb_a, 1.0);
+    }
+    return vec4<f32>(rgb_b, 1.0);
+}
+
+// Darkens towards the corners, starting `smoothness` away from them.
+@fragment
+fn fs_vignette(in: VertexOutput) -> @location(0) vec4<f32> {
+    // 1 in the corners
+    let distance = length(in.tex_coords - 0.5) * 1.41421356;
+    let shade = 1.0 - effect.intensity * smoothstep(1.0 - effect.smoothness, 1.0, distance);
+    return vec4<f32>(source(in.tex_coords) * shade, 1.0);
+}
+
+// Looks colours up in a LUT laid out as a strip of square slices, one per blue value, with red
+// across each slice and green down it.
+@fragment
+fn fs_color_grading(in: VertexOutput) -> @location(0) vec4<f32> {
+    let size = f32(textureDimensions(t_lut).y);
+    let color = to_srgb(source(in.tex_coords)) * (size - 1.0);
+
+    let slice = floor(color.b);
+    let next = min(slice + 1.0, size - 1.0);
+    let x = (color.r + 0.5) / (size * size);
+    let y = (color.g + 0.5) / size;
+    let a = textureSampleLevel(t_lut, s_lut, vec2<f32>(x + slice / size, y), 0.0).rgb;
+    let b = textureSampleLevel(t_lut, s_lut, vec2<f32>(x + next / size, y), 0.0).rgb;
+    return vec4<f32>(to_linear(mix(a, b, color.b - slice)), 1.0);
+}
+
+fn hash(p: vec2<f32>) -> f32 {
+    let q = fract(p * vec2<f32>(0.1031, 0.1030));
+    let r = q + dot(q, q.yx + 33.33);
+    return fract((r.x + r.y) * r.x);
+}
+
+@fragment
+fn fs_film_grain(in: VertexOutput) -> @location(0) vec4<f32> {
+    let noise = hash(in.clip_position.xy + effect.seed * 17.0) - 0.5;
+    let color = to_srgb(source(in.tex_coords)) + noise * effect.intensity;
+    return vec4<f32>(to_linear(color), 1.0);
+}
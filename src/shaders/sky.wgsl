@@ -0,0 +1,44 @@
+// Draws the background of the scene behind everything that was drawn before it.
+struct Sky {
+    // Maps clip space to view directions, ignoring the camera's position
+    inverse_view_proj: mat4x4<f32>,
+    top: vec4<f32>,
+    bottom: vec4<f32>,
+    // 0 for a gradient, 1 for a cubemap
+    mode: u32,
+};
+@group(0) @binding(0)
+var<uniform> sky: Sky;
+@group(0) @binding(1)
+var t_sky: texture_cube<f32>;
+@group(0) @binding(2)
+var s_sky: sampler;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) position: vec2<f32>,
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    // (0, 0), (2, 0), (0, 2) covers the whole screen once clipped
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    let position = vec2<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0);
+
+    var out: VertexOutput;
+    // On the far plane, so that only pixels no geometry covers pass the depth test
+    out.clip_position = vec4<f32>(position, 1.0, 1.0);
+    out.position = position;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let far = sky.inverse_view_proj * vec4<f32>(in.position, 1.0, 1.0);
+    let direction = normalize(far.xyz / far.w);
+
+    if sky.mode == 0u {
+        return vec4<f32>(mix(sky.bottom.rgb, sky.top.rgb, direction.y * 0.5 + 0.5), 1.0);
+    }
+    return vec4<f32>(textureSampleLevel(t_sky, s_sky, direction, 0.0).rgb, 1.0);
+}
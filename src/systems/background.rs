@@ -0,0 +1,139 @@
+use specs::Join;
+
+use crate::{
+    assets::AssetPaths,
+    components::{
+        background::{Background, Sky, SkyUniform},
+        hierarchy::GlobalTransform,
+        rendering::{Camera, Transform},
+    },
+    environment::{Environment, Skybox},
+    material_manager::{MaterialManager, SamplerOptions},
+    texture_data::{ColorSpace, TextureError},
+};
+
+pub struct BackgroundSystem;
+
+impl<'a> specs::System<'a> for BackgroundSystem {
+    type SystemData = (
+        specs::ReadStorage<'a, Camera>,
+        specs::ReadStorage<'a, Transform>,
+        specs::ReadStorage<'a, GlobalTransform>,
+        specs::WriteExpect<'a, Sky>,
+        Option<specs::ReadExpect<'a, Environment>>,
+        specs::ReadExpect<'a, AssetPaths>,
+        specs::ReadExpect<'a, MaterialManager>,
+        specs::ReadExpect<'a, wgpu::Device>,
+        specs::ReadExpect<'a, wgpu::Queue>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            cameras,
+            transforms,
+            globals,
+            mut sky,
+            environment,
+            asset_paths,
+            material_manager,
+            device,
+            queue,
+        ): Self::SystemData,
+    ) {
+        use cgmath::SquareMatrix;
+
+        // Like the camera system, this only uses the first camera
+        let Some((camera, transform, global)) = (&cameras, &transforms, &globals).join().next()
+        else {
+            return;
+        };
+
+        if *sky.background() != camera.background {
+            let skybox = load_skybox(
+                &camera.background,
+                &asset_paths,
+                &material_manager,
+                &device,
+                &queue,
+            )
+            .unwrap_or_else(|error| {
+                log::error!(
+                    "Couldn't load background {:?}: {}",
+                    camera.background,
+                    error
+                );
+                None
+            });
+            sky.set_background(camera.background.clone(), skybox);
+        }
+
+        sky.bind_cubemap(environment.as_deref(), &device);
+        if !sky.is_drawn() {
+            return;
+        }
+
+        // Without its translation the view only turns directions, so the sky is infinitely far
+        let mut view = camera.view_matrix(transform, global);
+        view.w = cgmath::Vector4::unit_w();
+        let inverse_view_proj = (camera.projection_matrix() * view)
+            .invert()
+            .unwrap_or_else(cgmath::Matrix4::identity);
+
+        let (top, bottom, mode) = match sky.background() {
+            Background::Gradient {
+                top: [tr, tg, tb],
+                bottom: [br, bg, bb],
+            } => ([*tr, *tg, *tb, 1.0], [*br, *bg, *bb, 1.0], 0),
+            _ => ([0.0; 4], [0.0; 4], 1),
+        };
+
+        let uniform = SkyUniform {
+            inverse_view_proj: inverse_view_proj.into(),
+            top,
+            bottom,
+            mode,
+            _padding: [0; 3],
+        };
+        queue.write_buffer(&sky.buffer, 0, bytemuck::cast_slice(&[uniform]));
+    }
+}
+
+/// Loads the cubemap a background needs, if it needs one of its own.
+fn load_skybox(
+    background: &Background,
+    asset_paths: &AssetPaths,
+    material_manager: &MaterialManager,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+) -> Result<Option<Skybox>, TextureError> {
+    let load = |file: &str, options: SamplerOptions| {
+        let path = asset_paths.resolve(file, None);
+        let options = SamplerOptions {
+            anisotropy: 1,
+            ..options
+        };
+        material_manager.load_texture(&path, &options, ColorSpace::Srgb, device, queue)
+    };
+
+    match background {
+        Background::Skybox(file) => {
+            // Repeats horizontally, so that the seam where the image wraps around is filtered
+            let equirectangular = load(file, SamplerOptions::default())?;
+            Ok(Some(Skybox::from_equirectangular(
+                &equirectangular,
+                device,
+                queue,
+            )))
+        }
+        Background::Cubemap(files) => {
+            let faces = files
+                .iter()
+                .map(|file| load(file, SamplerOptions::clamped()))
+                .collect::<Result<Vec<_>, _>>()?;
+            let faces = std::array::from_fn(|face| &*faces[face]);
+            Ok(Some(Skybox::from_faces(faces, device, queue)))
+        }
+        Background::Color(_) | Background::Gradient { .. } | Background::Environment => Ok(None),
+    }
+}
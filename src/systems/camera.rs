@@ -2,7 +2,10 @@ use specs::Join;
 use wgpu::util::DeviceExt;
 
 use crate::{
-    components::rendering::{Camera, CameraUniform, Renderer, Transform},
+    components::{
+        hierarchy::GlobalTransform,
+        rendering::{Camera, CameraUniform, Renderer, Transform},
+    },
     material_manager::MaterialManager,
 };
 
@@ -10,74 +13,60 @@ pub struct CameraSystem;
 
 impl<'a> specs::System<'a> for CameraSystem {
     type SystemData = (
-        specs::ReadStorage<'a, Camera>,
+        specs::WriteStorage<'a, Camera>,
         specs::WriteStorage<'a, Transform>,
+        specs::ReadStorage<'a, GlobalTransform>,
         specs::ReadStorage<'a, Renderer>,
         specs::ReadExpect<'a, wgpu::Device>,
         specs::ReadExpect<'a, MaterialManager>,
+        specs::ReadExpect<'a, winit::dpi::PhysicalSize<u32>>,
     );
 
     fn run(
         &mut self,
-        (cameras, mut transforms, renderers, device, material_manager): Self::SystemData,
+        (mut cameras, mut transforms, globals, renderers, device, material_manager, size): Self::SystemData,
     ) {
-        for (camera, transform) in (&cameras, &mut transforms).join() {
-            let view_projection =
-                cgmath::Matrix4::look_at_rh(transform.position, camera.target, camera.up);
-            let perspective_projection = cgmath::perspective(
-                cgmath::Deg(camera.fovy),
-                camera.aspect,
-                camera.znear,
-                camera.zfar,
-            );
+        // Taken from the size every frame rather than when it changes, so that cameras created
+        // after a resize, and the first frame, are never drawn stretched
+        if size.height > 0 {
+            for camera in (&mut cameras).join() {
+                camera.aspect = size.width as f32 / size.height as f32;
+            }
+        }
 
-            for (_, transform) in (&renderers, &mut transforms).join() {
-                let model_projection = cgmath::Matrix4::from_translation(cgmath::Vector3 {
-                    x: transform.position.x,
-                    y: transform.position.y,
-                    z: transform.position.z,
-                }) * cgmath::Matrix4::from(transform.rotation)
-                    * cgmath::Matrix4::from_nonuniform_scale(
-                        transform.scale.x,
-                        transform.scale.y,
-                        transform.scale.z,
-                    );
+        // TODO: Support multiple cameras
+        // This currently only uses the first camera
+        let Some((camera, camera_transform, camera_global)) =
+            (&cameras, &transforms, &globals).join().next()
+        else {
+            return;
+        };
 
-                let projection = OPENGL_TO_WGPU_MATRIX
-                    * perspective_projection
-                    * view_projection
-                    * model_projection;
+        let view_projection =
+            camera.projection_matrix() * camera.view_matrix(camera_transform, camera_global);
 
-                let mut camera_uniform = CameraUniform::new();
-                camera_uniform.set_projection(projection);
+        for (_, transform, global) in (&renderers, &mut transforms, &globals).join() {
+            let projection = view_projection * global.matrix;
 
-                let camera_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                    label: Some("Camera Buffer"),
-                    contents: bytemuck::cast_slice(&[camera_uniform]),
-                    usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-                });
+            let mut camera_uniform = CameraUniform::new();
+            camera_uniform.set_projection(projection);
+            camera_uniform.set_model(global.matrix);
+            camera_uniform.set_view_position(camera_global.position());
 
-                transform.bind = Some(device.create_bind_group(&wgpu::BindGroupDescriptor {
-                    layout: material_manager.get_camera_bind_group_layout(),
-                    entries: &[wgpu::BindGroupEntry {
-                        binding: 0,
-                        resource: camera_buffer.as_entire_binding(),
-                    }],
-                    label: Some("transform_bind_group"),
-                }));
-            }
+            let camera_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Camera Buffer"),
+                contents: bytemuck::cast_slice(&[camera_uniform]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            });
 
-            // TODO: Support multiple cameras
-            // This currenly breaks the loop after the first camera
-            break;
+            transform.bind = Some(device.create_bind_group(&wgpu::BindGroupDescriptor {
+                layout: material_manager.get_camera_bind_group_layout(),
+                entries: &[wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: camera_buffer.as_entire_binding(),
+                }],
+                label: Some("transform_bind_group"),
+            }));
         }
     }
 }
-
-#[rustfmt::skip]
-const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
-    1.0, 0.0, 0.0, 0.0,
-    0.0, 1.0, 0.0, 0.0,
-    0.0, 0.0, 0.5, 0.0,
-    0.0, 0.0, 0.5, 1.0,
-);
@@ -0,0 +1,213 @@
+use std::ops::Range;
+
+use specs::Join;
+
+use crate::{
+    components::{
+        dynamic_mesh::DynamicMesh,
+        rendering::{Material, Mesh, Renderer},
+    },
+    culling::Aabb,
+    material_manager::MaterialManager,
+};
+
+/// Uploads the changes to every `DynamicMesh` into its entity's `Renderer`.
+pub struct DynamicMeshSystem;
+
+impl<'a> specs::System<'a> for DynamicMeshSystem {
+    type SystemData = (
+        specs::WriteStorage<'a, DynamicMesh>,
+        specs::WriteStorage<'a, Renderer>,
+        specs::ReadExpect<'a, MaterialManager>,
+        specs::ReadExpect<'a, wgpu::Device>,
+        specs::ReadExpect<'a, wgpu::Queue>,
+    );
+
+    fn run(
+        &mut self,
+        (mut dynamic_meshes, mut renderers, material_manager, device, queue): Self::SystemData,
+    ) {
+        for (dynamic_mesh, renderer) in (&mut dynamic_meshes, &mut renderers).join() {
+            let mut changes = std::mem::take(&mut dynamic_mesh.changes);
+
+            // A renderer that hasn't had this mesh uploaded yet, or had a model loaded into it
+            // before the mesh was added, starts over with everything uploaded
+            let is_uploaded = renderer.meshes.len() == 1
+                && renderer.materials.len() == 1
+                && renderer.source.is_none()
+                && renderer.primitive.is_none();
+            if !is_uploaded {
+                renderer.meshes = vec![Mesh {
+                    name: "dynamic".to_string(),
+                    vertex_buffer: None,
+                    index_buffer: None,
+                    num_elements: 0,
+                    index_format: wgpu::IndexFormat::Uint32,
+                    points: false,
+                    color_buffer: None,
+                    tex_coords1_buffer: None,
+                    tangent_buffer: None,
+                    material: 0,
+                    bounds: Aabb::default(),
+                }];
+                renderer.lods.clear();
+                renderer.level = 0;
+                renderer.source = None;
+                renderer.primitive = None;
+                renderer.lod_source.clear();
+                changes.vertices = Some(0..dynamic_mesh.vertices().len());
+                changes.indices = Some(0..dynamic_mesh.indices().len());
+                changes.material = true;
+            }
+
+            if changes.material {
+                let material = dynamic_mesh.material();
+                renderer.materials = vec![Material {
+                    name: material.name.clone(),
+                    bind: Some(material_manager.create_material_bind_group(material, &device)),
+                    alpha_mode: material.alpha_mode,
+                }];
+            }
+
+            let mesh = &mut renderer.meshes[0];
+            let (vertices, indices) = (dynamic_mesh.vertices(), dynamic_mesh.indices());
+            if let Some(range) = &changes.vertices {
+                upload(
+                    &mut mesh.vertex_buffer,
+                    wgpu::BufferUsages::VERTEX,
+                    "Vertex",
+                    vertices,
+                    range.clone(),
+                    &device,
+                    &queue,
+                );
+                mesh.bounds = Aabb::from_points(vertices.iter().map(|vertex| vertex.position));
+            }
+            if let Some(range) = &changes.indices {
+                upload(
+                    &mut mesh.index_buffer,
+                    wgpu::BufferUsages::INDEX,
+                    "Index",
+                    indices,
+                    range.clone(),
+                    &device,
+                    &queue,
+                );
+            }
+
+            if changes.vertices.is_some() || changes.indices.is_some() {
+                // An index past the vertices would read outside the vertex buffer
+                mesh.num_elements = if indices.len() % 3 != 0 {
+                    log::error!(
+                        "A dynamic mesh has {} indices, which isn't whole triangles",
+                        indices.len()
+                    );
+                    0
+                } else if indices
+                    .iter()
+                    .any(|&index| index as usize >= vertices.len())
+                {
+                    log::error!(
+                        "A dynamic mesh has an index past its {} vertices, so it isn't drawn",
+                        vertices.len()
+                    );
+                    0
+                } else {
+                    indices.len() as u32
+                };
+            }
+        }
+    }
+}
+
+/// Writes the `range` of `values` that changed into `buffer`, or all of them into a new buffer
+/// when they don't fit in it anymore. Vertices and indices are both a multiple of four bytes, as
+/// writes have to be.
+fn upload<T: bytemuck::Pod>(
+    buffer: &mut Option<wgpu::Buffer>,
+    usage: wgpu::BufferUsages,
+    name: &str,
+    values: &[T],
+    range: Range<usize>,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+) {
+    let size = std::mem::size_of_val(values) as wgpu::BufferAddress;
+    let capacity = buffer.as_ref().map(wgpu::Buffer::size);
+    match plan_upload(capacity, size, values.len(), range) {
+        Upload::Write(range) => {
+            if let (Some(buffer), false) = (buffer, range.is_empty()) {
+                let offset = (range.start * std::mem::size_of::<T>()) as wgpu::BufferAddress;
+                queue.write_buffer(buffer, offset, bytemuck::cast_slice(&values[range]));
+            }
+        }
+        Upload::Grow(capacity) => {
+            let grown = device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(&format!("Dynamic Mesh {} Buffer", name)),
+                size: capacity,
+                usage: usage | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            });
+            if !values.is_empty() {
+                queue.write_buffer(&grown, 0, bytemuck::cast_slice(values));
+            }
+            *buffer = Some(grown);
+        }
+    }
+}
+
+/// What `upload` does with a buffer.
+#[derive(Debug, PartialEq, Eq)]
+enum Upload {
+    /// Writes the values in the range into the buffer.
+    Write(Range<usize>),
+    /// Writes every value into a new buffer with this many bytes.
+    Grow(wgpu::BufferAddress),
+}
+
+/// How `upload` writes the `range` of `count` values, which take `size` bytes, into a buffer of
+/// `capacity` bytes, or no buffer. The range is clamped to the values there are.
+fn plan_upload(
+    capacity: Option<wgpu::BufferAddress>,
+    size: wgpu::BufferAddress,
+    count: usize,
+    range: Range<usize>,
+) -> Upload {
+    match capacity {
+        Some(capacity) if capacity >= size => {
+            Upload::Write(range.start.min(count)..range.end.min(count))
+        }
+        // Twice as large as needed, so that a mesh that grows a little every frame isn't given
+        // a new buffer every frame
+        _ => Upload::Grow((size * 2).max(wgpu::COPY_BUFFER_ALIGNMENT)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn changes_are_written_into_buffers_they_fit_in() {
+        assert_eq!(plan_upload(Some(64), 64, 16, 2..5), Upload::Write(2..5));
+        // A mesh that shrank keeps its buffer
+        assert_eq!(plan_upload(Some(64), 16, 4, 0..4), Upload::Write(0..4));
+    }
+
+    #[test]
+    fn ranges_past_the_values_are_clamped() {
+        assert_eq!(plan_upload(Some(64), 16, 4, 2..10), Upload::Write(2..4));
+        assert_eq!(plan_upload(Some(64), 16, 4, 6..10), Upload::Write(4..4));
+    }
+
+    #[test]
+    fn buffers_grow_to_twice_what_they_need() {
+        assert_eq!(plan_upload(None, 48, 12, 0..12), Upload::Grow(96));
+        assert_eq!(plan_upload(Some(64), 68, 17, 16..17), Upload::Grow(136));
+        // Buffers can't be empty
+        assert_eq!(
+            plan_upload(None, 0, 0, 0..0),
+            Upload::Grow(wgpu::COPY_BUFFER_ALIGNMENT)
+        );
+    }
+}
@@ -0,0 +1,68 @@
+use specs::Join;
+
+use crate::{
+    components::{
+        hierarchy::GlobalTransform,
+        lighting::{Light, LightKind, LightUniform, Lighting, LightsUniform, MAX_LIGHTS},
+    },
+    environment::Environment,
+    material_manager::MaterialManager,
+};
+
+pub struct LightingSystem;
+
+impl<'a> specs::System<'a> for LightingSystem {
+    type SystemData = (
+        specs::ReadStorage<'a, Light>,
+        specs::ReadStorage<'a, GlobalTransform>,
+        specs::WriteExpect<'a, Lighting>,
+        Option<specs::ReadExpect<'a, Environment>>,
+        specs::ReadExpect<'a, MaterialManager>,
+        specs::ReadExpect<'a, wgpu::Device>,
+        specs::ReadExpect<'a, wgpu::Queue>,
+    );
+
+    fn run(
+        &mut self,
+        (lights, globals, mut lighting, environment, material_manager, device, queue): Self::SystemData,
+    ) {
+        use cgmath::InnerSpace;
+
+        let environment = environment.as_deref();
+        lighting.bind_environment(
+            environment,
+            &device,
+            material_manager.get_light_bind_group_layout(),
+        );
+
+        let mut uniform = LightsUniform {
+            ambient: lighting.ambient,
+            count: 0,
+            environment_intensity: environment.map_or(0.0, |e| e.intensity),
+            max_reflection_lod: environment.map_or(0.0, Environment::max_reflection_lod),
+            _padding: [0.0; 2],
+            lights: [LightUniform::default(); MAX_LIGHTS],
+        };
+
+        for (light, global) in (&lights, &globals).join() {
+            let Some(slot) = uniform.lights.get_mut(uniform.count as usize) else {
+                log::warn!("Only the first {} lights are shaded", MAX_LIGHTS);
+                break;
+            };
+
+            slot.position = match light.kind {
+                LightKind::Directional => {
+                    let towards_light = global.matrix.z.truncate().normalize();
+                    towards_light.extend(0.0).into()
+                }
+                LightKind::Point => global.matrix.w.into(),
+            };
+            let [r, g, b] = light.color.map(|c| c * light.intensity);
+            slot.color = [r, g, b, 1.0];
+
+            uniform.count += 1;
+        }
+
+        queue.write_buffer(&lighting.buffer, 0, bytemuck::cast_slice(&[uniform]));
+    }
+}
@@ -0,0 +1,52 @@
+use specs::Join;
+
+use crate::components::{
+    hierarchy::GlobalTransform,
+    lod::Lod,
+    rendering::{Camera, Renderer},
+};
+
+pub struct LodSystem;
+
+impl<'a> specs::System<'a> for LodSystem {
+    type SystemData = (
+        specs::ReadStorage<'a, Camera>,
+        specs::ReadStorage<'a, Lod>,
+        specs::ReadStorage<'a, GlobalTransform>,
+        specs::WriteStorage<'a, Renderer>,
+    );
+
+    fn run(&mut self, (cameras, lods, globals, mut renderers): Self::SystemData) {
+        use cgmath::{InnerSpace, MetricSpace};
+
+        // Like the camera system, this only uses the first camera
+        let Some((camera, camera_global)) = (&cameras, &globals).join().next() else {
+            return;
+        };
+        let camera_position = camera_global.position();
+        let half_height = (cgmath::Deg(camera.fovy) / 2.0).0.to_radians().tan();
+
+        for (lod, global, renderer) in (&lods, &globals, &mut renderers).join() {
+            let Some(bounds) = renderer
+                .meshes
+                .iter()
+                .map(|mesh| mesh.bounds)
+                .reduce(|a, b| a.union(&b))
+            else {
+                continue;
+            };
+
+            // The size of the bounding sphere on screen, as a fraction of the screen's height
+            let bounds = bounds.transformed(&global.matrix);
+            let radius = cgmath::Vector3::from(bounds.extents()).magnitude();
+            let distance = camera_position.distance(bounds.center().into());
+            let screen_size = if distance > radius {
+                radius / (distance * half_height)
+            } else {
+                f32::INFINITY
+            };
+
+            renderer.level = lod.select(screen_size, renderer.level);
+        }
+    }
+}
@@ -1,4 +1,11 @@
+pub mod background;
 pub mod camera;
+pub mod dynamic_mesh;
+pub mod lighting;
+pub mod lod;
 pub mod model_builder;
+pub mod postprocessing;
 pub mod rendering;
 pub mod resizing;
+pub mod rotate;
+pub mod transform;
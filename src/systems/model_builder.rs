@@ -1,6 +1,17 @@
 use crate::{
-    components::rendering::{Material, Mesh, Model, Renderer, Vertex},
+    assets::AssetPaths,
+    components::{
+        dynamic_mesh::DynamicMesh,
+        lod::{Lod, LodModel},
+        primitive::Primitive,
+        rendering::{Material, Mesh, Model, Renderer},
+    },
+    import::{
+        self, optimize::optimize, simplify::simplify, ImportSettings, ImportedModel, MeshData,
+    },
+    material::PbrMaterial,
     material_manager::MaterialManager,
+    primitives,
 };
 use specs::Join;
 use wgpu::util::DeviceExt;
@@ -10,127 +21,222 @@ pub struct ModelBuilderSystem;
 impl<'a> specs::System<'a> for ModelBuilderSystem {
     type SystemData = (
         specs::ReadStorage<'a, Model>,
+        specs::ReadStorage<'a, Primitive>,
+        specs::ReadStorage<'a, DynamicMesh>,
+        specs::ReadStorage<'a, Lod>,
         specs::WriteStorage<'a, Renderer>,
         specs::ReadExpect<'a, MaterialManager>,
         specs::ReadExpect<'a, wgpu::Device>,
         specs::ReadExpect<'a, wgpu::Queue>,
+        specs::ReadExpect<'a, AssetPaths>,
+        specs::ReadExpect<'a, ImportSettings>,
     );
 
-    fn run(&mut self, (models, mut renderers, material_manager, device, queue): Self::SystemData) {
-        for (model, renderer) in (&models, &mut renderers).join() {
-            // TODO: Find a way to check if the model has changed
-            let needs_update = renderer.meshes.is_empty() || renderer.materials.is_empty();
-
-            if !needs_update {
+    fn run(
+        &mut self,
+        (
+            models,
+            primitives,
+            dynamic_meshes,
+            lods,
+            mut renderers,
+            material_manager,
+            device,
+            queue,
+            asset_paths,
+            settings,
+        ): Self::SystemData,
+    ) {
+        for (model, primitive, lod, renderer, ()) in (
+            models.maybe(),
+            primitives.maybe(),
+            lods.maybe(),
+            &mut renderers,
+            !&dynamic_meshes,
+        )
+            .join()
+        {
+            // A model file takes the place of a primitive on the same entity
+            let source = model.map(|model| model.file.clone());
+            let primitive = primitive.filter(|_| source.is_none()).cloned();
+            if source.is_none() && primitive.is_none() {
                 continue;
             }
-
-            let object_text = std::fs::read_to_string(&model.file).unwrap();
-            let object_cursor = std::io::Cursor::new(object_text);
-            let mut object_reader = std::io::BufReader::new(object_cursor);
-
-            let (imported_meshes, imported_materials) = tobj::load_obj_buf(
-                &mut object_reader,
-                &tobj::LoadOptions {
-                    triangulate: true,
-                    single_index: true,
-                    ..Default::default()
-                },
-                |p| {
-                    let material_text = std::fs::read_to_string(p).unwrap();
-                    let material_cursor = std::io::Cursor::new(material_text);
-                    let mut material_reader = std::io::BufReader::new(material_cursor);
-
-                    tobj::load_mtl_buf(&mut material_reader)
-                },
-            )
-            .unwrap(); // FIXME: Handle errors
-
-            let mut materials = Vec::new();
-
-            for imported_material in imported_materials.unwrap().iter() {
-                let diffuse_texture = material_manager.add_texture_from_path(
-                    &imported_material.diffuse_texture.to_string(),
+            let lod_models = lod.map_or_else(Vec::new, |lod| {
+                lod.levels.iter().map(|level| level.model.clone()).collect()
+            });
+            if renderer.source == source
+                && renderer.primitive == primitive
+                && renderer.lod_source == lod_models
+            {
+                continue;
+            }
+            // Set even if loading fails, so that a broken file isn't reloaded every frame
+            renderer.source = source.clone();
+            renderer.primitive = primitive.clone();
+            renderer.lod_source = lod_models.clone();
+            renderer.level = 0;
+            // Names the buffers and the log messages
+            let name = source
+                .clone()
+                .unwrap_or_else(|| format!("{:?}", primitive.as_ref().unwrap()));
+
+            let load = |file: &str| {
+                let path = asset_paths.resolve(file, None);
+                import::load(
+                    &path,
+                    &asset_paths,
+                    &settings,
+                    &material_manager,
                     &device,
                     &queue,
-                );
-
-                let bind = Some(device.create_bind_group(&wgpu::BindGroupDescriptor {
-                    layout: material_manager.get_texture_bind_group_layout(),
-                    entries: &[
-                        wgpu::BindGroupEntry {
-                            binding: 0,
-                            resource: wgpu::BindingResource::TextureView(&diffuse_texture.view),
-                        },
-                        wgpu::BindGroupEntry {
-                            binding: 1,
-                            resource: wgpu::BindingResource::Sampler(&diffuse_texture.sampler),
-                        },
-                    ],
-                    label: None,
-                }));
-
-                let material = Material {
-                    name: imported_material.name.to_string(),
-                    diffuse_bind: bind,
-                };
+                )
+            };
+            let create_material = |material: &PbrMaterial| Material {
+                name: material.name.clone(),
+                bind: Some(material_manager.create_material_bind_group(material, &device)),
+                alpha_mode: material.alpha_mode,
+            };
+
+            let imported = match &primitive {
+                Some(primitive) => Ok(ImportedModel {
+                    meshes: vec![primitives::generate(primitive)],
+                    materials: vec![PbrMaterial::default()],
+                }),
+                None => load(&name),
+            };
+            let imported = match imported {
+                Ok(imported) => imported,
+                Err(error) => {
+                    log::error!("Couldn't load model {:?}: {}", name, error);
+                    renderer.meshes.clear();
+                    renderer.lods.clear();
+                    renderer.materials.clear();
+                    continue;
+                }
+            };
+
+            let mut materials = imported
+                .materials
+                .iter()
+                .map(create_material)
+                .collect::<Vec<_>>();
 
-                materials.push(material);
-            }
+            let meshes = imported
+                .meshes
+                .iter()
+                .map(|mesh| create_mesh(mesh, &name, &device))
+                .collect::<Vec<_>>();
 
-            let meshes = imported_meshes
-                .into_iter()
-                .map(|m| {
-                    let vertices = (0..m.mesh.positions.len() / 3)
-                        .map(|i| Vertex {
-                            position: [
-                                m.mesh.positions[i * 3],
-                                m.mesh.positions[i * 3 + 1],
-                                m.mesh.positions[i * 3 + 2],
-                            ],
-                            tex_coords: [m.mesh.texcoords[i * 2], m.mesh.texcoords[i * 2 + 1]],
-                            normal: [
-                                m.mesh.normals[i * 3],
-                                m.mesh.normals[i * 3 + 1],
-                                m.mesh.normals[i * 3 + 2],
-                            ],
+            // Every level's materials are appended to the model's, so that all of them share the
+            // renderer's list
+            let lods = lod_models
+                .iter()
+                .map(|lod_model| match lod_model {
+                    LodModel::File(file) => match load(file) {
+                        Ok(level) => {
+                            let offset = materials.len();
+                            materials.extend(level.materials.iter().map(create_material));
+                            level
+                                .meshes
+                                .into_iter()
+                                .map(|mut mesh| {
+                                    mesh.material += offset;
+                                    create_mesh(&mesh, file, &device)
+                                })
+                                .collect()
+                        }
+                        Err(error) => {
+                            log::error!("Couldn't load LOD model {:?}: {}", file, error);
+                            Vec::new()
+                        }
+                    },
+                    LodModel::Simplified(ratio) => imported
+                        .meshes
+                        .iter()
+                        .map(|mesh| {
+                            // Point clouds have no edges to collapse
+                            if mesh.points {
+                                return create_mesh(mesh, &name, &device);
+                            }
+                            let mut simplified = simplify(mesh, *ratio);
+                            if settings.optimize_meshes {
+                                optimize(&mut simplified);
+                            }
+                            create_mesh(&simplified, &name, &device)
                         })
-                        .collect::<Vec<_>>();
-
-                    let vertex_buffer = Some(device.create_buffer_init(
-                        &wgpu::util::BufferInitDescriptor {
-                            label: Some(&format!("{:?} Vertex Buffer", model.file)),
-                            contents: bytemuck::cast_slice(&vertices),
-                            usage: wgpu::BufferUsages::VERTEX,
-                        },
-                    ));
-                    let index_buffer = Some(device.create_buffer_init(
-                        &wgpu::util::BufferInitDescriptor {
-                            label: Some(&format!("{:?} Index Buffer", model.file)),
-                            contents: bytemuck::cast_slice(&m.mesh.indices),
-                            usage: wgpu::BufferUsages::INDEX,
-                        },
-                    ));
-
-                    Mesh {
-                        name: model.file.to_string(),
-                        vertex_buffer,
-                        index_buffer,
-                        num_elements: m.mesh.indices.len() as u32,
-                        material: m.mesh.material_id.unwrap_or(0),
-                    }
+                        .collect(),
                 })
                 .collect::<Vec<_>>();
 
             log::info!(
-                "Loaded model: {:?} with {} meshes and {} materials",
-                model.file,
+                "Loaded model: {:?} with {} meshes, {} materials and {} levels of detail",
+                name,
                 meshes.len(),
-                materials.len()
+                materials.len(),
+                lods.len()
             );
 
             renderer.materials = materials;
             renderer.meshes = meshes;
+            renderer.lods = lods;
         }
     }
 }
+
+fn create_mesh(mesh: &MeshData, file: &str, device: &wgpu::Device) -> Mesh {
+    let vertex_buffer = Some(
+        device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&format!("{:?} Vertex Buffer", file)),
+            contents: bytemuck::cast_slice(&mesh.vertices),
+            usage: wgpu::BufferUsages::VERTEX,
+        }),
+    );
+    let attribute_buffer = |name: &str, contents: &[u8]| {
+        device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&format!("{:?} {} Buffer", file, name)),
+            contents,
+            usage: wgpu::BufferUsages::VERTEX,
+        })
+    };
+    let color_buffer = (mesh.colors.as_ref())
+        .map(|colors| attribute_buffer("Color", bytemuck::cast_slice(colors)));
+    let tex_coords1_buffer = (mesh.tex_coords1.as_ref())
+        .map(|tex_coords| attribute_buffer("Second UV", bytemuck::cast_slice(tex_coords)));
+    let tangent_buffer = (mesh.tangents.as_ref())
+        .map(|tangents| attribute_buffer("Tangent", bytemuck::cast_slice(tangents)));
+    // The largest 16-bit index is left out, as it restarts strips on some backends
+    let (index_format, indices) = if mesh.vertices.len() <= u16::MAX as usize {
+        let indices = mesh.indices.iter().map(|&i| i as u16).collect::<Vec<_>>();
+        (
+            wgpu::IndexFormat::Uint16,
+            bytemuck::cast_slice(&indices).to_vec(),
+        )
+    } else {
+        (
+            wgpu::IndexFormat::Uint32,
+            bytemuck::cast_slice(&mesh.indices).to_vec(),
+        )
+    };
+    let index_buffer = Some(
+        device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&format!("{:?} Index Buffer", file)),
+            contents: &indices,
+            usage: wgpu::BufferUsages::INDEX,
+        }),
+    );
+
+    Mesh {
+        name: mesh.name.clone(),
+        vertex_buffer,
+        index_buffer,
+        num_elements: mesh.indices.len() as u32,
+        index_format,
+        points: mesh.points,
+        color_buffer,
+        tex_coords1_buffer,
+        tangent_buffer,
+        material: mesh.material,
+        bounds: mesh.bounds,
+    }
+}
@@ -0,0 +1,47 @@
+use specs::Join;
+
+use crate::{
+    assets::AssetPaths,
+    components::{postprocessing::PostProcessing, rendering::Camera},
+    material_manager::{MaterialManager, SamplerOptions},
+    texture_data::ColorSpace,
+};
+
+pub struct PostProcessingSystem;
+
+impl<'a> specs::System<'a> for PostProcessingSystem {
+    type SystemData = (
+        specs::ReadStorage<'a, Camera>,
+        specs::WriteExpect<'a, PostProcessing>,
+        specs::ReadExpect<'a, AssetPaths>,
+        specs::ReadExpect<'a, MaterialManager>,
+        specs::ReadExpect<'a, wgpu::Device>,
+        specs::ReadExpect<'a, wgpu::Queue>,
+    );
+
+    fn run(
+        &mut self,
+        (cameras, mut post_processing, asset_paths, material_manager, device, queue): Self::SystemData,
+    ) {
+        // Like the camera system, this only uses the first camera
+        let effects = cameras
+            .join()
+            .next()
+            .map_or(&[][..], |camera| &camera.post_effects);
+
+        if post_processing.effects() != effects {
+            // The effects sample lookup tables with a sampler of their own
+            let options = SamplerOptions::clamped();
+            let load_lut = |file: &str| {
+                let path = asset_paths.resolve(file, None);
+                material_manager
+                    .load_texture(&path, &options, ColorSpace::Linear, &device, &queue)
+                    .map_err(|error| log::error!("Couldn't load LUT {:?}: {}", file, error))
+                    .ok()
+            };
+            post_processing.set_effects(effects, load_lut, &device);
+        }
+
+        post_processing.update(&queue);
+    }
+}
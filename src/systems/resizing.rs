@@ -1,19 +1,20 @@
-use crate::components::rendering::Camera;
-use specs::{Join, ReadExpect, WriteExpect, WriteStorage};
+use crate::{components::tonemapping::ToneMapping, render_target::RenderTargets};
+use specs::{ReadExpect, WriteExpect};
 
 pub struct ResizingSystem;
 
 impl<'a> specs::System<'a> for ResizingSystem {
     type SystemData = (
-        WriteStorage<'a, Camera>,
-        ReadExpect<'a, wgpu::Surface>,
+        Option<ReadExpect<'a, wgpu::Surface>>,
         ReadExpect<'a, wgpu::Device>,
         WriteExpect<'a, wgpu::SurfaceConfiguration>,
         ReadExpect<'a, winit::dpi::PhysicalSize<u32>>,
+        WriteExpect<'a, RenderTargets>,
+        WriteExpect<'a, ToneMapping>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
-        let (mut camera, surface, device, mut config, size) = data;
+        let (surface, device, mut config, size, mut targets, mut tone_mapping) = data;
 
         if size.width == config.width && size.height == config.height {
             return;
@@ -22,10 +23,10 @@ impl<'a> specs::System<'a> for ResizingSystem {
         config.width = size.width;
         config.height = size.height;
 
-        for camera in (&mut camera).join() {
-            camera.aspect = size.width as f32 / size.height as f32;
+        if let Some(surface) = surface {
+            surface.configure(&device, &config);
         }
-
-        surface.configure(&device, &config);
+        targets.resize(&device, size.width, size.height);
+        tone_mapping.bind_targets(&targets, &device);
     }
 }
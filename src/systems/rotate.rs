@@ -0,0 +1,23 @@
+use cgmath::Rotation3;
+use specs::Join;
+
+use crate::components::rendering::{Renderer, Transform};
+
+/// Spins every `Renderer` a little each frame.
+pub struct RotateSystem;
+
+impl<'a> specs::System<'a> for RotateSystem {
+    type SystemData = (
+        specs::WriteStorage<'a, Transform>,
+        specs::ReadStorage<'a, Renderer>,
+    );
+
+    fn run(&mut self, (mut transforms, renderer): Self::SystemData) {
+        for (transform, _) in (&mut transforms, &renderer).join() {
+            transform.rotation = transform.rotation
+                * cgmath::Quaternion::from_axis_angle(cgmath::Vector3::unit_z(), cgmath::Deg(0.5))
+                * cgmath::Quaternion::from_axis_angle(cgmath::Vector3::unit_y(), cgmath::Deg(-0.5))
+                * cgmath::Quaternion::from_axis_angle(cgmath::Vector3::unit_x(), cgmath::Deg(0.5));
+        }
+    }
+}
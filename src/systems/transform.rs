@@ -0,0 +1,225 @@
+use std::collections::{HashMap, HashSet};
+
+use specs::{Entities, Join, ReadStorage, WriteStorage};
+
+use crate::components::{
+    hierarchy::{GlobalTransform, Parent},
+    rendering::Transform,
+};
+
+/// Propagates `Transform`s down the `Parent` hierarchy into `GlobalTransform`s.
+///
+/// Runs every frame, so reparenting an entity only requires changing its `Parent` component.
+/// Entities whose parent (or any further ancestor) has been deleted are deleted as well.
+pub struct TransformSystem;
+
+impl<'a> specs::System<'a> for TransformSystem {
+    type SystemData = (
+        Entities<'a>,
+        ReadStorage<'a, Transform>,
+        ReadStorage<'a, Parent>,
+        WriteStorage<'a, GlobalTransform>,
+    );
+
+    fn run(&mut self, (entities, transforms, parents, mut globals): Self::SystemData) {
+        let mut resolved: HashMap<specs::Entity, Option<cgmath::Matrix4<f32>>> = HashMap::new();
+
+        for (entity, _) in (&entities, &transforms).join() {
+            let matrix = resolve(entity, &entities, &transforms, &parents, &mut resolved);
+
+            match matrix {
+                Some(matrix) => {
+                    globals
+                        .insert(entity, GlobalTransform { matrix })
+                        .expect("Entity is alive");
+                }
+                None => {
+                    entities.delete(entity).expect("Entity is alive");
+                }
+            }
+        }
+    }
+}
+
+/// Computes the global matrix of an entity, or `None` if one of its ancestors no longer exists.
+///
+/// The ancestor chain is walked iteratively so that deep hierarchies cannot overflow the stack.
+fn resolve(
+    entity: specs::Entity,
+    entities: &Entities,
+    transforms: &ReadStorage<Transform>,
+    parents: &ReadStorage<Parent>,
+    resolved: &mut HashMap<specs::Entity, Option<cgmath::Matrix4<f32>>>,
+) -> Option<cgmath::Matrix4<f32>> {
+    use cgmath::SquareMatrix;
+
+    // Walk up until we reach a root, an already resolved ancestor, or a deleted ancestor.
+    let mut chain = Vec::new();
+    let mut visited = HashSet::new();
+    let mut current = entity;
+    let mut base = Some(cgmath::Matrix4::identity());
+
+    loop {
+        if let Some(matrix) = resolved.get(&current) {
+            base = *matrix;
+            break;
+        }
+
+        if !entities.is_alive(current) {
+            base = None;
+            break;
+        }
+
+        if !visited.insert(current) {
+            log::warn!(
+                "Cycle in transform hierarchy at {:?}, treating it as a root",
+                current
+            );
+            break;
+        }
+
+        chain.push(current);
+
+        match parents.get(current) {
+            Some(parent) => current = parent.entity,
+            None => break,
+        }
+    }
+
+    // Resolve the chain from the topmost ancestor back down to the entity.
+    for ancestor in chain.into_iter().rev() {
+        let local = transforms
+            .get(ancestor)
+            .map(Transform::local_matrix)
+            .unwrap_or_else(cgmath::Matrix4::identity);
+
+        base = base.map(|parent| parent * local);
+        resolved.insert(ancestor, base);
+    }
+
+    base
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::Point3;
+    use specs::{Builder, RunNow, World, WorldExt};
+
+    use super::*;
+
+    fn world() -> World {
+        let mut world = World::new();
+        world.register::<Transform>();
+        world.register::<Parent>();
+        world.register::<GlobalTransform>();
+        world
+    }
+
+    fn at(world: &mut World, x: f32, parent: Option<specs::Entity>) -> specs::Entity {
+        let builder = world.create_entity().with(Transform {
+            position: Point3::new(x, 0.0, 0.0),
+            ..Default::default()
+        });
+        match parent {
+            Some(entity) => builder.with(Parent { entity }),
+            None => builder,
+        }
+        .build()
+    }
+
+    fn run(world: &mut World) {
+        TransformSystem.run_now(world);
+        world.maintain();
+    }
+
+    fn x(world: &World, entity: specs::Entity) -> f32 {
+        world
+            .read_storage::<GlobalTransform>()
+            .get(entity)
+            .unwrap()
+            .position()
+            .x
+    }
+
+    #[test]
+    fn children_follow_their_parents() {
+        let mut world = world();
+        let root = at(&mut world, 1.0, None);
+        let child = at(&mut world, 2.0, Some(root));
+        let grandchild = at(&mut world, 4.0, Some(child));
+        run(&mut world);
+        assert_eq!(x(&world, root), 1.0);
+        assert_eq!(x(&world, child), 3.0);
+        assert_eq!(x(&world, grandchild), 7.0);
+    }
+
+    #[test]
+    fn reparenting_moves_the_subtree() {
+        let mut world = world();
+        let first = at(&mut world, 1.0, None);
+        let second = at(&mut world, 10.0, None);
+        let child = at(&mut world, 1.0, Some(first));
+        let grandchild = at(&mut world, 1.0, Some(child));
+        run(&mut world);
+        assert_eq!(x(&world, grandchild), 3.0);
+
+        world
+            .write_storage::<Parent>()
+            .insert(child, Parent { entity: second })
+            .unwrap();
+        run(&mut world);
+        assert_eq!(x(&world, child), 11.0);
+        assert_eq!(x(&world, grandchild), 12.0);
+
+        world.write_storage::<Parent>().remove(child);
+        run(&mut world);
+        assert_eq!(x(&world, child), 1.0);
+        assert_eq!(x(&world, grandchild), 2.0);
+    }
+
+    #[test]
+    fn deleting_a_parent_deletes_its_descendants() {
+        let mut world = world();
+        let root = at(&mut world, 0.0, None);
+        let child = at(&mut world, 0.0, Some(root));
+        let grandchild = at(&mut world, 0.0, Some(child));
+        let other = at(&mut world, 0.0, None);
+        run(&mut world);
+
+        world.delete_entity(root).unwrap();
+        run(&mut world);
+        assert!(!world.is_alive(child));
+        assert!(!world.is_alive(grandchild));
+        assert!(world.is_alive(other));
+    }
+
+    #[test]
+    fn deep_hierarchies_do_not_overflow_the_stack() {
+        let mut world = world();
+        let mut parent = None;
+        for _ in 0..100_000 {
+            parent = Some(at(&mut world, 1.0, parent));
+        }
+        run(&mut world);
+        assert_eq!(x(&world, parent.unwrap()), 100_000.0);
+    }
+
+    #[test]
+    fn cycles_are_broken_instead_of_looping() {
+        let mut world = world();
+        let first = at(&mut world, 1.0, None);
+        let second = at(&mut world, 2.0, Some(first));
+        world
+            .write_storage::<Parent>()
+            .insert(first, Parent { entity: second })
+            .unwrap();
+        let child = at(&mut world, 4.0, Some(second));
+        run(&mut world);
+
+        // Whichever end the cycle is broken at, both are kept and placed once each
+        assert!(world.is_alive(first) && world.is_alive(second));
+        let (first, second, child) = (x(&world, first), x(&world, second), x(&world, child));
+        assert!(first == 1.0 && second == 3.0 || first == 3.0 && second == 2.0);
+        assert_eq!(child, second + 4.0);
+    }
+}
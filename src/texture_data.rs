@@ -0,0 +1,481 @@
+use std::path::Path;
+
+/// Whether the texels of a texture are sRGB-encoded colours or linear data such as normals.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ColorSpace {
+    Srgb,
+    Linear,
+}
+
+/// Decoded texels ready to be uploaded by the `MaterialManager`.
+pub struct TextureData {
+    pub format: wgpu::TextureFormat,
+    pub size: wgpu::Extent3d,
+    /// Mip levels, largest first.
+    pub levels: Vec<Vec<u8>>,
+    /// Whether the remaining mip levels should be generated on the GPU after uploading.
+    pub generate_mipmaps: bool,
+}
+
+#[derive(Debug)]
+pub enum TextureError {
+    Io(std::io::Error),
+    Image(image::ImageError),
+    Ktx2(ktx2::ParseError),
+    Dds(ddsfile::Error),
+    UnsupportedFormat(String),
+    MissingFeatures {
+        format: wgpu::TextureFormat,
+        missing: wgpu::Features,
+    },
+}
+
+impl std::fmt::Display for TextureError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TextureError::Io(error) => write!(f, "Couldn't read texture file: {error}"),
+            TextureError::Image(error) => write!(f, "Couldn't decode image: {error}"),
+            TextureError::Ktx2(error) => write!(f, "Couldn't parse KTX2 file: {error:?}"),
+            TextureError::Dds(error) => write!(f, "Couldn't parse DDS file: {error}"),
+            TextureError::UnsupportedFormat(format) => {
+                write!(f, "Texture format {format} is not supported")
+            }
+            TextureError::MissingFeatures { format, missing } => write!(
+                f,
+                "Texture format {format:?} needs features {missing:?}, which the device lacks"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for TextureError {}
+
+impl TextureData {
+    /// Loads a texture file. KTX2 and DDS files are uploaded as stored, including compressed
+    /// formats and their mip levels. Everything else is decoded by the `image` crate, with
+    /// Radiance HDR and OpenEXR images kept as floating point.
+    ///
+    /// When `cpu_mipmaps` is set, mip levels of decoded images are generated here instead of on
+    /// the GPU.
+    pub fn load(
+        path: impl AsRef<Path>,
+        color_space: ColorSpace,
+        cpu_mipmaps: bool,
+    ) -> Result<Self, TextureError> {
+        let path = path.as_ref();
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(str::to_ascii_lowercase);
+
+        match extension.as_deref() {
+            Some("ktx2") => {
+                let bytes = std::fs::read(path).map_err(TextureError::Io)?;
+                Self::from_ktx2(&bytes, color_space)
+            }
+            Some("dds") => {
+                let file = std::fs::File::open(path).map_err(TextureError::Io)?;
+                Self::from_dds(std::io::BufReader::new(file), color_space)
+            }
+            Some("hdr") => {
+                // `image::open` tone maps Radiance files down to 8 bits
+                let file = std::fs::File::open(path).map_err(TextureError::Io)?;
+                let decoder = image::codecs::hdr::HdrDecoder::new(std::io::BufReader::new(file))
+                    .map_err(TextureError::Image)?;
+                let metadata = decoder.metadata();
+                let pixels = decoder.read_image_hdr().map_err(TextureError::Image)?;
+
+                let img = image::Rgb32FImage::from_fn(metadata.width, metadata.height, |x, y| {
+                    pixels[(y * metadata.width + x) as usize]
+                });
+                Ok(Self::from_image(
+                    &image::DynamicImage::ImageRgb32F(img),
+                    color_space,
+                    cpu_mipmaps,
+                ))
+            }
+            _ => {
+                let img = image::open(path).map_err(TextureError::Image)?;
+                Ok(Self::from_image(&img, color_space, cpu_mipmaps))
+            }
+        }
+    }
+
+    pub fn from_image(
+        img: &image::DynamicImage,
+        color_space: ColorSpace,
+        cpu_mipmaps: bool,
+    ) -> Self {
+        let is_float = matches!(
+            img,
+            image::DynamicImage::ImageRgb32F(_) | image::DynamicImage::ImageRgba32F(_)
+        );
+
+        let (format, encode): (_, fn(&image::DynamicImage) -> Vec<u8>) = if is_float {
+            (wgpu::TextureFormat::Rgba16Float, encode_rgba16f)
+        } else {
+            let format = match color_space {
+                ColorSpace::Srgb => wgpu::TextureFormat::Rgba8UnormSrgb,
+                ColorSpace::Linear => wgpu::TextureFormat::Rgba8Unorm,
+            };
+            (format, |img| img.to_rgba8().into_raw())
+        };
+
+        let size = wgpu::Extent3d {
+            width: img.width(),
+            height: img.height(),
+            depth_or_array_layers: 1,
+        };
+
+        let mut levels = vec![encode(img)];
+
+        if cpu_mipmaps {
+            let mut level = img.clone();
+            for mip in 1..size.max_mips(wgpu::TextureDimension::D2) {
+                let mip_size = size.mip_level_size(mip, wgpu::TextureDimension::D2);
+                level = level.resize_exact(
+                    mip_size.width,
+                    mip_size.height,
+                    image::imageops::FilterType::Triangle,
+                );
+                levels.push(encode(&level));
+            }
+        }
+
+        Self {
+            format,
+            size,
+            levels,
+            generate_mipmaps: !cpu_mipmaps,
+        }
+    }
+
+    pub fn from_ktx2(bytes: &[u8], color_space: ColorSpace) -> Result<Self, TextureError> {
+        let reader = ktx2::Reader::new(bytes).map_err(TextureError::Ktx2)?;
+        let header = reader.header();
+
+        if let Some(scheme) = header.supercompression_scheme {
+            return Err(TextureError::UnsupportedFormat(format!(
+                "{scheme:?} supercompression"
+            )));
+        }
+        if header.face_count != 1 || header.layer_count > 1 || header.pixel_depth > 1 {
+            return Err(TextureError::UnsupportedFormat(
+                "KTX2 cubemap, array or 3D texture".to_string(),
+            ));
+        }
+
+        let format = header
+            .format
+            .and_then(ktx2_format)
+            .ok_or_else(|| TextureError::UnsupportedFormat(format!("{:?}", header.format)))?;
+
+        Ok(Self {
+            format: with_color_space(format, color_space),
+            size: wgpu::Extent3d {
+                width: header.pixel_width,
+                height: header.pixel_height.max(1),
+                depth_or_array_layers: 1,
+            },
+            levels: reader.levels().map(<[u8]>::to_vec).collect(),
+            generate_mipmaps: false,
+        })
+    }
+
+    pub fn from_dds(
+        reader: impl std::io::Read,
+        color_space: ColorSpace,
+    ) -> Result<Self, TextureError> {
+        let dds = ddsfile::Dds::read(reader).map_err(TextureError::Dds)?;
+
+        let is_cubemap = dds.header.caps2.contains(ddsfile::Caps2::CUBEMAP)
+            || dds
+                .header10
+                .as_ref()
+                .is_some_and(|header| header.misc_flag.contains(ddsfile::MiscFlag::TEXTURECUBE));
+        if is_cubemap || dds.get_num_array_layers() > 1 || dds.get_depth() > 1 {
+            return Err(TextureError::UnsupportedFormat(
+                "DDS cubemap, array or 3D texture".to_string(),
+            ));
+        }
+
+        let format = dds
+            .get_dxgi_format()
+            .and_then(dxgi_format)
+            .or_else(|| dds.get_d3d_format().and_then(d3d_format))
+            .ok_or_else(|| {
+                TextureError::UnsupportedFormat(format!(
+                    "DDS {:?} / {:?}",
+                    dds.get_dxgi_format(),
+                    dds.get_d3d_format()
+                ))
+            })?;
+        let format = with_color_space(format, color_space);
+
+        let size = wgpu::Extent3d {
+            width: dds.get_width(),
+            height: dds.get_height(),
+            depth_or_array_layers: 1,
+        };
+
+        let data = dds.get_data(0).map_err(TextureError::Dds)?;
+        let mut levels = Vec::new();
+        let mut offset = 0;
+
+        for mip in 0..dds.get_num_mipmap_levels().max(1) {
+            let length =
+                level_byte_size(format, size.mip_level_size(mip, wgpu::TextureDimension::D2));
+            let level = data
+                .get(offset..offset + length)
+                .ok_or(TextureError::Dds(ddsfile::Error::OutOfBounds))?;
+            levels.push(level.to_vec());
+            offset += length;
+        }
+
+        Ok(Self {
+            format,
+            size,
+            levels,
+            generate_mipmaps: false,
+        })
+    }
+
+    /// Bytes per row of blocks, and number of rows of blocks, of a mip level.
+    pub fn level_layout(&self, mip: u32) -> (u32, u32) {
+        let info = self.format.describe();
+        let size = self
+            .size
+            .mip_level_size(mip, wgpu::TextureDimension::D2)
+            .physical_size(self.format);
+
+        (
+            size.width / info.block_dimensions.0 as u32 * info.block_size as u32,
+            size.height / info.block_dimensions.1 as u32,
+        )
+    }
+}
+
+fn level_byte_size(format: wgpu::TextureFormat, size: wgpu::Extent3d) -> usize {
+    let info = format.describe();
+    let size = size.physical_size(format);
+    let blocks = (size.width / info.block_dimensions.0 as u32)
+        * (size.height / info.block_dimensions.1 as u32);
+    blocks as usize * info.block_size as usize
+}
+
+fn encode_rgba16f(img: &image::DynamicImage) -> Vec<u8> {
+    img.to_rgba32f()
+        .into_raw()
+        .into_iter()
+        .flat_map(|c| half::f16::from_f32(c).to_le_bytes())
+        .collect()
+}
+
+/// Switches a format to its sRGB or linear variant, if it has one.
+fn with_color_space(format: wgpu::TextureFormat, color_space: ColorSpace) -> wgpu::TextureFormat {
+    use wgpu::TextureFormat as F;
+
+    const PAIRS: [(F, F); 8] = [
+        (F::Rgba8Unorm, F::Rgba8UnormSrgb),
+        (F::Bgra8Unorm, F::Bgra8UnormSrgb),
+        (F::Bc1RgbaUnorm, F::Bc1RgbaUnormSrgb),
+        (F::Bc2RgbaUnorm, F::Bc2RgbaUnormSrgb),
+        (F::Bc3RgbaUnorm, F::Bc3RgbaUnormSrgb),
+        (F::Bc7RgbaUnorm, F::Bc7RgbaUnormSrgb),
+        (F::Etc2Rgb8Unorm, F::Etc2Rgb8UnormSrgb),
+        (F::Etc2Rgba8Unorm, F::Etc2Rgba8UnormSrgb),
+    ];
+
+    if let F::Astc { block, channel } = format {
+        let channel = match (channel, color_space) {
+            (wgpu::AstcChannel::Unorm, ColorSpace::Srgb) => wgpu::AstcChannel::UnormSrgb,
+            (wgpu::AstcChannel::UnormSrgb, ColorSpace::Linear) => wgpu::AstcChannel::Unorm,
+            (channel, _) => channel,
+        };
+        return F::Astc { block, channel };
+    }
+
+    for (linear, srgb) in PAIRS {
+        match color_space {
+            ColorSpace::Srgb if format == linear => return srgb,
+            ColorSpace::Linear if format == srgb => return linear,
+            _ => {}
+        }
+    }
+
+    format
+}
+
+fn ktx2_format(format: ktx2::Format) -> Option<wgpu::TextureFormat> {
+    use ktx2::Format as K;
+    use wgpu::{AstcBlock as B, AstcChannel as C, TextureFormat as F};
+
+    let astc = |block, channel| Some(F::Astc { block, channel });
+
+    match format {
+        K::R8G8B8A8_UNORM => Some(F::Rgba8Unorm),
+        K::R8G8B8A8_SRGB => Some(F::Rgba8UnormSrgb),
+        K::B8G8R8A8_UNORM => Some(F::Bgra8Unorm),
+        K::B8G8R8A8_SRGB => Some(F::Bgra8UnormSrgb),
+        K::R16G16B16A16_SFLOAT => Some(F::Rgba16Float),
+        K::BC1_RGBA_UNORM_BLOCK | K::BC1_RGB_UNORM_BLOCK => Some(F::Bc1RgbaUnorm),
+        K::BC1_RGBA_SRGB_BLOCK | K::BC1_RGB_SRGB_BLOCK => Some(F::Bc1RgbaUnormSrgb),
+        K::BC2_UNORM_BLOCK => Some(F::Bc2RgbaUnorm),
+        K::BC2_SRGB_BLOCK => Some(F::Bc2RgbaUnormSrgb),
+        K::BC3_UNORM_BLOCK => Some(F::Bc3RgbaUnorm),
+        K::BC3_SRGB_BLOCK => Some(F::Bc3RgbaUnormSrgb),
+        K::BC4_UNORM_BLOCK => Some(F::Bc4RUnorm),
+        K::BC4_SNORM_BLOCK => Some(F::Bc4RSnorm),
+        K::BC5_UNORM_BLOCK => Some(F::Bc5RgUnorm),
+        K::BC5_SNORM_BLOCK => Some(F::Bc5RgSnorm),
+        K::BC6H_UFLOAT_BLOCK => Some(F::Bc6hRgbUfloat),
+        K::BC6H_SFLOAT_BLOCK => Some(F::Bc6hRgbSfloat),
+        K::BC7_UNORM_BLOCK => Some(F::Bc7RgbaUnorm),
+        K::BC7_SRGB_BLOCK => Some(F::Bc7RgbaUnormSrgb),
+        K::ETC2_R8G8B8_UNORM_BLOCK => Some(F::Etc2Rgb8Unorm),
+        K::ETC2_R8G8B8_SRGB_BLOCK => Some(F::Etc2Rgb8UnormSrgb),
+        K::ETC2_R8G8B8A1_UNORM_BLOCK => Some(F::Etc2Rgb8A1Unorm),
+        K::ETC2_R8G8B8A1_SRGB_BLOCK => Some(F::Etc2Rgb8A1UnormSrgb),
+        K::ETC2_R8G8B8A8_UNORM_BLOCK => Some(F::Etc2Rgba8Unorm),
+        K::ETC2_R8G8B8A8_SRGB_BLOCK => Some(F::Etc2Rgba8UnormSrgb),
+        K::EAC_R11_UNORM_BLOCK => Some(F::EacR11Unorm),
+        K::EAC_R11_SNORM_BLOCK => Some(F::EacR11Snorm),
+        K::EAC_R11G11_UNORM_BLOCK => Some(F::EacRg11Unorm),
+        K::EAC_R11G11_SNORM_BLOCK => Some(F::EacRg11Snorm),
+        K::ASTC_4x4_UNORM_BLOCK => astc(B::B4x4, C::Unorm),
+        K::ASTC_4x4_SRGB_BLOCK => astc(B::B4x4, C::UnormSrgb),
+        K::ASTC_5x4_UNORM_BLOCK => astc(B::B5x4, C::Unorm),
+        K::ASTC_5x4_SRGB_BLOCK => astc(B::B5x4, C::UnormSrgb),
+        K::ASTC_5x5_UNORM_BLOCK => astc(B::B5x5, C::Unorm),
+        K::ASTC_5x5_SRGB_BLOCK => astc(B::B5x5, C::UnormSrgb),
+        K::ASTC_6x5_UNORM_BLOCK => astc(B::B6x5, C::Unorm),
+        K::ASTC_6x5_SRGB_BLOCK => astc(B::B6x5, C::UnormSrgb),
+        K::ASTC_6x6_UNORM_BLOCK => astc(B::B6x6, C::Unorm),
+        K::ASTC_6x6_SRGB_BLOCK => astc(B::B6x6, C::UnormSrgb),
+        K::ASTC_8x5_UNORM_BLOCK => astc(B::B8x5, C::Unorm),
+        K::ASTC_8x5_SRGB_BLOCK => astc(B::B8x5, C::UnormSrgb),
+        K::ASTC_8x6_UNORM_BLOCK => astc(B::B8x6, C::Unorm),
+        K::ASTC_8x6_SRGB_BLOCK => astc(B::B8x6, C::UnormSrgb),
+        K::ASTC_8x8_UNORM_BLOCK => astc(B::B8x8, C::Unorm),
+        K::ASTC_8x8_SRGB_BLOCK => astc(B::B8x8, C::UnormSrgb),
+        K::ASTC_10x5_UNORM_BLOCK => astc(B::B10x5, C::Unorm),
+        K::ASTC_10x5_SRGB_BLOCK => astc(B::B10x5, C::UnormSrgb),
+        K::ASTC_10x6_UNORM_BLOCK => astc(B::B10x6, C::Unorm),
+        K::ASTC_10x6_SRGB_BLOCK => astc(B::B10x6, C::UnormSrgb),
+        K::ASTC_10x8_UNORM_BLOCK => astc(B::B10x8, C::Unorm),
+        K::ASTC_10x8_SRGB_BLOCK => astc(B::B10x8, C::UnormSrgb),
+        K::ASTC_10x10_UNORM_BLOCK => astc(B::B10x10, C::Unorm),
+        K::ASTC_10x10_SRGB_BLOCK => astc(B::B10x10, C::UnormSrgb),
+        K::ASTC_12x10_UNORM_BLOCK => astc(B::B12x10, C::Unorm),
+        K::ASTC_12x10_SRGB_BLOCK => astc(B::B12x10, C::UnormSrgb),
+        K::ASTC_12x12_UNORM_BLOCK => astc(B::B12x12, C::Unorm),
+        K::ASTC_12x12_SRGB_BLOCK => astc(B::B12x12, C::UnormSrgb),
+        _ => None,
+    }
+}
+
+fn dxgi_format(format: ddsfile::DxgiFormat) -> Option<wgpu::TextureFormat> {
+    use ddsfile::DxgiFormat as D;
+    use wgpu::TextureFormat as F;
+
+    match format {
+        D::R8G8B8A8_UNorm => Some(F::Rgba8Unorm),
+        D::R8G8B8A8_UNorm_sRGB => Some(F::Rgba8UnormSrgb),
+        D::B8G8R8A8_UNorm => Some(F::Bgra8Unorm),
+        D::B8G8R8A8_UNorm_sRGB => Some(F::Bgra8UnormSrgb),
+        D::R16G16B16A16_Float => Some(F::Rgba16Float),
+        D::BC1_UNorm => Some(F::Bc1RgbaUnorm),
+        D::BC1_UNorm_sRGB => Some(F::Bc1RgbaUnormSrgb),
+        D::BC2_UNorm => Some(F::Bc2RgbaUnorm),
+        D::BC2_UNorm_sRGB => Some(F::Bc2RgbaUnormSrgb),
+        D::BC3_UNorm => Some(F::Bc3RgbaUnorm),
+        D::BC3_UNorm_sRGB => Some(F::Bc3RgbaUnormSrgb),
+        D::BC4_UNorm => Some(F::Bc4RUnorm),
+        D::BC4_SNorm => Some(F::Bc4RSnorm),
+        D::BC5_UNorm => Some(F::Bc5RgUnorm),
+        D::BC5_SNorm => Some(F::Bc5RgSnorm),
+        D::BC6H_UF16 => Some(F::Bc6hRgbUfloat),
+        D::BC6H_SF16 => Some(F::Bc6hRgbSfloat),
+        D::BC7_UNorm => Some(F::Bc7RgbaUnorm),
+        D::BC7_UNorm_sRGB => Some(F::Bc7RgbaUnormSrgb),
+        _ => None,
+    }
+}
+
+fn d3d_format(format: ddsfile::D3DFormat) -> Option<wgpu::TextureFormat> {
+    use ddsfile::D3DFormat as D;
+    use wgpu::TextureFormat as F;
+
+    match format {
+        D::DXT1 => Some(F::Bc1RgbaUnorm),
+        D::DXT2 | D::DXT3 => Some(F::Bc2RgbaUnorm),
+        D::DXT4 | D::DXT5 => Some(F::Bc3RgbaUnorm),
+        D::A16B16G16R16F => Some(F::Rgba16Float),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dds(array_layers: u32, depth: Option<u32>, is_cubemap: bool) -> Vec<u8> {
+        let dds = ddsfile::Dds::new_dxgi(ddsfile::NewDxgiParams {
+            height: 8,
+            width: 8,
+            depth,
+            format: ddsfile::DxgiFormat::BC1_UNorm,
+            mipmap_levels: Some(2),
+            array_layers: Some(array_layers),
+            caps2: None,
+            is_cubemap,
+            resource_dimension: if depth.is_some() {
+                ddsfile::D3D10ResourceDimension::Texture3D
+            } else {
+                ddsfile::D3D10ResourceDimension::Texture2D
+            },
+            alpha_mode: ddsfile::AlphaMode::Unknown,
+        })
+        .unwrap();
+        let mut bytes = Vec::new();
+        dds.write(&mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn dds_textures_load_with_their_mip_levels() {
+        let texture = TextureData::from_dds(&dds(1, None, false)[..], ColorSpace::Srgb).unwrap();
+        assert_eq!(texture.format, wgpu::TextureFormat::Bc1RgbaUnormSrgb);
+        assert_eq!(
+            texture.levels.iter().map(Vec::len).collect::<Vec<_>>(),
+            [32, 8]
+        );
+    }
+
+    #[test]
+    fn dds_cubemaps_arrays_and_volumes_are_rejected() {
+        let legacy_cubemap = ddsfile::Dds::new_d3d(ddsfile::NewD3dParams {
+            height: 8,
+            width: 8,
+            depth: None,
+            format: ddsfile::D3DFormat::DXT1,
+            mipmap_levels: None,
+            caps2: Some(ddsfile::Caps2::CUBEMAP | ddsfile::Caps2::CUBEMAP_ALLFACES),
+        })
+        .unwrap();
+        let mut legacy_cubemap_bytes = Vec::new();
+        legacy_cubemap.write(&mut legacy_cubemap_bytes).unwrap();
+
+        for bytes in [
+            dds(6, None, true),
+            dds(3, None, false),
+            dds(1, Some(4), false),
+            legacy_cubemap_bytes,
+        ] {
+            assert!(matches!(
+                TextureData::from_dds(&bytes[..], ColorSpace::Linear),
+                Err(TextureError::UnsupportedFormat(_))
+            ));
+        }
+    }
+}
@@ -0,0 +1,42 @@
+//! Renders scenes without a window, as `grt --headless` does.
+
+use grt::{App, Options};
+
+/// Renders `scene` for two frames into a PNG in the system temp directory, returning its path.
+fn render(name: &str, scene: &str) -> Result<std::path::PathBuf, grt::EngineError> {
+    let directory = std::env::temp_dir().join(format!("grt-headless-{}", std::process::id()));
+    std::fs::create_dir_all(&directory).unwrap();
+    let scene_path = directory.join(format!("{name}.ron"));
+    std::fs::write(&scene_path, scene).unwrap();
+    let output = directory.join(format!("{name}.png"));
+
+    let options = Options {
+        path: scene_path.to_string_lossy().into_owned(),
+        headless: Some(2),
+        output: output.to_string_lossy().into_owned(),
+        width: 64,
+        height: 48,
+        ..Default::default()
+    };
+    let config = options.engine_config();
+    pollster::block_on(
+        App::builder()
+            .with_options(options)
+            .with_config(config)
+            .run(),
+    )?;
+    Ok(output)
+}
+
+#[test]
+fn scenes_without_a_camera_render_the_background() {
+    let output = render(
+        "no-camera",
+        "Scene(entities: [
+            [Primitive(Cube()), Transform()],
+            [Light(kind: Directional), Transform()],
+        ])",
+    )
+    .unwrap();
+    assert!(output.exists());
+}